@@ -0,0 +1,122 @@
+//! Per-frame cost of the output stage with a large patch. Run with
+//! `cargo bench --bench output`; timings are printed, not compared.
+
+use std::{
+    hint::black_box,
+    net::UdpSocket,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ecolor::Color32;
+use tether_artnet_controller::{
+    artnet::{ArtNetInterface, ArtNetMode, PortAddress},
+    clock::ManualClock,
+    output_plan::OutputPlan,
+    project::{fixture::FixtureMacro, Project},
+    settings::CHANNELS_PER_UNIVERSE,
+};
+
+const FIXTURES: usize = 1000;
+const FRAMES: u32 = 2000;
+
+/// Fixtures with colour, position and control macros, overlapping across the
+/// universe; all macros are active and colours change every frame
+fn large_project() -> Project {
+    let configs = [
+        "Varytec HERO 340FX",
+        "ADJ Vizi Hex Wash 7",
+        "Aputure INFINIMAT",
+    ];
+    let fixtures: Vec<_> = (0..FIXTURES)
+        .map(|i| {
+            serde_json::json!({
+                "label": format!("Fixture {}", i + 1),
+                "configName": configs[i % configs.len()],
+                "startChannel": 1 + (i * 7) % 480,
+                "modeIndex": 0
+            })
+        })
+        .collect();
+    let path = std::env::temp_dir().join("tether-artnet-controller-bench.project.json");
+    std::fs::write(
+        &path,
+        serde_json::json!({ "fixtures": fixtures, "scenes": [] }).to_string(),
+    )
+    .expect("failed to write bench project");
+    let project = Project::load(path.to_str().unwrap()).expect("failed to load bench project");
+    std::fs::remove_file(&path).ok();
+    project
+}
+
+fn set_colours(project: &mut Project, frame: u32) {
+    for (i, fixture) in project.fixtures.iter_mut().enumerate() {
+        for m in fixture.config.active_mode.macros.iter_mut() {
+            if let FixtureMacro::Colour(colour_macro) = m {
+                let v = (frame as usize + i) as u8;
+                colour_macro.current_value = Color32::from_rgb(v, 255 - v, v / 2);
+            }
+        }
+    }
+}
+
+fn report(label: &str, total: Duration, count: u32) {
+    println!(
+        "{:<40} {:>10.1} µs",
+        label,
+        total.as_secs_f64() * 1e6 / count as f64
+    );
+}
+
+fn main() {
+    let mut project = large_project();
+    let channels_state = vec![0u8; CHANNELS_PER_UNIVERSE as usize];
+    println!("{} fixtures", project.fixtures.len());
+
+    let started = Instant::now();
+    for _ in 0..100 {
+        black_box(OutputPlan::compile(&project.fixtures));
+    }
+    report("compile plan (on patch change)", started.elapsed(), 100);
+
+    let plan = OutputPlan::compile(&project.fixtures);
+    println!("{} macros in plan", plan.macros.len());
+
+    let mut channels = channels_state.clone();
+    let mut elapsed = Duration::ZERO;
+    for frame in 0..FRAMES {
+        set_colours(&mut project, frame);
+        let started = Instant::now();
+        channels.copy_from_slice(&channels_state);
+        plan.write_macros(&mut channels, &project.fixtures);
+        plan.apply_output_limits(&mut channels);
+        elapsed += started.elapsed();
+        black_box(&channels);
+    }
+    report("write macros + limits, per frame", elapsed, FRAMES);
+
+    // The whole output stage, including sending to a local socket which
+    // never reads (so packets are simply dropped)
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let clock = Arc::new(ManualClock::new());
+    let mut artnet = ArtNetInterface::new(
+        ArtNetMode::Unicast(
+            "127.0.0.1:0".parse().unwrap(),
+            vec![sink.local_addr().unwrap()],
+        ),
+        44,
+        PortAddress::default(),
+    )
+    .unwrap()
+    .with_clock(clock.clone());
+    let mut elapsed = Duration::ZERO;
+    for frame in 0..FRAMES {
+        set_colours(&mut project, frame);
+        clock.advance_ms(25);
+        let started = Instant::now();
+        let sent = artnet.update(&channels_state, &project.fixtures, &[], None, true);
+        elapsed += started.elapsed();
+        assert!(sent);
+    }
+    report("ArtNetInterface::update, per frame", elapsed, FRAMES);
+}
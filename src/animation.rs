@@ -1,12 +1,15 @@
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant};
 
-use egui::Color32;
+use ecolor::Color32;
 use tween::{Tween, Tweener};
 
+use crate::colour::Temperature;
+
 type StoredTweener = Tweener<f32, usize, Box<dyn Tween<f32>>>;
 
 pub struct Animation {
-    pub start_time: SystemTime,
+    /// Set the first time the animation is updated
+    pub start_time: Option<Instant>,
     pub duration: Duration,
     pub tweener: StoredTweener,
 }
@@ -20,15 +23,16 @@ impl Animation {
     ) -> Self {
         let duration_ms = duration.as_millis() as usize;
         Animation {
-            start_time: SystemTime::now(),
+            start_time: None,
             duration,
             tweener: Tweener::new(start_value, end_value, duration_ms, tween),
         }
     }
 
-    /// Update the animation using delta time, get the value in the range `[0,1]`
-    pub fn get_value(&mut self) -> f32 {
-        let elapsed = self.start_time.elapsed().unwrap().as_millis() as usize;
+    /// Update the animation to the given time, get the value in the range `[0,1]`
+    pub fn get_value(&mut self, now: Instant) -> f32 {
+        let start_time = *self.start_time.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start_time).as_millis() as usize;
 
         self.tweener.move_to(elapsed)
     }
@@ -37,8 +41,8 @@ impl Animation {
         self.tweener.current_time as f32 / self.tweener.duration as f32
     }
 
-    pub fn get_value_and_done(&mut self) -> (f32, bool) {
-        (self.get_value(), self.tweener.is_finished())
+    pub fn get_value_and_done(&mut self, now: Instant) -> (f32, bool) {
+        (self.get_value(now), self.tweener.is_finished())
     }
 }
 
@@ -55,3 +59,63 @@ fn linear_interpolate_u8(a: u8, b: u8, t: f32) -> u8 {
     let v = a as f32 + t * (b as f32 - a as f32);
     v as u8
 }
+
+pub fn animate_position(start: &[u16; 2], end: &[u16; 2], progress: f32) -> [u16; 2] {
+    [
+        linear_interpolate_u16(start[0], end[0], progress),
+        linear_interpolate_u16(start[1], end[1], progress),
+    ]
+}
+
+fn linear_interpolate_u16(a: u16, b: u16, t: f32) -> u16 {
+    let v = a as f32 + t * (b as f32 - a as f32);
+    v as u16
+}
+
+pub fn animate_temperature(start: &Temperature, end: &Temperature, progress: f32) -> Temperature {
+    Temperature::new(
+        linear_interpolate_u16(start.kelvin, end.kelvin, progress),
+        start.tint + progress * (end.tint - start.tint),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tween::{Linear, QuadInOut};
+
+    use super::Animation;
+    use crate::clock::{Clock, ManualClock};
+
+    #[test]
+    fn animation_follows_clock_exactly() {
+        let clock = ManualClock::new();
+        let mut animation = Animation::new(Duration::from_millis(1000), 0.2, 1.0, Box::new(Linear));
+
+        // Starts when first updated
+        clock.advance_ms(5000);
+        assert_eq!(animation.get_value_and_done(clock.now()), (0.2, false));
+
+        clock.advance_ms(250);
+        assert_eq!(animation.get_value_and_done(clock.now()), (0.4, false));
+        assert_eq!(animation.get_progress(), 0.25);
+
+        clock.advance_ms(750);
+        assert_eq!(animation.get_value_and_done(clock.now()), (1.0, true));
+    }
+
+    #[test]
+    fn eased_animation_is_symmetrical() {
+        let clock = ManualClock::new();
+        let mut animation =
+            Animation::new(Duration::from_millis(2000), 0.0, 1.0, Box::new(QuadInOut));
+        animation.get_value(clock.now());
+
+        clock.advance_ms(1000);
+        assert_eq!(animation.get_value(clock.now()), 0.5);
+
+        clock.advance_ms(500);
+        assert_eq!(animation.get_value(clock.now()), 0.875);
+    }
+}
@@ -1,275 +1,648 @@
 use serde::{Deserialize, Serialize};
 use std::{
-    net::{SocketAddr, ToSocketAddrs, UdpSocket},
-    time::{Duration, SystemTime},
+    fmt,
+    net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
 use anyhow::anyhow;
-use artnet_protocol::{ArtCommand, Output};
+use artnet_protocol::{ArtCommand, Poll, ARTNET_HEADER};
 use log::*;
 use rand::Rng;
 
 use crate::{
-    project::fixture::{
-        ChannelList, ChannelWithResolution, FixtureInstance, FixtureMacro, GroupedCMYChannels,
-        GroupedRGBLChannels, GroupedRGBWChannels,
-    },
+    clock::{system_clock, SharedClock},
+    output_plan::OutputPlan,
+    project::fixture::FixtureInstance,
+    project::safety::{SafetyConfig, SafetyLimiter},
+    receiver::{bind_shared, ARTNET_PORT},
     settings::CHANNELS_PER_UNIVERSE,
+    timecode::{FrameRate, Timecode},
 };
 
+/// OpCode of an ArtDmx packet (sent little-endian)
+const OP_DMX: u16 = 0x5000;
+/// OpCode of an ArtSync packet (sent little-endian)
+const OP_SYNC: u16 = 0x5200;
+
+/// How often unicast destinations are sent an ArtPoll
+pub const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// A destination with no ArtPollReply for this long is shown as unreachable
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct ArtNetInterface {
     socket: UdpSocket,
-    destination: SocketAddr,
-    universe: u8,
+    /// Listens on the ArtNet port of every interface, where timecode sources
+    /// broadcast ArtTimeCode and nodes send ArtPollReply, if that was possible
+    listener: Option<UdpSocket>,
+    destinations: Vec<Destination>,
+    last_poll: Option<Instant>,
+    /// The latest ArtTimeCode received, until taken by receive_timecode()
+    received_timecode: Option<(Timecode, FrameRate)>,
+    port_address: PortAddress,
+    /// Values from sliders and macros, before the output stage
     channels: Vec<u8>,
+    /// What is actually sent: the channels after output limits, parks and
+    /// the safety limiter. Kept apart so that none of these feed back into
+    /// the channels on the next frame.
+    output: Vec<u8>,
     update_interval: Duration,
-    last_sent: Option<SystemTime>,
+    /// When a frame was last put together (sent or not)
+    last_frame: Option<Instant>,
+    last_sent: Option<Instant>,
+    /// The channels as last sent, to tell whether anything has changed
+    sent_channels: Vec<u8>,
+    send_policy: SendPolicy,
+    stats: UniverseStats,
+    clock: SharedClock,
     mode_in_use: ArtNetMode,
+    safety_limiter: SafetyLimiter,
+    last_timecode: Option<Timecode>,
+    plan: OutputPlan,
+    /// Reused for every ArtDmx packet
+    packet: Vec<u8>,
+}
+
+/// When to send ArtDmx frames
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum SendPolicy {
+    /// A full frame at the update frequency, whether or not anything changed
+    #[default]
+    Continuous,
+    /// Only when the output changes (still no faster than the update
+    /// frequency), plus a keep-alive frame when nothing has changed for this long
+    OnChange { keep_alive: Duration },
+}
+
+/// What has been sent to a universe
+#[derive(Clone, Debug, Default)]
+pub struct UniverseStats {
+    /// ArtDmx packets sent, including keep-alives
+    pub packets: u64,
+    /// Packets sent only because nothing had changed for the keep-alive interval
+    pub keep_alives: u64,
+    /// Frames not sent because nothing had changed
+    pub unchanged: u64,
+    pub errors: u64,
+    pub last_sent: Option<Instant>,
+    /// Over the last full second
+    pub packets_per_second: f32,
+    window_start: Option<Instant>,
+    window_packets: u64,
+}
+
+impl UniverseStats {
+    /// Start a new one-second window for the rate, if the last one is over
+    fn tick(&mut self, now: Instant) {
+        match self.window_start {
+            Some(start) => {
+                let elapsed = now.saturating_duration_since(start);
+                if elapsed >= Duration::from_secs(1) {
+                    self.packets_per_second = self.window_packets as f32 / elapsed.as_secs_f32();
+                    self.window_start = Some(now);
+                    self.window_packets = 0;
+                }
+            }
+            None => self.window_start = Some(now),
+        }
+    }
+
+    fn sent(&mut self, now: Instant, keep_alive: bool) {
+        self.packets += 1;
+        self.window_packets += 1;
+        if keep_alive {
+            self.keep_alives += 1;
+        }
+        self.last_sent = Some(now);
+    }
+}
+
+/// The full 15-bit ArtNet Port-Address of a universe
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct PortAddress {
+    /// 0-127
+    pub net: u8,
+    /// 0-15
+    pub sub_net: u8,
+    /// 0-15
+    pub universe: u8,
+}
+
+impl PortAddress {
+    pub const MAX_NET: u8 = 127;
+    pub const MAX_SUB_NET: u8 = 15;
+    pub const MAX_UNIVERSE: u8 = 15;
+
+    pub fn new(net: u8, sub_net: u8, universe: u8) -> anyhow::Result<Self> {
+        let port_address = PortAddress {
+            net,
+            sub_net,
+            universe,
+        };
+        if port_address.is_valid() {
+            Ok(port_address)
+        } else {
+            Err(anyhow!(
+                "Port-Address {} is out of range (net 0-{}, sub-net 0-{}, universe 0-{})",
+                port_address,
+                Self::MAX_NET,
+                Self::MAX_SUB_NET,
+                Self::MAX_UNIVERSE
+            ))
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.net <= Self::MAX_NET
+            && self.sub_net <= Self::MAX_SUB_NET
+            && self.universe <= Self::MAX_UNIVERSE
+    }
+
+    /// The SubUni byte of an ArtDmx packet: sub-net in the high nibble,
+    /// universe in the low
+    pub fn sub_uni(&self) -> u8 {
+        (self.sub_net << 4) | (self.universe & 0x0F)
+    }
+}
+
+impl From<PortAddress> for u16 {
+    fn from(value: PortAddress) -> Self {
+        ((value.net as u16 & 0x7F) << 8) | value.sub_uni() as u16
+    }
+}
+
+impl TryFrom<u16> for PortAddress {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        if value > 0x7FFF {
+            return Err(anyhow!("Port-Address {} is more than 15 bits", value));
+        }
+        Ok(PortAddress {
+            net: (value >> 8) as u8,
+            sub_net: ((value >> 4) & 0x0F) as u8,
+            universe: (value & 0x0F) as u8,
+        })
+    }
+}
+
+/// As net:sub-net:universe, e.g. "1:0:3"
+impl fmt::Display for PortAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.net, self.sub_net, self.universe)
+    }
+}
+
+/// Either net:sub-net:universe (e.g. "1:0:3") or the 15-bit number (e.g. "259")
+impl FromStr for PortAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        let number = |part: &str| {
+            part.trim()
+                .parse::<u16>()
+                .map_err(|_| anyhow!("Invalid Port-Address \"{}\"", s))
+        };
+        match parts[..] {
+            [whole] => PortAddress::try_from(number(whole)?),
+            [net, sub_net, universe] => {
+                let byte = |part| {
+                    u8::try_from(number(part)?)
+                        .map_err(|_| anyhow!("Invalid Port-Address \"{}\"", s))
+                };
+                PortAddress::new(byte(net)?, byte(sub_net)?, byte(universe)?)
+            }
+            _ => Err(anyhow!(
+                "Invalid Port-Address \"{}\"; use net:sub-net:universe, e.g. 1:0:3",
+                s
+            )),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum ArtNetMode {
     Broadcast,
-    /// Specify from (interface) + to (destination) addresses
-    Unicast(SocketAddr, SocketAddr),
+    /// Specify from (interface) + to (destination) addresses; every
+    /// destination gets the same universe
+    Unicast(SocketAddr, Vec<SocketAddr>),
+}
+
+/// Somewhere ArtNet is sent, and whether a node there has answered an ArtPoll
+#[derive(Clone, Debug)]
+pub struct Destination {
+    pub address: SocketAddr,
+    first_polled: Option<Instant>,
+    pub last_reply: Option<Instant>,
+    /// The node's short name, from its ArtPollReply
+    pub node_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reachability {
+    /// Not polled yet, or no reply to the first poll yet
+    Waiting,
+    /// ArtPollReply seen recently
+    Reachable,
+    Unreachable,
+}
+
+impl Destination {
+    fn new(address: SocketAddr) -> Self {
+        Destination {
+            address,
+            first_polled: None,
+            last_reply: None,
+            node_name: None,
+        }
+    }
+
+    pub fn reachability(&self, now: Instant) -> Reachability {
+        let recent = |t: Instant| now.saturating_duration_since(t) < REPLY_TIMEOUT;
+        match (self.first_polled, self.last_reply) {
+            (_, Some(t)) if recent(t) => Reachability::Reachable,
+            (None, _) => Reachability::Waiting,
+            (Some(t), None) if recent(t) => Reachability::Waiting,
+            _ => Reachability::Unreachable,
+        }
+    }
 }
 
 impl ArtNetInterface {
-    pub fn new(mode: ArtNetMode, update_frequency: u64, universe: u8) -> anyhow::Result<Self> {
+    pub fn new(
+        mode: ArtNetMode,
+        update_frequency: u64,
+        port_address: PortAddress,
+    ) -> anyhow::Result<Self> {
         let channels = Vec::with_capacity(CHANNELS_PER_UNIVERSE as usize);
 
         let update_interval = Duration::from_secs_f32(1.0 / update_frequency as f32);
 
-        match mode {
+        let (socket, destinations, listener) = match &mode {
             ArtNetMode::Broadcast => {
                 let socket = UdpSocket::bind((String::from("0.0.0.0"), 6455))?;
                 let broadcast_addr = ("255.255.255.255", 6454).to_socket_addrs()?.next().unwrap();
                 socket.set_broadcast(true)?;
+                socket.set_nonblocking(true)?;
                 debug!("Broadcast mode set up OK");
-                Ok(ArtNetInterface {
-                    socket,
-                    destination: broadcast_addr,
-                    universe,
-                    channels,
-                    update_interval,
-                    last_sent: None,
-                    mode_in_use: mode.clone(),
-                })
+                (socket, vec![broadcast_addr], bind_listener(None, &[]))
             }
-            ArtNetMode::Unicast(src, destination) => {
+            ArtNetMode::Unicast(src, destinations) => {
+                if destinations.is_empty() {
+                    return Err(anyhow!("No unicast destinations given"));
+                }
                 info!(
-                    "Will connect from interface {} to destination {}",
-                    &src, &destination
+                    "Will connect from interface {} to destination(s) {}",
+                    &src,
+                    destinations
+                        .iter()
+                        .map(|d| d.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
                 );
-                match UdpSocket::bind(src) {
-                    Ok(socket) => {
-                        socket.set_broadcast(false)?;
-                        Ok(ArtNetInterface {
-                            socket,
-                            destination,
-                            channels,
-                            universe,
-                            update_interval,
-                            last_sent: None,
-                            mode_in_use: mode.clone(),
-                        })
-                    }
-                    Err(e) => Err(anyhow!("Error binding socket: {}", e)),
-                }
+                let socket =
+                    UdpSocket::bind(src).map_err(|e| anyhow!("Error binding socket: {}", e))?;
+                socket.set_broadcast(false)?;
+                socket.set_nonblocking(true)?;
+                let listener = bind_listener(Some(src.ip()), destinations);
+                (socket, destinations.clone(), listener)
             }
-        }
+        };
+
+        Ok(ArtNetInterface {
+            socket,
+            listener,
+            destinations: destinations.into_iter().map(Destination::new).collect(),
+            last_poll: None,
+            received_timecode: None,
+            port_address,
+            channels,
+            output: Vec::with_capacity(CHANNELS_PER_UNIVERSE as usize),
+            update_interval,
+            last_frame: None,
+            last_sent: None,
+            sent_channels: Vec::new(),
+            send_policy: SendPolicy::default(),
+            stats: UniverseStats::default(),
+            mode_in_use: mode,
+            safety_limiter: SafetyLimiter::default(),
+            last_timecode: None,
+            clock: system_clock(),
+            plan: OutputPlan::default(),
+            packet: Vec::new(),
+        })
     }
 
     pub fn update(
         &mut self,
         channels_state: &[u8],
         fixtures: &[FixtureInstance],
+        parked: &[(u16, u8)],
+        safety: Option<&SafetyConfig>,
         apply_macros: bool,
     ) -> bool {
-        match self.last_sent {
-            Some(t) => {
-                if t.elapsed().unwrap() < self.update_interval {
-                    return false; // early return; not ready to send
+        let now = self.clock.now();
+        if self
+            .last_frame
+            .is_some_and(|t| now.saturating_duration_since(t) < self.update_interval)
+        {
+            return false; // early return; not ready to send
+        }
+        self.last_frame = Some(now);
+        self.stats.tick(now);
+        self.receive();
+
+        self.channels.clear();
+        self.channels.extend_from_slice(channels_state);
+
+        if self.plan.refresh(fixtures) {
+            debug!("Patch changed; output plan rebuilt");
+        }
+        if apply_macros {
+            self.plan.write_macros(&mut self.channels, fixtures);
+        }
+        self.output.clone_from(&self.channels);
+        self.plan.apply_output_limits(&mut self.output);
+
+        // Parked values always win, exactly as given
+        for (channel, value) in parked {
+            if let Some(c) = (*channel as usize)
+                .checked_sub(1)
+                .and_then(|i| self.output.get_mut(i))
+            {
+                *c = *value;
+            }
+        }
+
+        // ...except for the safety limiter, which nothing gets around
+        if let Some(safety) = safety {
+            self.safety_limiter
+                .apply(safety, &mut self.output, fixtures, now);
+        }
+
+        let keep_alive = match self.send_policy {
+            SendPolicy::Continuous => false,
+            SendPolicy::OnChange { keep_alive } => {
+                if self.output != self.sent_channels {
+                    false
+                } else if self
+                    .last_sent
+                    .is_some_and(|t| now.saturating_duration_since(t) < keep_alive)
+                {
+                    self.stats.unchanged += 1;
+                    return false;
+                } else {
+                    true
+                }
+            }
+        };
+        self.last_sent = Some(now);
+        self.sent_channels.clone_from(&self.output);
+
+        trace!("Channel state {:?}", self.output);
+        self.write_dmx_packet();
+        for destination in &self.destinations {
+            match self.socket.send_to(&self.packet, destination.address) {
+                Ok(_) => self.stats.sent(now, keep_alive),
+                Err(e) => {
+                    self.stats.errors += 1;
+                    error!("Error sending ArtNet to {}: {}", destination.address, e)
                 }
             }
-            None => self.last_sent = Some(SystemTime::now()),
         }
 
-        // zero(&mut self.channels);
-        self.channels = channels_state.into(); // copy slice contents into Vec
+        true
+    }
 
-        if apply_macros {
-            for f in fixtures {
-                for m in &f.config.active_mode.macros {
-                    match m {
-                        FixtureMacro::Control(control_macro) => {
-                            for c in &control_macro.channels {
-                                match c {
-                                    ChannelWithResolution::LoRes(single_channel) => {
-                                        let target_channel =
-                                            (*single_channel - 1 + f.start_channel - 1) as usize;
-                                        let scaled_value = ((control_macro.current_value as f32
-                                            / u16::MAX as f32)
-                                            * 255.0)
-                                            as u8;
-                                        debug!(
-                                            "Apply LoRes value to single fixture macro (channel {}) => {}, value {} => {}",
-                                            single_channel,
-                                            target_channel,
-                                            control_macro.current_value,
-                                            scaled_value
-                                        );
-                                        self.channels[target_channel] = scaled_value;
-                                    }
-                                    ChannelWithResolution::HiRes((c1, c2)) => {
-                                        // Assume coarse+fine 16-bit values are "big endian" (be):
-                                        let [b1, b2] = control_macro.current_value.to_be_bytes();
-                                        // coarse channel:
-                                        self.channels[(*c1 - 1 + f.start_channel - 1) as usize] =
-                                            b1;
-                                        // fine channel:
-                                        self.channels[(*c2 - 1 + f.start_channel - 1) as usize] =
-                                            b2;
-                                    }
-                                }
-                            }
-                        }
-                        FixtureMacro::Colour(colour_macro) => {
-                            match &colour_macro.channels {
-                                ChannelList::AdditiveRGBW8(rgba) => {
-                                    let GroupedRGBWChannels {
-                                        red,
-                                        green,
-                                        blue,
-                                        white,
-                                    } = rgba;
-
-                                    // Convert all rgb values from "opaque" version (ignoring alpha)
-                                    let opaque = colour_macro.current_value.to_opaque();
-                                    for c in red.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.r();
-                                    }
-                                    for c in green.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.g();
-                                    }
-                                    for c in blue.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.b();
-                                    }
-
-                                    // Use inverse of alpha for "white mix" , i.e.
-                                    //  alpha = 100% => full saturation, no white
-                                    //  alpha = 0% => RGB the same, but mix in full white
-                                    let white_inverse = 255 - colour_macro.current_value.a();
-                                    for c in white.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            white_inverse;
-                                    }
-                                }
-                                ChannelList::Subtractive(cmy) => {
-                                    let GroupedCMYChannels {
-                                        cyan,
-                                        magenta,
-                                        yellow,
-                                    } = cmy;
-                                    // let c = 255 - colour_macro.current_value.r();
-                                    // let m = 255 - colour_macro.current_value.g();
-                                    // let y = 255 - colour_macro.current_value.b();
-
-                                    // Convert all cmy values from "opaque" version (ignoring alpha)
-                                    let opaque = colour_macro.current_value.to_opaque();
-
-                                    for channel in cyan.iter() {
-                                        self.channels
-                                            [(*channel - 1 + f.start_channel - 1) as usize] =
-                                            255 - opaque.r();
-                                    }
-                                    for channel in magenta.iter() {
-                                        self.channels
-                                            [(*channel - 1 + f.start_channel - 1) as usize] =
-                                            255 - opaque.g();
-                                    }
-                                    for channel in yellow.iter() {
-                                        self.channels
-                                            [(*channel - 1 + f.start_channel - 1) as usize] =
-                                            255 - opaque.b();
-                                    }
-                                }
-                                ChannelList::AdditiveRGB16(_rgb16) => {
-                                    // let HiResRGBChannels { red, green, blue } = rgb16;
-
-                                    // let [r, g, b, _] = colour_macro.current_value.to_array();
-
-                                    // let channel_pairs_with_target_value =
-                                    //     vec![(red, r), (green, g), (blue, b)];
-
-                                    // for ((c1, c2), value) in channel_pairs_with_target_value {
-                                    //     // Assume coarse+fine 16-bit values are "big endian" (be):
-                                    //     let [b1, b2] = value.to_be_bytes();
-
-                                    //     // coarse channel:
-                                    //     self.channels[(*c1  + f.offset_channels) as usize] = b1;
-                                    //     // fine channel:
-                                    //     self.channels[(*c2  + f.offset_channels) as usize] = b2;
-                                    // }
-                                    todo!("Not yet implemented; current Colour Macros are 8-bit channels only!");
-                                }
-                                ChannelList::AdditiveRGBL8(rgbl) => {
-                                    let GroupedRGBLChannels {
-                                        red, green, blue, ..
-                                    } = rgbl;
-
-                                    // Convert all rgb values from "opaque" version (ignoring alpha)
-                                    let opaque = colour_macro.current_value.to_opaque();
-                                    for c in red.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.r();
-                                    }
-                                    for c in green.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.g();
-                                    }
-                                    for c in blue.iter() {
-                                        self.channels[(*c - 1 + f.start_channel - 1) as usize] =
-                                            opaque.b();
-                                    }
-                                    // Ignore lime, since we don't represent it in standard colour macros
-                                }
-                            }
-                        }
-                    }
+    /// Build the ArtDmx packet for the current output in place, rather
+    /// than allocating (and copying the output into) a new one each frame
+    fn write_dmx_packet(&mut self) {
+        // Data length must be even, between 2 and 512
+        let length = (self.output.len().max(2) + 1) & !1;
+        self.packet.clear();
+        self.packet.extend_from_slice(ARTNET_HEADER);
+        self.packet.extend_from_slice(&OP_DMX.to_le_bytes());
+        // ProtVer (14), Sequence (disabled), Physical, SubUni, Net, Length (big-endian)
+        self.packet.extend_from_slice(&[
+            0,
+            14,
+            0,
+            0,
+            self.port_address.sub_uni(),
+            self.port_address.net,
+        ]);
+        self.packet
+            .extend_from_slice(&(length as u16).to_be_bytes());
+        self.packet.extend_from_slice(&self.output);
+        self.packet
+            .resize(self.packet.len() + length - self.output.len(), 0);
+    }
+
+    /// Tell nodes to output the data sent since the last ArtSync, so that
+    /// all universes change together
+    pub fn send_sync(&self) {
+        let mut packet = ARTNET_HEADER.to_vec();
+        packet.extend_from_slice(&OP_SYNC.to_le_bytes());
+        // ProtVer (14), Aux1, Aux2
+        packet.extend_from_slice(&[0, 14, 0, 0]);
+        for destination in &self.destinations {
+            if let Err(e) = self.socket.send_to(&packet, destination.address) {
+                error!("Error sending ArtSync to {}: {}", destination.address, e);
+            }
+        }
+    }
+
+    /// Send an ArtTimeCode packet, unless this frame has already been sent
+    pub fn send_timecode(&mut self, timecode: Timecode, rate: FrameRate) {
+        if self.last_timecode == Some(timecode) {
+            return;
+        }
+        self.last_timecode = Some(timecode);
+        let packet = timecode.to_art_net(rate);
+        for destination in &self.destinations {
+            if let Err(e) = self.socket.send_to(&packet, destination.address) {
+                error!(
+                    "Error sending ArtTimeCode to {}: {}",
+                    destination.address, e
+                );
+            }
+        }
+    }
+
+    /// The latest ArtTimeCode packet received since last time, if any
+    pub fn receive_timecode(&mut self) -> Option<(Timecode, FrameRate)> {
+        self.receive();
+        self.received_timecode.take()
+    }
+
+    /// Send an ArtPoll to each unicast destination, if it is time to; their
+    /// replies show which are reachable. Does nothing in broadcast mode.
+    pub fn poll_destinations(&mut self) {
+        if matches!(self.mode_in_use, ArtNetMode::Broadcast) {
+            return;
+        }
+        let now = self.clock.now();
+        if self
+            .last_poll
+            .is_some_and(|t| now.saturating_duration_since(t) < POLL_INTERVAL)
+        {
+            return;
+        }
+        self.last_poll = Some(now);
+        self.receive();
+
+        let packet = match ArtCommand::Poll(Poll::default()).write_to_buffer() {
+            Ok(packet) => packet,
+            Err(e) => {
+                error!("Could not build ArtPoll: {}", e);
+                return;
+            }
+        };
+        for destination in self.destinations.iter_mut() {
+            destination.first_polled.get_or_insert(now);
+            if let Err(e) = self.socket.send_to(&packet, destination.address) {
+                error!("Error sending ArtPoll to {}: {}", destination.address, e);
+            }
+        }
+    }
+
+    /// Handle everything that has arrived since last time: ArtTimeCode is
+    /// kept for receive_timecode(), and ArtPollReply marks a destination reachable
+    fn receive(&mut self) {
+        let now = self.clock.now();
+        let mut buffer = [0u8; 1024];
+        let mut replies = Vec::new();
+        for socket in std::iter::once(&self.socket).chain(self.listener.as_ref()) {
+            while let Ok((length, from)) = socket.recv_from(&mut buffer) {
+                let bytes = &buffer[..length];
+                if let Some(timecode) = Timecode::from_art_net(bytes) {
+                    self.received_timecode = Some(timecode);
+                } else if let Ok(ArtCommand::PollReply(reply)) = ArtCommand::from_buffer(bytes) {
+                    let name = String::from_utf8_lossy(&reply.short_name)
+                        .trim_end_matches('\0')
+                        .to_string();
+                    replies.push((from.ip(), name));
                 }
             }
         }
 
-        trace!("Channel state {:?}", self.channels);
-        let command = ArtCommand::Output(Output {
-            port_address: self.universe.into(),
-            data: self.channels.clone().into(), // make temp copy of self channel state (?)
-            ..Output::default()
-        });
-
-        let buff = command.write_to_buffer().unwrap();
-        match self.socket.send_to(&buff, self.destination) {
-            Ok(_) => {}
-            Err(e) => error!("Error sending ArtNet: {}", e),
+        for (ip, name) in replies {
+            for destination in self
+                .destinations
+                .iter_mut()
+                .filter(|d| d.address.ip() == ip)
+            {
+                if destination.reachability(now) != Reachability::Reachable {
+                    info!(
+                        "ArtNet destination {} is reachable (\"{}\")",
+                        destination.address, name
+                    );
+                }
+                destination.last_reply = Some(now);
+                destination.node_name = Some(name.clone());
+            }
         }
+    }
 
-        true
+    /// Each destination, with whether a node there is answering ArtPoll
+    pub fn destinations(&self) -> Vec<(&Destination, Reachability)> {
+        let now = self.clock.now();
+        self.destinations
+            .iter()
+            .map(|d| (d, d.reachability(now)))
+            .collect()
+    }
+
+    /// Use this clock (rather than the system clock) for output timing
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
     }
 
+    pub fn with_send_policy(mut self, send_policy: SendPolicy) -> Self {
+        self.send_policy = send_policy;
+        self
+    }
+
+    pub fn send_policy(&self) -> SendPolicy {
+        self.send_policy
+    }
+
+    pub fn port_address(&self) -> PortAddress {
+        self.port_address
+    }
+
+    pub fn stats(&self) -> &UniverseStats {
+        &self.stats
+    }
+
+    /// The channels as last sent, after the output stage
     pub fn get_state(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// The channels as last written by sliders and macros, before output
+    /// limits, parks and the safety limiter
+    pub fn get_live_state(&self) -> &[u8] {
         &self.channels
     }
 
+    /// The compiled patch used for the most recent frame
+    pub fn output_plan(&self) -> &OutputPlan {
+        &self.plan
+    }
+
+    /// Compile the patch again for the next frame, since fixtures have changed
+    pub fn invalidate_plan(&mut self) {
+        self.plan.invalidate();
+    }
+
+    pub fn safety_limiter(&self) -> &SafetyLimiter {
+        &self.safety_limiter
+    }
+
     pub fn mode_in_use(&self) -> &ArtNetMode {
         &self.mode_in_use
     }
 }
 
+/// Listen on the ArtNet port of every interface, for ArtTimeCode and
+/// ArtPollReply. Skipped when sending to this machine: the most recently
+/// bound socket gets unicast packets, so this would take the ones meant for
+/// the receiver there (which replies to the port the poll came from, so is
+/// still seen).
+fn bind_listener(interface: Option<IpAddr>, destinations: &[SocketAddr]) -> Option<UdpSocket> {
+    if destinations
+        .iter()
+        .any(|d| d.ip().is_loopback() || Some(d.ip()) == interface)
+    {
+        debug!(
+            "Sending to this machine; not listening on port {}",
+            ARTNET_PORT
+        );
+        return None;
+    }
+    match bind_shared(ARTNET_PORT).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }) {
+        Ok(socket) => Some(socket),
+        Err(e) => {
+            warn!(
+                "Could not listen on port {} ({}); ArtTimeCode and ArtPollReply sent there will be missed",
+                ARTNET_PORT, e
+            );
+            None
+        }
+    }
+}
+
 pub fn zero(channels: &mut Vec<u8>) {
     *channels = [0].repeat(CHANNELS_PER_UNIVERSE as usize);
 }
@@ -280,3 +653,60 @@ pub fn random(channels: &mut [u8]) {
         *c = rng.gen::<u8>();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::DEFAULT_ARTNET_HERTZ;
+
+    #[test]
+    fn port_address_forms() {
+        let port_address: PortAddress = "1:2:3".parse().unwrap();
+        assert_eq!(port_address, PortAddress::new(1, 2, 3).unwrap());
+        assert_eq!(u16::from(port_address), 0x123);
+        assert_eq!(port_address.sub_uni(), 0x23);
+        assert_eq!(port_address.to_string(), "1:2:3");
+
+        // A plain number is the whole 15-bit Port-Address, as before
+        assert_eq!("291".parse::<PortAddress>().unwrap(), port_address);
+        assert_eq!(PortAddress::try_from(0x123).unwrap(), port_address);
+
+        for invalid in ["128:0:0", "0:16:0", "0:0:16", "32768", "1:2", "x"] {
+            assert!(invalid.parse::<PortAddress>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn timecode_sent_to_the_artnet_port_is_received() {
+        // Sending elsewhere, so the ArtNet port is listened on
+        let mut artnet = ArtNetInterface::new(
+            ArtNetMode::Unicast(
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+                vec![SocketAddr::from(([192, 0, 2, 1], ARTNET_PORT))],
+            ),
+            DEFAULT_ARTNET_HERTZ,
+            PortAddress::default(),
+        )
+        .unwrap();
+        assert!(artnet.listener.is_some());
+
+        let timecode: Timecode = "01:02:03:04".parse().unwrap();
+        let sender = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        sender
+            .send_to(
+                &timecode.to_art_net(FrameRate::Ebu),
+                ("127.0.0.1", ARTNET_PORT),
+            )
+            .unwrap();
+
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(2) {
+            if let Some(received) = artnet.receive_timecode() {
+                assert_eq!(received, (timecode, FrameRate::Ebu));
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("no ArtTimeCode received");
+    }
+}
@@ -0,0 +1,510 @@
+use ecolor::Color32;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// Range offered for Temperature Macros which do not specify their own
+pub const MIN_KELVIN: u16 = 1700;
+pub const MAX_KELVIN: u16 = 10000;
+
+/// A colour temperature, plus green/magenta tint
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Temperature {
+    pub kelvin: u16,
+    /// -1 (full magenta) to +1 (full green); 0 sits on the black-body curve
+    #[serde(default)]
+    pub tint: f32,
+}
+
+impl Default for Temperature {
+    fn default() -> Self {
+        Temperature {
+            kelvin: 5600,
+            tint: 0.,
+        }
+    }
+}
+
+impl Temperature {
+    pub fn new(kelvin: u16, tint: f32) -> Self {
+        Temperature {
+            kelvin,
+            tint: tint.clamp(-1., 1.),
+        }
+    }
+
+    /// Approximate RGB for this temperature (Tanner Helland's fit to the
+    /// black-body curve), with the tint pulling green up or down
+    pub fn to_rgb(self) -> Color32 {
+        let t = self.kelvin.clamp(1000, 40000) as f32 / 100.;
+        let r = if t <= 66. {
+            255.
+        } else {
+            329.69873 * (t - 60.).powf(-0.13320476)
+        };
+        let g = if t <= 66. {
+            99.4708 * t.ln() - 161.11957
+        } else {
+            288.12216 * (t - 60.).powf(-0.07551485)
+        };
+        let b = if t >= 66. {
+            255.
+        } else if t <= 19. {
+            0.
+        } else {
+            138.51773 * (t - 10.).ln() - 305.0448
+        };
+
+        // Green tint takes some red and blue away; magenta takes away green
+        let (r, g, b) = if self.tint >= 0. {
+            let k = 1. - self.tint * 0.5;
+            (r * k, g, b * k)
+        } else {
+            (r, g * (1. + self.tint * 0.5), b)
+        };
+
+        let to_u8 = |v: f32| v.clamp(0., 255.) as u8;
+        Color32::from_rgb(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Nearest colour temperature to an RGB colour (McCamy's approximation),
+    /// or None for black. Tint is not estimated.
+    pub fn from_rgb(colour: Color32) -> Option<Temperature> {
+        let linear = |c: u8| {
+            let c = c as f32 / 255.;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let [r, g, b] = [linear(colour.r()), linear(colour.g()), linear(colour.b())];
+        let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+        let sum = x + y + z;
+        if sum <= 0. {
+            return None;
+        }
+        let (cx, cy) = (x / sum, y / sum);
+        let n = (cx - 0.3320) / (0.1858 - cy);
+        let kelvin = 449. * n.powi(3) + 3525. * n.powi(2) + 6823.3 * n + 5520.33;
+        Some(Temperature::new(kelvin.clamp(1000., 25000.) as u16, 0.))
+    }
+
+    /// Relative levels `[0,1]` for a pair of warm and cool emitters, mixed in
+    /// mired (perceptually even) steps and scaled so the brighter one is at full
+    pub fn warm_cool_levels(&self, warm_kelvin: u16, cool_kelvin: u16) -> (f32, f32) {
+        let mired = |k: u16| 1_000_000. / k.max(1) as f32;
+        let (warm, cool) = (mired(warm_kelvin), mired(cool_kelvin));
+        let cool_fraction = if (warm - cool).abs() < f32::EPSILON {
+            0.5
+        } else {
+            ((warm - mired(self.kelvin)) / (warm - cool)).clamp(0., 1.)
+        };
+        let warm_fraction = 1. - cool_fraction;
+        let max = warm_fraction.max(cool_fraction);
+        (warm_fraction / max, cool_fraction / max)
+    }
+
+    /// This temperature as a fraction `[0,1]` of the given range, e.g. for a native CCT channel
+    pub fn fraction_of_range(&self, min_kelvin: u16, max_kelvin: u16) -> f32 {
+        if max_kelvin <= min_kelvin {
+            return 0.;
+        }
+        ((self.kelvin as f32 - min_kelvin as f32) / (max_kelvin - min_kelvin) as f32).clamp(0., 1.)
+    }
+}
+
+/// Hue, saturation, value; all in the range `[0,1]`
+pub fn hsv_to_rgb([h, s, v]: [f32; 3]) -> Color32 {
+    let h = h.rem_euclid(1.) * 6.;
+    let (s, v) = (s.clamp(0., 1.), v.clamp(0., 1.));
+    let c = v * s;
+    let x = c * (1. - (h % 2. - 1.).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let m = v - c;
+    from_unit_rgb(r + m, g + m, b + m)
+}
+
+/// Hue, saturation, intensity; all in the range `[0,1]`. Unlike HSV, intensity
+/// is the average of the channels, so fully saturated hues all have the same
+/// total output (as on many LED desks).
+pub fn hsi_to_rgb([h, s, i]: [f32; 3]) -> Color32 {
+    use std::f32::consts::PI;
+    let h = h.rem_euclid(1.) * 2. * PI;
+    let (s, i) = (s.clamp(0., 1.), i.clamp(0., 1.));
+    let sector = 2. * PI / 3.;
+    let f = |h: f32| i * (1. + s * h.cos() / (PI / 3. - h).cos());
+    let low = i * (1. - s);
+    let (r, g, b) = if h < sector {
+        let r = f(h);
+        (r, 3. * i - (r + low), low)
+    } else if h < 2. * sector {
+        let g = f(h - sector);
+        (low, g, 3. * i - (g + low))
+    } else {
+        let b = f(h - 2. * sector);
+        (3. * i - (b + low), low, b)
+    };
+    from_unit_rgb(r, g, b)
+}
+
+/// The inverse of [`hsi_to_rgb`]. Hue is the angle around the colour wheel
+/// (not the hexagonal approximation used for HSV), so colours survive a round trip.
+pub fn rgb_to_hsi(colour: Color32) -> [f32; 3] {
+    use std::f32::consts::PI;
+    let (r, g, b) = to_unit_rgb(colour);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let i = (r + g + b) / 3.;
+    let s = if i > 0. { 1. - min / i } else { 0. };
+    let h = if max - min <= 0. {
+        0.
+    } else {
+        let cos = 0.5 * ((r - g) + (r - b)) / ((r - g).powi(2) + (r - b) * (g - b)).sqrt();
+        let theta = cos.clamp(-1., 1.).acos();
+        if b > g {
+            1. - theta / (2. * PI)
+        } else {
+            theta / (2. * PI)
+        }
+    };
+    [h, s, i]
+}
+
+fn to_unit_rgb(colour: Color32) -> (f32, f32, f32) {
+    let [r, g, b, _] = colour.to_opaque().to_array();
+    (r as f32 / 255., g as f32 / 255., b as f32 / 255.)
+}
+
+fn from_unit_rgb(r: f32, g: f32, b: f32) -> Color32 {
+    let to_u8 = |v: f32| (v.clamp(0., 1.) * 255.).round() as u8;
+    Color32::from_rgb(to_u8(r), to_u8(g), to_u8(b))
+}
+
+/// The kinds of LED emitter a colour can be mixed from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Emitter {
+    Red,
+    Green,
+    Blue,
+    White,
+    Amber,
+    Lime,
+    Uv,
+}
+
+/// Emitters other than red, green and blue, in the order they are used up
+const EXTRA_EMITTERS: [Emitter; 3] = [Emitter::White, Emitter::Amber, Emitter::Lime];
+
+impl Emitter {
+    /// Whether colour macros set this emitter. UV hardly shows as a colour (and
+    /// would come on for every purple), so it is left to its own control macro.
+    pub fn is_mixed(self) -> bool {
+        self != Emitter::Uv
+    }
+
+    /// Roughly how this emitter looks at full, in RGB terms
+    fn default_appearance(self) -> [u8; 3] {
+        match self {
+            Emitter::Red => [255, 0, 0],
+            Emitter::Green => [0, 255, 0],
+            Emitter::Blue => [0, 0, 255],
+            Emitter::White => [255, 255, 255],
+            Emitter::Amber => [255, 150, 0],
+            Emitter::Lime => [180, 255, 0],
+            Emitter::Uv => [64, 0, 128],
+        }
+    }
+}
+
+/// Per-fixture corrections, so that washes made up of different brands match
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EmitterCalibration {
+    /// Scale applied to each emitter's level, e.g. `{ "red": 0.85 }` for a red
+    /// that is brighter than the other fixtures'
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub gain: IndexMap<Emitter, f32>,
+    /// What the white, amber or lime emitters look like at full, in RGB,
+    /// where this differs from the defaults, e.g. `{ "white": [255, 230, 200] }`
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub appearance: IndexMap<Emitter, [u8; 3]>,
+}
+
+/// Levels `[0,1]` for each emitter of a fixture
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EmitterLevels {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub white: f32,
+    pub amber: f32,
+    pub lime: f32,
+    pub uv: f32,
+}
+
+impl EmitterLevels {
+    fn get_mut(&mut self, emitter: Emitter) -> &mut f32 {
+        match emitter {
+            Emitter::Red => &mut self.red,
+            Emitter::Green => &mut self.green,
+            Emitter::Blue => &mut self.blue,
+            Emitter::White => &mut self.white,
+            Emitter::Amber => &mut self.amber,
+            Emitter::Lime => &mut self.lime,
+            Emitter::Uv => &mut self.uv,
+        }
+    }
+
+    /// The 8-bit DMX value for one emitter
+    pub fn dmx_value(&self, emitter: Emitter) -> u8 {
+        let level = match emitter {
+            Emitter::Red => self.red,
+            Emitter::Green => self.green,
+            Emitter::Blue => self.blue,
+            Emitter::White => self.white,
+            Emitter::Amber => self.amber,
+            Emitter::Lime => self.lime,
+            Emitter::Uv => self.uv,
+        };
+        (level.clamp(0., 1.) * 255.).round() as u8
+    }
+}
+
+/// Work out emitter levels which reproduce the target colour, using up as much
+/// as possible of each extra emitter (white first, then amber and lime)
+/// before making up the rest with red, green and blue. UV is never mixed in.
+///
+/// The colour's alpha is the "white mix" of Colour Macros: 255 for the colour
+/// as-is, down to 0 to add full white on top (only for fixtures with white).
+pub fn mix_emitters(
+    target: Color32,
+    available: &[Emitter],
+    calibration: Option<&EmitterCalibration>,
+) -> EmitterLevels {
+    let (r, g, b) = to_unit_rgb(target);
+    let mut remaining = [r, g, b];
+    if available.contains(&Emitter::White) {
+        let white_mix = 1. - target.a() as f32 / 255.;
+        for c in remaining.iter_mut() {
+            *c += white_mix;
+        }
+    }
+
+    let mut levels = EmitterLevels::default();
+    for emitter in EXTRA_EMITTERS {
+        if !available.contains(&emitter) {
+            continue;
+        }
+        let appearance = calibration
+            .and_then(|c| c.appearance.get(&emitter))
+            .copied()
+            .unwrap_or(emitter.default_appearance())
+            .map(|c| c as f32 / 255.);
+        // As much of this emitter as fits inside what is left of the target
+        let amount = remaining
+            .iter()
+            .zip(appearance)
+            .filter(|(_, e)| *e > 0.01)
+            .map(|(t, e)| t / e)
+            .fold(f32::MAX, f32::min)
+            .clamp(0., 1.);
+        for (t, e) in remaining.iter_mut().zip(appearance) {
+            *t = (*t - amount * e).max(0.);
+        }
+        *levels.get_mut(emitter) = amount;
+    }
+    [levels.red, levels.green, levels.blue] = remaining.map(|c| c.min(1.));
+
+    if let Some(calibration) = calibration {
+        for (emitter, gain) in calibration.gain.iter() {
+            *levels.get_mut(*emitter) *= gain;
+        }
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Emitter::*;
+
+    const RGBW: [Emitter; 4] = [Red, Green, Blue, White];
+    const RGBWA: [Emitter; 5] = [Red, Green, Blue, White, Amber];
+    const RGBWAUV: [Emitter; 6] = [Red, Green, Blue, White, Amber, Uv];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    /// What a set of levels looks like, in the same terms as the target colour
+    fn appearance(levels: &EmitterLevels, emitters: &[Emitter]) -> [f32; 3] {
+        let mut total = [0.; 3];
+        for emitter in emitters {
+            let level = levels.dmx_value(*emitter) as f32 / 255.;
+            for (t, e) in total.iter_mut().zip(emitter.default_appearance()) {
+                *t += level * e as f32 / 255.;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn extra_emitters_are_used_up_first() {
+        let levels = mix_emitters(Color32::WHITE, &RGBW, None);
+        assert_eq!(levels.white, 1.);
+        assert_eq!([levels.red, levels.green, levels.blue], [0., 0., 0.]);
+
+        let levels = mix_emitters(Color32::from_rgb(255, 150, 0), &RGBWA, None);
+        assert_eq!(levels.amber, 1.);
+        assert_eq!([levels.red, levels.green, levels.blue], [0., 0., 0.]);
+        assert_eq!(levels.white, 0.);
+
+        let levels = mix_emitters(Color32::RED, &RGBW, None);
+        assert_eq!(levels.red, 1.);
+        assert_eq!(levels.white, 0.);
+    }
+
+    #[test]
+    fn mixes_reproduce_the_target_colour() {
+        let colours = [
+            Color32::from_rgb(200, 100, 50),
+            Color32::from_rgb(255, 200, 120),
+            Color32::from_rgb(30, 180, 255),
+            Color32::from_rgb(128, 0, 255),
+            Color32::from_rgb(90, 90, 90),
+        ];
+        for emitters in [&RGBW[..], &RGBWA[..], &RGBWAUV[..]] {
+            for colour in colours {
+                let levels = mix_emitters(colour, emitters, None);
+                let (r, g, b) = to_unit_rgb(colour);
+                let mixed = appearance(&levels, emitters);
+                assert!(
+                    mixed.iter().zip([r, g, b]).all(|(m, t)| close(*m, t)),
+                    "{:?} on {:?} gave {:?}",
+                    colour,
+                    emitters,
+                    mixed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uv_is_not_mixed() {
+        let purple = Color32::from_rgb(128, 0, 255);
+        let levels = mix_emitters(purple, &RGBWAUV, None);
+        assert_eq!(levels.uv, 0.);
+        assert!(close(levels.red, 128. / 255.));
+        assert_eq!(levels.blue, 1.);
+        assert!(!Uv.is_mixed());
+    }
+
+    #[test]
+    fn alpha_adds_white_only_where_there_is_white() {
+        let pale_red = Color32::from_rgba_premultiplied(255, 0, 0, 0);
+        let levels = mix_emitters(pale_red, &RGBW, None);
+        assert_eq!([levels.red, levels.white], [1., 1.]);
+        assert_eq!([levels.green, levels.blue], [0., 0.]);
+
+        let levels = mix_emitters(pale_red, &[Red, Green, Blue], None);
+        assert_eq!([levels.red, levels.green, levels.blue], [1., 0., 0.]);
+    }
+
+    #[test]
+    fn calibration_scales_and_corrects_emitters() {
+        let calibration = EmitterCalibration {
+            gain: IndexMap::from([(Red, 0.5)]),
+            appearance: IndexMap::from([(White, [255, 230, 204])]),
+        };
+        let levels = mix_emitters(Color32::WHITE, &RGBW, Some(&calibration));
+        assert_eq!(levels.white, 1.);
+        assert_eq!(levels.red, 0.);
+        assert!(close(levels.green, 25. / 255.));
+        assert!(close(levels.blue, 51. / 255.));
+
+        let levels = mix_emitters(Color32::RED, &RGBW, Some(&calibration));
+        assert_eq!(levels.red, 0.5);
+    }
+
+    #[test]
+    fn temperature_known_values() {
+        assert_eq!(
+            Temperature::new(6600, 0.).to_rgb(),
+            Color32::from_rgb(255, 255, 255)
+        );
+        assert_eq!(
+            Temperature::new(2700, 0.).to_rgb(),
+            Color32::from_rgb(255, 166, 87)
+        );
+        assert_eq!(
+            Temperature::new(6600, 1.).to_rgb(),
+            Color32::from_rgb(127, 255, 127)
+        );
+        assert_eq!(
+            Temperature::new(6600, -1.).to_rgb(),
+            Color32::from_rgb(255, 127, 255)
+        );
+
+        let d65 = Temperature::from_rgb(Color32::WHITE).unwrap();
+        assert!((6400..=6600).contains(&d65.kelvin), "{}", d65.kelvin);
+        assert_eq!(Temperature::from_rgb(Color32::BLACK), None);
+    }
+
+    #[test]
+    fn temperature_round_trip() {
+        for kelvin in [2000, 2700, 3200, 4000, 5600, 6500, 8000] {
+            let back = Temperature::from_rgb(Temperature::new(kelvin, 0.).to_rgb())
+                .unwrap()
+                .kelvin;
+            let error = (back as f32 - kelvin as f32).abs() / kelvin as f32;
+            assert!(error < 0.06, "{}K came back as {}K", kelvin, back);
+        }
+    }
+
+    #[test]
+    fn hsv_known_values() {
+        assert_eq!(hsv_to_rgb([0., 1., 1.]), Color32::RED);
+        assert_eq!(hsv_to_rgb([1. / 3., 1., 1.]), Color32::GREEN);
+        assert_eq!(hsv_to_rgb([2. / 3., 1., 1.]), Color32::BLUE);
+        assert_eq!(hsv_to_rgb([1., 1., 1.]), Color32::RED);
+        assert_eq!(hsv_to_rgb([0.3, 0., 1.]), Color32::WHITE);
+        assert_eq!(hsv_to_rgb([0.5, 1., 0.5]), Color32::from_rgb(0, 128, 128));
+    }
+
+    #[test]
+    fn hsi_known_values() {
+        assert_eq!(hsi_to_rgb([0., 1., 1. / 3.]), Color32::RED);
+        assert_eq!(hsi_to_rgb([1. / 3., 1., 1. / 3.]), Color32::GREEN);
+        assert_eq!(hsi_to_rgb([2. / 3., 1., 1. / 3.]), Color32::BLUE);
+        assert_eq!(hsi_to_rgb([0., 0., 0.5]), Color32::from_gray(128));
+
+        let [h, s, i] = rgb_to_hsi(Color32::from_rgb(255, 0, 255));
+        assert!(close(h, 5. / 6.) && close(s, 1.) && close(i, 2. / 3.));
+    }
+
+    #[test]
+    fn hsi_round_trip() {
+        for colour in [
+            Color32::from_rgb(200, 100, 50),
+            Color32::from_rgb(50, 200, 100),
+            Color32::from_rgb(10, 20, 200),
+            Color32::from_rgb(90, 10, 240),
+            Color32::from_rgb(255, 128, 0),
+            Color32::from_gray(128),
+            Color32::BLACK,
+        ] {
+            assert_eq!(hsi_to_rgb(rgb_to_hsi(colour)), colour);
+        }
+    }
+}
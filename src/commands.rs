@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use chrono::Local;
+use log::{debug, info, warn};
+
+use tether_artnet_controller::{
+    artnet::zero,
+    project::{
+        artnetconfig::{get_artnet_interface, output_port_address},
+        fixture::FixtureMacro,
+        load_all_fixture_configs, Project,
+    },
+    receiver::{ReceivedPacket, VirtualReceiver},
+    recording::{Recording, RecordingPlayer},
+    settings::{EngineConfig, CHANNELS_PER_UNIVERSE},
+    tether_interface::{send_scene_message, RemoteSceneMessage},
+};
+
+/// How many times to repeat one-shot ArtNet frames, since UDP gives no guarantees
+const ONE_SHOT_REPEATS: usize = 3;
+
+pub fn validate(project_path: &str) -> anyhow::Result<()> {
+    let project = Project::load(project_path)?;
+    let report = project.validate();
+
+    for warning in report.warnings.iter() {
+        println!("WARNING: {}", warning);
+    }
+    for error in report.errors.iter() {
+        println!("ERROR: {}", error);
+    }
+
+    if report.is_valid() {
+        println!(
+            "Project \"{}\" is valid ({} fixtures, {} scenes, {} warnings)",
+            project_path,
+            project.fixtures.len(),
+            project.scenes.len(),
+            report.warnings.len()
+        );
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Project \"{}\" has {} error(s)",
+            project_path,
+            report.errors.len()
+        ))
+    }
+}
+
+pub fn list_fixtures() {
+    let mut configs = load_all_fixture_configs();
+    configs.sort_by_key(|c| c.name.to_lowercase());
+    for config in configs.iter() {
+        println!("{} ({} modes)", &config.name, config.modes.len());
+    }
+}
+
+pub fn show_fixture(name: &str) -> anyhow::Result<()> {
+    let configs = load_all_fixture_configs();
+    let config = configs
+        .iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .ok_or(anyhow!(
+            "No fixture named \"{}\"; use `fixtures list` to see all known fixtures",
+            name
+        ))?;
+
+    println!("{}", &config.name);
+    println!("Reference: {}", &config.reference);
+    for (mode_index, mode) in config.modes.iter().enumerate() {
+        println!();
+        println!(
+            "Mode #{}: {} ({} channels)",
+            mode_index,
+            &mode.name,
+            mode.mappings.len()
+        );
+        for m in mode.mappings.iter() {
+            println!(
+                "  CH{:<3} {}{}",
+                m.channel,
+                &m.label,
+                m.home.map(|h| format!(" (home {})", h)).unwrap_or_default()
+            );
+            if let Some(ranges) = &m.ranges {
+                for r in ranges.iter() {
+                    println!(
+                        "         {:>3}-{:<3} {}{}",
+                        r.range[0],
+                        r.range[1],
+                        &r.label,
+                        if r.strobe { " (strobe)" } else { "" }
+                    );
+                }
+            }
+        }
+        if !mode.macros.is_empty() {
+            println!("  Macros:");
+            for m in mode.macros.iter() {
+                let kind = match m {
+                    FixtureMacro::Control(_) => "control",
+                    FixtureMacro::Colour(_) => "colour",
+                    FixtureMacro::Position(_) => "position",
+                    FixtureMacro::Temperature(_) => "temperature",
+                };
+                println!("    {} ({})", m.label(), kind);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn print_patch(project_path: &str) -> anyhow::Result<()> {
+    let project = Project::load(project_path)?;
+
+    let mut fixtures = project.fixtures.clone();
+    fixtures.sort_by_key(|f| f.start_channel);
+
+    println!(
+        "{:<9} {:<32} {:<32} {:<20} {:>5}",
+        "DMX", "Label", "Fixture", "Mode", "Chans"
+    );
+    for fixture in fixtures.iter() {
+        let range = match fixture.channel_range() {
+            Some((first, last)) => format!("{}-{}", first, last),
+            None => String::from("-"),
+        };
+        println!(
+            "{:<9} {:<32} {:<32} {:<20} {:>5}",
+            range,
+            &fixture.label,
+            &fixture.config_name,
+            &fixture.config.active_mode.name,
+            fixture.config.active_mode.mappings.len()
+        );
+    }
+
+    for warning in project.validate().warnings.iter() {
+        println!("WARNING: {}", warning);
+    }
+
+    Ok(())
+}
+
+pub fn print_schedule(project_path: &str, count: usize) -> anyhow::Result<()> {
+    let project = Project::load(project_path)?;
+    let Some(schedule) = &project.schedule else {
+        println!("Project \"{}\" has no schedule", project_path);
+        return Ok(());
+    };
+
+    for (time, entry) in schedule.upcoming(Local::now(), count) {
+        println!(
+            "{:<22} {:<24} {}",
+            time.format("%a %Y-%m-%d %H:%M"),
+            &entry.when,
+            entry.action.describe()
+        );
+    }
+
+    for error in project.validate().errors.iter() {
+        println!("ERROR: {}", error);
+    }
+
+    Ok(())
+}
+
+pub fn scene_go(
+    config: &EngineConfig,
+    scene_label: &str,
+    ms: Option<u64>,
+    fixture_labels: Option<Vec<String>>,
+) -> anyhow::Result<()> {
+    send_scene_message(
+        config.tether_host.as_deref(),
+        config.tether_port.as_ref(),
+        config.tether_user.as_deref(),
+        config.tether_pass.as_deref(),
+        config.tether_subscribe_id.as_deref(),
+        RemoteSceneMessage {
+            scene_label: String::from(scene_label),
+            ms,
+            fixture_labels,
+        },
+    )?;
+    info!("Sent scene \"{}\" OK", scene_label);
+    Ok(())
+}
+
+pub fn blackout(config: &EngineConfig, project_path: Option<&str>) -> anyhow::Result<()> {
+    let mut project = match project_path {
+        Some(path) => Project::load(path)?,
+        None => Project::new(),
+    };
+    let mut artnet = get_artnet_interface(config, &project)?;
+
+    let mut channels = Vec::with_capacity(CHANNELS_PER_UNIVERSE as usize);
+    zero(&mut channels);
+    // Parked channels (e.g. lamps, hazers) keep their values even in a blackout
+    let parked = project.parked_channels();
+
+    let mut frames_sent = 0;
+    while frames_sent < ONE_SHOT_REPEATS {
+        if artnet.update(&channels, &[], parked, None, false) {
+            if config.artnet_sync {
+                artnet.send_sync();
+            }
+            frames_sent += 1;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    info!("Blackout sent");
+    Ok(())
+}
+
+pub fn replay(
+    config: &EngineConfig,
+    recording_path: &str,
+    looping: bool,
+    speed: f64,
+    from_secs: f64,
+) -> anyhow::Result<()> {
+    if speed <= 0. {
+        return Err(anyhow!("Speed must be more than zero"));
+    }
+    let recording = Recording::load(recording_path)?;
+    let port_address = output_port_address(config, &Project::new());
+    if recording.port_address != port_address {
+        warn!(
+            "Recording was made on Port-Address {}, but will be sent on {}",
+            recording.port_address, port_address
+        );
+    }
+    let mut artnet = get_artnet_interface(config, &Project::new())?;
+
+    let from_ms = (from_secs * 1000.) as u32;
+    info!(
+        "Replaying {} frames ({:.1}s) from \"{}\"",
+        recording.frames.len(),
+        recording.duration_ms() as f64 / 1000.,
+        recording_path
+    );
+
+    let mut player = RecordingPlayer::new(&recording);
+    player.advance_to(from_ms);
+    let mut started = Instant::now();
+    let mut pending = true;
+    loop {
+        let position = from_ms + (started.elapsed().as_secs_f64() * speed * 1000.) as u32;
+        if player.advance_to(position) {
+            pending = true;
+        }
+        // Keep trying until the interface is ready to send
+        if pending && artnet.update(player.channels(), &[], &[], None, false) {
+            if config.artnet_sync {
+                artnet.send_sync();
+            }
+            pending = false;
+        }
+        if player.is_finished() && !pending {
+            if !looping {
+                break;
+            }
+            debug!("Recording finished; looping");
+            player.rewind();
+            player.advance_to(from_ms);
+            started = Instant::now();
+            pending = true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    info!("Replay finished");
+    Ok(())
+}
+
+pub fn export_csv(recording_path: &str, output_path: Option<&str>) -> anyhow::Result<()> {
+    let recording = Recording::load(recording_path)?;
+    let output_path = match output_path {
+        Some(path) => String::from(path),
+        None => Path::new(recording_path)
+            .with_extension("csv")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let mut out = BufWriter::new(File::create(&output_path)?);
+    recording.write_csv(&mut out)?;
+    out.flush()?;
+    info!(
+        "Exported {} frames to \"{}\"",
+        recording.frames.len(),
+        output_path
+    );
+    Ok(())
+}
+
+pub fn monitor(port: u16, gui: bool, all: bool, count: Option<usize>) -> anyhow::Result<()> {
+    let mut receiver = VirtualReceiver::bind(port)?;
+
+    if gui {
+        #[cfg(not(feature = "gui"))]
+        return Err(anyhow!(
+            "Built without the GUI; leave out --gui to print to the console"
+        ));
+        #[cfg(feature = "gui")]
+        return monitor_window(receiver);
+    }
+
+    println!("Listening on {}", receiver.local_addr()?);
+    let mut last_printed: HashMap<u16, Vec<u8>> = HashMap::new();
+    let mut printed = 0;
+    while count.is_none_or(|count| printed < count) {
+        let Some((packet, from)) = receiver.receive() else {
+            continue;
+        };
+        let should_print = match &packet {
+            ReceivedPacket::Dmx {
+                port_address, data, ..
+            } => all || last_printed.insert(*port_address, data.clone()).as_ref() != Some(data),
+            ReceivedPacket::Poll => true,
+            ReceivedPacket::Sync | ReceivedPacket::TimeCode(..) | ReceivedPacket::Other => all,
+        };
+        if should_print {
+            println!("{} from {}", packet, from);
+            printed += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "gui")]
+fn monitor_window(receiver: VirtualReceiver) -> anyhow::Result<()> {
+    use tether_artnet_controller::ui::{ReceiverApp, WIDER_WINDOW};
+
+    receiver.set_timeout(Duration::from_millis(1))?;
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size(WIDER_WINDOW),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "ArtNet Receiver",
+        options,
+        Box::new(|_cc| Box::new(ReceiverApp::new(receiver))),
+    )
+    .map_err(|e| anyhow!("Failed to launch GUI: {}", e))
+}
@@ -0,0 +1,1665 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Local, Timelike};
+use ecolor::Color32;
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use tween::QuadInOut;
+
+use crate::{
+    animation::{animate_colour, animate_position, animate_temperature, Animation},
+    artnet::{random, zero, ArtNetInterface},
+    clock::{system_clock, SharedClock},
+    colour::Temperature,
+    project::{
+        artnetconfig::{get_artnet_interface, output_port_address},
+        autosave::Autosave,
+        fixture::{
+            FixtureConfig, FixtureInstance, FixtureMacro, PositionMacro, TemperatureMacro,
+            ValueSource, LEGACY_PAN, LEGACY_TILT,
+        },
+        load_all_fixture_configs,
+        palette::INTENSITY_MACROS,
+        palette::{resolve_scene_value, Palette},
+        reload::ProjectWatcher,
+        safety::SafetyConfig,
+        scene::{SceneState, SceneValue},
+        schedule::{ScheduleAction, Scheduler},
+        timeline::{TimecodeSource, TimelinePlayer},
+        Project,
+    },
+    recording::Recorder,
+    settings::{EngineConfig, TimecodeOutput, CHANNELS_PER_UNIVERSE},
+    tether_interface::{
+        RemoteControlMessage, RemoteMacroMessage, RemoteMacroValue, RemoteSafetyMessage,
+        RemoteSceneMessage, TetherControlChangePayload, TetherInterface, TetherKnobPayload,
+        TetherMidiMessage, TetherNotePayload,
+    },
+    timecode::Timecode,
+};
+
+#[derive(PartialEq, Deserialize, Serialize)]
+pub enum BehaviourOnExit {
+    DoNothing,
+    Home,
+    Zero,
+}
+
+pub enum TetherStatus {
+    NotConnected,
+    Connected,
+    Errored(String),
+}
+
+/// Everything needed to drive a rig: the project, fixture macros, scenes and
+/// animations, remote control and ArtNet output, but no GUI. Call `update`
+/// regularly (about once a millisecond) to keep it running.
+pub struct Engine {
+    pub settings: EngineConfig,
+    pub channels_state: Vec<u8>,
+    pub channels_assigned: Vec<bool>,
+    /// What last set each channel, for the output monitor
+    pub channel_sources: Vec<ValueSource>,
+    pub tether_interface: TetherInterface,
+    pub tether_status: TetherStatus,
+    /// A working, connected ArtNet interface, or None if disconnected
+    /// and/or currently editing settings
+    pub artnet: Option<ArtNetInterface>,
+    pub recorder: Option<Recorder>,
+    pub project: Project,
+    /// If None, we are in a New/Unsaved project
+    pub current_project_path: Option<String>,
+    /// If Some, the project file is checked for changes and reloaded automatically
+    pub project_watcher: Option<ProjectWatcher>,
+    /// If Some, the project is periodically saved to a sidecar file
+    pub autosave: Option<Autosave>,
+    /// Keeps track of when the project's schedule is next due
+    pub scheduler: Scheduler,
+    /// Follows timecode to fire the project's timeline cues
+    pub timeline_player: TimelinePlayer,
+    /// Time for animations, timeline playback and output
+    pub clock: SharedClock,
+    pub known_fixtures: Vec<FixtureConfig>,
+
+    /// Whether macros should currently be applied via ArtNet output.
+    /// It is important that this is _disabled_ when adjusting channel
+    /// values directly, e.g. in Setup mode.
+    pub apply_macros: bool,
+    /// Determines which macros are adjusted via MIDI
+    pub selected_macro_group_index: usize,
+    pub exit_mode: BehaviourOnExit,
+    pub save_on_exit: bool,
+    pub should_quit: Arc<Mutex<bool>>,
+}
+
+impl Engine {
+    /// Load the project given in the config (or start a blank one), and
+    /// connect ArtNet and Tether as configured
+    pub fn new(config: EngineConfig) -> Engine {
+        let mut current_project_path = None;
+
+        let project = match Project::load(&config.project_path) {
+            Ok(p) => {
+                current_project_path = Some(String::from(&config.project_path));
+                p
+            }
+            Err(e) => {
+                error!(
+                    "Failed to load project from path \"{}\"; {:?}",
+                    &config.project_path, e
+                );
+                info!("Blank project will be loaded instead.");
+                Project::new()
+            }
+        };
+
+        Engine::with_project(config, project, current_project_path)
+    }
+
+    /// Run an already-loaded project; the path (if any) is where it is saved
+    /// and reloaded from
+    pub fn with_project(
+        config: EngineConfig,
+        project: Project,
+        current_project_path: Option<String>,
+    ) -> Engine {
+        let clock = system_clock();
+        let artnet = get_artnet_interface(&config, &project).map(|artnet| {
+            artnet
+                .with_clock(clock.clone())
+                .with_send_policy(config.send_policy())
+        });
+
+        let recorder = config.record_path.as_deref().and_then(|path| {
+            Recorder::create(path, output_port_address(&config, &project))
+                .map_err(|e| error!("Could not start recording to \"{}\": {}", path, e))
+                .ok()
+        });
+
+        let channels_assigned = assigned_channels(&project.fixtures);
+
+        let project_watcher = match &current_project_path {
+            Some(path) if config.headless_mode && !config.project_disable_watch => {
+                info!("Will watch project file \"{}\" for changes", path);
+                Some(ProjectWatcher::new(path))
+            }
+            _ => None,
+        };
+
+        let autosave = if config.project_autosave_secs > 0 {
+            Some(Autosave::new(
+                Duration::from_secs(config.project_autosave_secs),
+                &project,
+            ))
+        } else {
+            None
+        };
+
+        let should_quit = Arc::new(Mutex::new(false));
+
+        let tether_interface = TetherInterface::new();
+
+        let should_auto_connect = !config.tether_disable_autoconnect;
+
+        let mut engine = Engine {
+            tether_status: TetherStatus::NotConnected,
+            tether_interface,
+            channels_state: Vec::new(),
+            channels_assigned,
+            channel_sources: [ValueSource::None].repeat(CHANNELS_PER_UNIVERSE as usize),
+            settings: config,
+            artnet: artnet.ok(),
+            recorder,
+            project,
+            known_fixtures: load_all_fixture_configs(),
+            current_project_path,
+            project_watcher,
+            autosave,
+            scheduler: Scheduler::default(),
+            timeline_player: TimelinePlayer::new(clock.clone()),
+            clock: clock.clone(),
+            selected_macro_group_index: 0,
+            apply_macros: false,
+            exit_mode: BehaviourOnExit::Home,
+            save_on_exit: true,
+            should_quit,
+        };
+
+        if should_auto_connect {
+            info!("Auto connect Tether enabled; will attempt to connect now...");
+            engine.connect_tether()
+        }
+
+        engine.apply_home_values();
+
+        engine
+    }
+
+    pub fn update(&mut self) {
+        let mut work_done = false;
+
+        if let Some(watcher) = &mut self.project_watcher {
+            if watcher.has_changed() {
+                info!("Project file \"{}\" changed on disk", watcher.path());
+                self.reload_project();
+                work_done = true;
+            }
+        }
+
+        if let Some(autosave) = &mut self.autosave {
+            autosave.update(self.current_project_path.as_deref(), &self.project);
+        }
+
+        while let Ok(m) = self.tether_interface.message_rx.try_recv() {
+            work_done = true;
+            self.apply_macros = true;
+            match m {
+                RemoteControlMessage::Midi(midi_msg) => {
+                    self.handle_midi_message(midi_msg);
+                }
+                RemoteControlMessage::MacroAnimation(animation_msg) => {
+                    self.handle_macro_message(animation_msg);
+                }
+                RemoteControlMessage::SceneAnimation(scene_msg) => {
+                    self.handle_scene_message(scene_msg);
+                }
+                RemoteControlMessage::Palette(palette_msg) => {
+                    self.apply_palette(
+                        &palette_msg.palette_label,
+                        palette_msg.ms,
+                        palette_msg.fixture_labels,
+                    );
+                }
+                RemoteControlMessage::Park(park_msg) => {
+                    info!(
+                        "Remote message parks {} at {:?}",
+                        park_msg.target.describe(),
+                        park_msg.value
+                    );
+                    self.project.set_park(park_msg.target, park_msg.value);
+                }
+                RemoteControlMessage::Timecode(timecode_msg) => {
+                    let Some(timeline) = &self.project.timeline else {
+                        debug!("Timecode message ignored; project has no timeline");
+                        continue;
+                    };
+                    match Timecode::parse_with_rate(&timecode_msg.timecode, timeline.frame_rate) {
+                        Ok(timecode) => self
+                            .timeline_player
+                            .receive(timecode.to_ms(timeline.frame_rate), TimecodeSource::Tether),
+                        Err(e) => error!("Remote timecode message: {}", e),
+                    }
+                }
+                RemoteControlMessage::Safety(safety_msg) => {
+                    self.handle_safety_message(safety_msg);
+                }
+                RemoteControlMessage::ReloadProject => {
+                    info!("Remote message requests project reload");
+                    self.reload_project();
+                }
+            }
+        }
+
+        for action in self
+            .scheduler
+            .due(self.project.schedule.as_ref(), Local::now())
+        {
+            work_done = true;
+            info!("Schedule: {}", action.describe());
+            self.run_action(action);
+        }
+
+        if let (Some(artnet), Some(timeline)) = (&mut self.artnet, &self.project.timeline) {
+            while let Some((timecode, rate)) = artnet.receive_timecode() {
+                if rate != timeline.frame_rate {
+                    debug!(
+                        "ArtTimeCode at {:?} while timeline is {:?}",
+                        rate, timeline.frame_rate
+                    );
+                }
+                self.timeline_player
+                    .receive(timecode.to_ms(rate), TimecodeSource::ArtNet);
+            }
+        }
+        for action in self.timeline_player.due(self.project.timeline.as_ref()) {
+            work_done = true;
+            info!("Timeline: {}", action.describe());
+            self.run_action(action);
+        }
+
+        if self.settings.auto_random {
+            random(&mut self.channels_state);
+            self.set_all_channel_sources(ValueSource::Slider);
+        } else if self.settings.auto_zero {
+            zero(&mut self.channels_state);
+            self.set_all_channel_sources(ValueSource::Slider);
+        }
+        if let Some(artnet) = &mut self.artnet {
+            let project = &mut self.project;
+            if artnet.update(
+                &self.channels_state,
+                &project.fixtures,
+                project.parked.get(&project.parks, &project.fixtures),
+                project.safety.as_ref(),
+                self.apply_macros,
+            ) {
+                trace!("Artnet did update");
+                work_done = true;
+                if self.settings.artnet_sync {
+                    artnet.send_sync();
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.record(artnet.get_state());
+                }
+                if self.apply_macros {
+                    self.update_macro_channel_sources();
+                }
+            }
+        }
+        if let Some(artnet) = &mut self.artnet {
+            artnet.poll_destinations();
+        }
+
+        self.send_timecode();
+
+        if self.apply_macros {
+            work_done = true;
+            self.animate_macros();
+            if let Some(artnet) = &self.artnet {
+                // Only what the macros wrote; the output stage never feeds back
+                self.channels_state.clear();
+                self.channels_state
+                    .extend_from_slice(artnet.get_live_state());
+            }
+        }
+
+        if self.settings.auto_random || self.settings.auto_zero {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        if !work_done {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Anyone may switch the safety limiter on remotely, but only projects which
+    /// explicitly allow it can have it switched off
+    fn handle_safety_message(&mut self, m: RemoteSafetyMessage) {
+        match (&mut self.project.safety, m.enabled) {
+            (Some(safety), true) => {
+                info!("Remote message enables safety limiter");
+                safety.enabled = true;
+            }
+            (None, true) => {
+                info!("Remote message enables safety limiter, with default limits");
+                self.project.safety = Some(SafetyConfig::default());
+            }
+            (Some(safety), false) if safety.allow_remote_override => {
+                warn!("Remote message disables safety limiter");
+                safety.enabled = false;
+            }
+            (Some(_), false) => {
+                error!("Remote message tried to disable safety limiter; not allowed by project, ignored");
+            }
+            (None, false) => {}
+        }
+    }
+
+    /// Emit ArtTimeCode, if asked to on the command line
+    fn send_timecode(&mut self) {
+        let Some(output) = self.settings.artnet_timecode else {
+            return;
+        };
+        let Some(artnet) = &mut self.artnet else {
+            return;
+        };
+        let timeline = self.project.timeline.as_ref();
+        let rate = timeline.map(|t| t.frame_rate).unwrap_or_default();
+        let position_ms = match (output, timeline) {
+            (TimecodeOutput::Internal, _) => {
+                let now = Local::now().time();
+                now.num_seconds_from_midnight() as u64 * 1000 + now.nanosecond() as u64 / 1_000_000
+            }
+            (TimecodeOutput::Timeline, Some(timeline)) => {
+                self.timeline_player.position_ms(timeline)
+            }
+            (TimecodeOutput::Timeline, None) => return,
+        };
+        artnet.send_timecode(Timecode::from_ms(position_ms, rate), rate);
+    }
+
+    /// Channels written by macros take on the source which last set that macro
+    fn update_macro_channel_sources(&mut self) {
+        for fixture in self.project.fixtures.iter() {
+            for m in fixture.config.active_mode.macros.iter() {
+                let source = match m.last_source() {
+                    ValueSource::None => ValueSource::Macro,
+                    s => s,
+                };
+                for c in m.channels() {
+                    let channel_index = (c - 1) + (fixture.start_channel - 1);
+                    if let Some(s) = self.channel_sources.get_mut(channel_index as usize) {
+                        *s = source;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_all_channel_sources(&mut self, source: ValueSource) {
+        self.channel_sources = [source].repeat(CHANNELS_PER_UNIVERSE as usize);
+    }
+
+    fn animate_macros(&mut self) {
+        let now = self.clock.now();
+        for fixture in self.project.fixtures.iter_mut() {
+            for m in fixture.config.active_mode.macros.iter_mut() {
+                match m {
+                    FixtureMacro::Control(control_macro) => {
+                        if let Some(animation) = &mut control_macro.animation {
+                            let (value, is_done) = animation.get_value_and_done(now);
+                            let int_value = (value * u16::MAX as f32) as u16;
+
+                            control_macro.current_value = int_value;
+
+                            // NB: Check if done AFTER applying value
+                            if is_done {
+                                debug!("Animation done; delete");
+                                control_macro.animation = None;
+                            }
+                        }
+                    }
+                    FixtureMacro::Colour(colour_macro) => {
+                        if let Some((animation, start_colour, end_colour)) =
+                            &mut colour_macro.animation
+                        {
+                            let (progress, is_done) = animation.get_value_and_done(now);
+                            colour_macro.current_value =
+                                animate_colour(start_colour, end_colour, progress);
+
+                            // NB: Check if done AFTER applying value
+                            if is_done {
+                                debug!("Animation done; delete");
+                                colour_macro.animation = None;
+                            }
+                        }
+                    }
+                    FixtureMacro::Position(position_macro) => {
+                        if let Some((animation, start, end)) = &mut position_macro.animation {
+                            let (progress, is_done) = animation.get_value_and_done(now);
+                            position_macro.current_value = animate_position(start, end, progress);
+
+                            // NB: Check if done AFTER applying value
+                            if is_done {
+                                debug!("Animation done; delete");
+                                position_macro.animation = None;
+                            }
+                        }
+                    }
+                    FixtureMacro::Temperature(temperature_macro) => {
+                        if let Some((animation, start, end)) = &mut temperature_macro.animation {
+                            let (progress, is_done) = animation.get_value_and_done(now);
+                            temperature_macro.current_value =
+                                animate_temperature(start, end, progress);
+
+                            // NB: Check if done AFTER applying value
+                            if is_done {
+                                debug!("Animation done; delete");
+                                temperature_macro.animation = None;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_midi_message(&mut self, m: TetherMidiMessage) {
+        match m {
+            // TetherMidiMessage::Raw(_) => todo!(),
+            TetherMidiMessage::NoteOn(note) => {
+                let TetherNotePayload {
+                    note,
+                    channel: _,
+                    velocity: _,
+                } = note;
+                let start_note = self.project.midi_config.note_start;
+                let index = note - start_note;
+                debug!("Note {} => macro group index {}", note, index);
+                self.selected_macro_group_index = index as usize;
+            }
+            // TetherMidiMessage::NoteOff(_) => todo!(),
+            TetherMidiMessage::ControlChange(cc) => {
+                let TetherControlChangePayload {
+                    channel: _,
+                    controller,
+                    value,
+                } = cc;
+
+                let controller_start = self.project.midi_config.controller_start;
+
+                if controller < controller_start {
+                    return;
+                }
+
+                for (i, fixture) in self.project.fixtures.iter_mut().enumerate() {
+                    if self.selected_macro_group_index == i {
+                        let target_macro_index = controller - controller_start;
+                        debug!(
+                            "Controller number {} => target macro index {}",
+                            controller, target_macro_index
+                        );
+                        match macro_for_controller(
+                            &mut fixture.config.active_mode.macros,
+                            target_macro_index as usize,
+                        ) {
+                            Some((m, axis)) => match m {
+                                FixtureMacro::Control(control_macro) => {
+                                    // MIDI uses 7-bit, i.e. 0-127
+                                    let percentage = value as f32 / 127.0;
+                                    let converted_value: u16 =
+                                        (percentage * u16::MAX as f32) as u16;
+                                    debug!(
+                                        "Adjust {} to {}",
+                                        &control_macro.label, converted_value
+                                    );
+                                    control_macro.current_value = converted_value;
+                                    control_macro.last_source = ValueSource::Remote;
+                                }
+                                FixtureMacro::Colour(colour_macro) => {
+                                    let value = value * 2;
+
+                                    let [r, g, b, a] = colour_macro.current_value.to_array();
+
+                                    colour_macro.current_value =
+                                        Color32::from_rgba_premultiplied(r, g, b, value);
+                                    colour_macro.last_source = ValueSource::Remote;
+
+                                    debug!("Color a {} => {}", a, colour_macro.current_value.a());
+                                }
+                                FixtureMacro::Position(position_macro) => {
+                                    position_macro.current_value[axis] =
+                                        (value as f32 / 127.0 * u16::MAX as f32) as u16;
+                                    position_macro.last_source = ValueSource::Remote;
+                                }
+                                FixtureMacro::Temperature(temperature_macro) => {
+                                    // Sweep the range this fixture can actually produce
+                                    let (min, max) = temperature_macro.channels.kelvin_range();
+                                    let kelvin = min as f32
+                                        + (max as f32 - min as f32) * value as f32 / 127.0;
+                                    temperature_macro.current_value.kelvin = kelvin as u16;
+                                    temperature_macro.last_source = ValueSource::Remote;
+                                }
+                            },
+                            None => {
+                                error!("Failed to match macro control");
+                            }
+                        }
+                    }
+                }
+            }
+            TetherMidiMessage::Knob(TetherKnobPayload { index, position }) => {
+                for fixture in self.project.fixtures.iter_mut() {
+                    for m in fixture.config.active_mode.macros.iter_mut() {
+                        match m {
+                            FixtureMacro::Control(control_macro) => {
+                                if let Some(global_index) = control_macro.midi_knob_index {
+                                    if (index as usize) == global_index {
+                                        control_macro.current_value =
+                                            (u16::MAX as f32 * position) as u16;
+                                        control_macro.last_source = ValueSource::Remote;
+                                    }
+                                }
+                            }
+                            FixtureMacro::Position(position_macro) => {
+                                let axis = position_macro
+                                    .midi_knob_index
+                                    .and_then(|pan_index| (index as usize).checked_sub(pan_index))
+                                    .filter(|axis| *axis < 2);
+                                if let Some(axis) = axis {
+                                    position_macro.current_value[axis] =
+                                        (u16::MAX as f32 * position) as u16;
+                                    position_macro.last_source = ValueSource::Remote;
+                                }
+                            }
+                            FixtureMacro::Colour(_) | FixtureMacro::Temperature(_) => {
+                                // Ignore colour and temperature macros for now
+                            }
+                        }
+                    }
+                }
+                self.project.scenes.iter_mut().for_each(|scene| {
+                    if scene.last_active {
+                        scene.is_editing = true;
+                    }
+                });
+            }
+        }
+    }
+
+    pub fn handle_macro_message(&mut self, msg: RemoteMacroMessage) {
+        // Fixture labels may include group labels, which are expanded in order
+        let target_labels = msg
+            .fixture_labels
+            .as_ref()
+            .map(|labels| self.project.resolve_fixture_labels(labels));
+        let ordered_labels: Vec<String> = match &target_labels {
+            Some(labels) => labels.clone(),
+            None => self
+                .project
+                .fixtures
+                .iter()
+                .map(|f| f.label.clone())
+                .collect(),
+        };
+
+        for fixture in self.project.fixtures.iter_mut() {
+            if fixtures_list_contains(&target_labels, &fixture.label) {
+                let offset = spread_offset(&ordered_labels, &fixture.label, msg.spread);
+                if let Some(target_macro) = fixture
+                    .config
+                    .active_mode
+                    .macros
+                    .iter_mut()
+                    .find(|m| m.matches_label(&msg.macro_label))
+                {
+                    target_macro.set_last_source(ValueSource::Remote);
+                    match target_macro {
+                        FixtureMacro::Control(control_macro) => {
+                            match msg.value {
+                                RemoteMacroValue::ControlValue(target_value) => {
+                                    if let Some(ms) = msg.ms {
+                                        let duration = Duration::from_millis(ms);
+                                        let start_value =
+                                            control_macro.current_value as f32 / u16::MAX as f32;
+                                        let end_value =
+                                            (target_value / u16::MAX as f32 + offset).clamp(0., 1.);
+
+                                        control_macro.animation = Some(Animation::new(
+                                            duration,
+                                            start_value,
+                                            end_value,
+                                            Box::new(QuadInOut),
+                                        ));
+
+                                        debug!(
+                                            "Added Control Value animation with duration {}ms, {} -> {}",
+                                            duration.as_millis(),
+                                            start_value,
+                                            end_value
+                                        );
+                                    } else {
+                                        debug!(
+                                            "No animation; immediately go to Control Macro value"
+                                        );
+                                        control_macro.animation = None; // cancel first
+                                        control_macro.current_value = ((target_value + offset)
+                                            .clamp(0., 1.)
+                                            * (u16::MAX as f32))
+                                            as u16;
+                                    }
+                                }
+                                _ => {
+                                    error!("Remote Animation Message targets Control Macro, but provides Colour/Position Value instead");
+                                }
+                            }
+                        }
+                        FixtureMacro::Colour(colour_macro) => match msg.value.as_colour() {
+                            None => {
+                                error!("Remote Animation Message targets Colour Macro, but provices Control/Position Value instead");
+                            }
+                            Some(target_colour) => {
+                                if let Some(ms) = msg.ms {
+                                    let duration = Duration::from_millis(ms);
+                                    let start_value = 0.;
+                                    let end_value = 1.0;
+
+                                    let animation = Animation::new(
+                                        duration,
+                                        start_value,
+                                        end_value,
+                                        Box::new(QuadInOut),
+                                    );
+                                    let start_colour = colour_macro.current_value;
+                                    let end_colour = target_colour;
+
+                                    debug!(
+                                        "Added Colour animation with duration {}ms, {:?} => {:?}",
+                                        duration.as_millis(),
+                                        start_colour,
+                                        end_colour
+                                    );
+
+                                    colour_macro.animation =
+                                        Some((animation, start_colour, end_colour));
+                                } else {
+                                    debug!("No animation; immediately go to Colour Macro value");
+                                    colour_macro.current_value = target_colour;
+                                }
+                            }
+                        },
+                        FixtureMacro::Position(position_macro) => {
+                            let to_u16 = |v: f32| (v.clamp(0., 1.) * u16::MAX as f32) as u16;
+                            // Spread fans out the pan only
+                            let target = match msg.value {
+                                RemoteMacroValue::PositionValue([pan, tilt]) => {
+                                    Some([to_u16(pan + offset), to_u16(tilt)])
+                                }
+                                RemoteMacroValue::ControlValue(v) => {
+                                    let [pan, tilt] = position_macro.current_value;
+                                    if msg.macro_label.eq_ignore_ascii_case(LEGACY_PAN) {
+                                        Some([to_u16(v + offset), tilt])
+                                    } else if msg.macro_label.eq_ignore_ascii_case(LEGACY_TILT) {
+                                        Some([pan, to_u16(v)])
+                                    } else {
+                                        None
+                                    }
+                                }
+                                _ => None,
+                            };
+                            match target {
+                                Some(target) => go_to_position(position_macro, target, msg.ms),
+                                None => error!("Remote Animation Message targets Position Macro, but does not provide a Position Value (or legacy pan/tilt Control Value)"),
+                            }
+                        }
+                        FixtureMacro::Temperature(temperature_macro) => {
+                            match msg.value.as_temperature() {
+                                Some(target) => {
+                                    go_to_temperature(temperature_macro, target, msg.ms)
+                                }
+                                None => error!("Remote Animation Message targets Temperature Macro, but does not provide a colour of any kind"),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn handle_scene_message(&mut self, msg: RemoteSceneMessage) {
+        match self
+            .project
+            .scenes
+            .iter_mut()
+            .enumerate()
+            .find(|(_i, s)| s.label.eq_ignore_ascii_case(&msg.scene_label))
+        {
+            Some((index, scene)) => {
+                debug!("Found scene \"{}\" at index {}", &scene.label, index);
+                scene.last_active = true;
+                self.apply_scene(index, msg.ms, msg.fixture_labels);
+            }
+            None => {
+                error!("Failed to find matching scene for \"{}\"", &msg.scene_label);
+            }
+        }
+
+        // If we are in GUI mode, mark all scenes as active/not active depending on match
+        if !self.settings.headless_mode {
+            for scene in self.project.scenes.iter_mut() {
+                scene.last_active = scene.label.eq_ignore_ascii_case(&msg.scene_label);
+            }
+        }
+    }
+
+    pub fn apply_scene(
+        &mut self,
+        scene_index: usize,
+        animation_ms: Option<u64>,
+        fixture_filters: Option<Vec<String>>,
+    ) {
+        // Filters may include group labels as well as fixture labels
+        let fixture_filters =
+            fixture_filters.map(|filters| self.project.resolve_fixture_labels(&filters));
+        let groups = &self.project.groups;
+        let palettes = &self.project.palettes;
+
+        match self.project.scenes.get(scene_index) {
+            Some(scene) => {
+                debug!("Match scene {}", &scene.label);
+                for fixture in self.project.fixtures.iter_mut() {
+                    // If there are fixtureFilters applied, skip any fixture not in the list
+                    if let Some(filters) = &fixture_filters {
+                        if !filters
+                            .iter()
+                            .any(|f| f.eq_ignore_ascii_case(&fixture.label))
+                        {
+                            continue;
+                        }
+                    }
+
+                    // Scene entries for groups containing this fixture are applied first,
+                    // so that an entry for the fixture itself takes precedence
+                    let fixture_label = fixture.label.clone();
+                    let group_entries = scene.state.iter().filter(|(label, _)| {
+                        groups.iter().any(|g| {
+                            g.label.eq_ignore_ascii_case(label) && g.contains(&fixture_label)
+                        })
+                    });
+                    let fixture_entries = scene
+                        .state
+                        .iter()
+                        .filter(|(label, _)| label.eq_ignore_ascii_case(&fixture_label));
+
+                    for (label_in_scene, scene_state) in group_entries.chain(fixture_entries) {
+                        debug!(
+                            "Scene has match for fixture {} == {}",
+                            &fixture.label, label_in_scene
+                        );
+                        apply_scene_state(fixture, scene_state, palettes, animation_ms);
+                    }
+                }
+                self.apply_macros = true;
+            }
+            None => {
+                error!("Failed to find scene with index {}", scene_index);
+            }
+        }
+    }
+
+    /// Apply a Palette to the given fixtures and/or groups (or all fixtures, if None).
+    /// Macros set this way remember the Palette, so that saving a Scene stores a
+    /// reference to it rather than the raw values.
+    pub fn apply_palette(
+        &mut self,
+        palette_label: &str,
+        animation_ms: Option<u64>,
+        fixture_labels: Option<Vec<String>>,
+    ) {
+        let Some(palette) = self.project.find_palette(palette_label) else {
+            error!("Failed to find palette \"{}\"", palette_label);
+            return;
+        };
+        debug!("Apply palette \"{}\"", &palette.label);
+        let palette_refs: SceneState = palette
+            .values
+            .keys()
+            .map(|macro_label| {
+                (
+                    macro_label.clone(),
+                    SceneValue::Palette(palette.label.clone()),
+                )
+            })
+            .collect();
+
+        let target_labels =
+            fixture_labels.map(|labels| self.project.resolve_fixture_labels(&labels));
+        let palettes = &self.project.palettes;
+        for fixture in self.project.fixtures.iter_mut() {
+            if fixtures_list_contains(&target_labels, &fixture.label) {
+                apply_scene_state(fixture, &palette_refs, palettes, animation_ms);
+            }
+        }
+        self.apply_macros = true;
+    }
+
+    /// Carry out a scheduled or timeline action
+    pub fn run_action(&mut self, action: ScheduleAction) {
+        match action {
+            ScheduleAction::GoScene {
+                scene,
+                ms,
+                fixture_labels,
+            } => self.handle_scene_message(RemoteSceneMessage {
+                scene_label: scene,
+                ms,
+                fixture_labels,
+            }),
+            ScheduleAction::Blackout { ms } => self.blackout(ms),
+        }
+    }
+
+    /// Take every intensity macro to zero and every colour macro to black,
+    /// immediately or over the given time
+    pub fn blackout(&mut self, animation_ms: Option<u64>) {
+        let palettes = &self.project.palettes;
+        for fixture in self.project.fixtures.iter_mut() {
+            let dark: SceneState = fixture
+                .config
+                .active_mode
+                .macros
+                .iter()
+                .filter_map(|m| match m {
+                    FixtureMacro::Control(c)
+                        if INTENSITY_MACROS
+                            .iter()
+                            .any(|l| c.label.eq_ignore_ascii_case(l)) =>
+                    {
+                        Some((c.label.clone(), SceneValue::ControlValue(0)))
+                    }
+                    FixtureMacro::Colour(c) => {
+                        Some((c.label.clone(), SceneValue::ColourValue(Color32::BLACK)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            apply_scene_state(fixture, &dark, palettes, animation_ms);
+        }
+        self.apply_macros = true;
+    }
+
+    /// Reload the current project from disk, keeping current macro values and
+    /// running animations for fixtures and scenes which still exist. If the
+    /// new version fails to load or validate, the current project is kept.
+    pub fn reload_project(&mut self) {
+        let Some(path) = self.current_project_path.clone() else {
+            warn!("No project file loaded; nothing to reload");
+            return;
+        };
+
+        let mut new_project = match Project::load(&path) {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Reload failed; keeping current project: {}", e);
+                return;
+            }
+        };
+
+        let report = new_project.validate();
+        for warning in report.warnings.iter() {
+            warn!("Reloaded project: {}", warning);
+        }
+        if !report.is_valid() {
+            for e in report.errors.iter() {
+                error!("Reloaded project: {}", e);
+            }
+            error!("Reload rejected; keeping current project");
+            return;
+        }
+
+        let changes = self.project.describe_changes(&new_project);
+        if changes.is_empty() {
+            info!("Reloaded project \"{}\"; no changes", &path);
+        } else {
+            info!(
+                "Reloaded project \"{}\" with {} change(s):",
+                &path,
+                changes.len()
+            );
+            for change in changes.iter() {
+                info!("  {}", change);
+            }
+        }
+
+        // Fixtures which are new or have been moved/changed should start from
+        // their home values; all other channels are left as they are
+        let changed_fixtures: Vec<_> = new_project
+            .fixtures
+            .iter()
+            .filter(|f| {
+                !self.project.fixtures.iter().any(|old| {
+                    old.label == f.label
+                        && old.config_name.eq_ignore_ascii_case(&f.config_name)
+                        && old.mode_index == f.mode_index
+                        && old.start_channel == f.start_channel
+                })
+            })
+            .cloned()
+            .collect();
+
+        let artnet_config_changed = self.project.artnet_config != new_project.artnet_config
+            || self.project.artnet_port_address != new_project.artnet_port_address;
+
+        new_project.carry_over_state(&mut self.project);
+        self.project = new_project;
+        let previously_assigned = std::mem::take(&mut self.channels_assigned);
+        self.patch_changed();
+
+        // Channels left behind by fixtures which were removed or moved would
+        // otherwise keep sending their last values
+        for (channel, (was, is)) in previously_assigned
+            .iter()
+            .zip(self.channels_assigned.iter())
+            .enumerate()
+            .skip(1)
+        {
+            if *was && !*is {
+                if let Some(c) = self.channels_state.get_mut(channel - 1) {
+                    *c = 0;
+                }
+                if let Some(s) = self.channel_sources.get_mut(channel - 1) {
+                    *s = ValueSource::None;
+                }
+            }
+        }
+
+        for fixture in changed_fixtures.iter() {
+            for m in fixture.config.active_mode.mappings.iter() {
+                if let Some(home) = m.home {
+                    let channel_index = (m.channel - 1) + (fixture.start_channel - 1);
+                    if let Some(c) = self.channels_state.get_mut(channel_index as usize) {
+                        *c = home;
+                    }
+                    if let Some(s) = self.channel_sources.get_mut(channel_index as usize) {
+                        *s = ValueSource::Home;
+                    }
+                }
+            }
+        }
+
+        if artnet_config_changed {
+            info!("ArtNet config changed; reconnecting ArtNet interface");
+            match get_artnet_interface(&self.settings, &self.project) {
+                Ok(artnet) => {
+                    self.artnet = Some(
+                        artnet
+                            .with_clock(self.clock.clone())
+                            .with_send_policy(self.settings.send_policy()),
+                    )
+                }
+                Err(e) => error!("Failed to reconnect ArtNet; keeping existing: {}", e),
+            }
+        }
+    }
+
+    pub fn apply_home_values(&mut self) {
+        debug!("Apply home values");
+        debug!("Before: {:?}", self.channels_state);
+
+        self.channels_state = [0].repeat(CHANNELS_PER_UNIVERSE as usize); // init zeroes
+        self.set_all_channel_sources(ValueSource::Home);
+
+        let fixtures_clone = self.project.fixtures.clone();
+        for fixture in fixtures_clone.iter() {
+            let current_mode = &fixture.config.active_mode;
+            for m in &current_mode.mappings {
+                if let Some(default_value) = m.home {
+                    let channel_index = (m.channel - 1) + (fixture.start_channel - 1);
+                    self.channels_state[channel_index as usize] = default_value;
+                }
+            }
+        }
+        debug!("After: {:?}", self.channels_state);
+    }
+
+    /// Save the project to the given path (keeping backups of any existing
+    /// file), which then becomes the current project path
+    pub fn save_project_as(&mut self, path: &str) -> anyhow::Result<()> {
+        Project::save_with_backup(path, &self.project, self.settings.project_backups)?;
+        self.current_project_path = Some(String::from(path));
+        if let Some(autosave) = &mut self.autosave {
+            autosave.reset(path, &self.project);
+        }
+        Ok(())
+    }
+
+    /// Call after the project has been edited directly, e.g. to add fixtures
+    pub fn project_changed(&mut self) {
+        self.patch_changed();
+        self.apply_macros = true;
+    }
+
+    /// Switch to a different project, starting from its home values
+    pub fn replace_project(&mut self, project: Project) {
+        self.project = project;
+        self.patch_changed();
+        self.apply_home_values();
+    }
+
+    /// The fixtures may have been added, removed, moved or changed
+    fn patch_changed(&mut self) {
+        self.channels_assigned = assigned_channels(&self.project.fixtures);
+        if let Some(artnet) = &mut self.artnet {
+            artnet.invalidate_plan();
+        }
+    }
+
+    pub fn connect_tether(&mut self) {
+        match self.tether_interface.connect(
+            self.should_quit.clone(),
+            self.settings.tether_host.as_deref(),
+            self.settings.tether_port.as_ref(),
+            self.settings.tether_user.as_deref(),
+            self.settings.tether_pass.as_deref(),
+            self.settings.tether_subscribe_id.as_deref(),
+        ) {
+            Ok(_) => {
+                self.tether_status = TetherStatus::Connected;
+            }
+            Err(e) => {
+                self.tether_status = TetherStatus::Errored(format!("Error: {e}"));
+            }
+        }
+    }
+
+    pub fn reset_before_quit(&mut self) {
+        *self.should_quit.lock().unwrap() = true;
+        if self.save_on_exit {
+            info!("Save-on-exit enabled; will save current project if loaded...");
+            if let Some(existing_project_path) = self.current_project_path.clone() {
+                match self.save_project_as(&existing_project_path) {
+                    Ok(_) => info!("...Saved current project \"{}\" OK", &existing_project_path),
+                    Err(e) => error!("...Something went wrong saving: {}", e),
+                }
+            } else {
+                warn!("...No project was loaded; nothing saved")
+            }
+        }
+        match self.exit_mode {
+            BehaviourOnExit::DoNothing => {
+                warn!("Exit behaviour explicitly set to do Nothing; will just quit")
+            }
+            BehaviourOnExit::Home => {
+                info!("Exit Behaviour: All fixtures Go Home");
+                self.apply_macros = false;
+                self.apply_home_values();
+                self.update();
+            }
+            BehaviourOnExit::Zero => {
+                info!("Exit Behaviour: All fixtures Go Zero");
+                self.apply_macros = false;
+                zero(&mut self.channels_state);
+                self.set_all_channel_sources(ValueSource::Slider);
+                self.update();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(500));
+        info!("...reset before quit done");
+    }
+}
+
+/// Apply the macro values from a single Scene entry to a fixture, either
+/// immediately or animated over the given duration. Palette references are
+/// looked up in the given list.
+fn apply_scene_state(
+    fixture: &mut FixtureInstance,
+    scene_state: &SceneState,
+    palettes: &[Palette],
+    animation_ms: Option<u64>,
+) {
+    for m in fixture.config.active_mode.macros.iter_mut() {
+        match m {
+            FixtureMacro::Control(control_macro_in_fixture) => {
+                if let Some((macro_in_scene, palette_label)) =
+                    resolve_scene_value(scene_state, &control_macro_in_fixture.label, palettes)
+                {
+                    match macro_in_scene {
+                        SceneValue::ControlValue(control_macro_in_scene) => {
+                            control_macro_in_fixture.last_source = ValueSource::Scene;
+                            control_macro_in_fixture.palette =
+                                palette_label.map(|p| (String::from(p), *control_macro_in_scene));
+                            debug!(
+                                "With fixture {}, Scene sets control macro {} to {}",
+                                &fixture.label,
+                                &control_macro_in_fixture.label,
+                                control_macro_in_scene
+                            );
+                            if let Some(ms) = animation_ms {
+                                debug!(
+                                    "Scene includes animation; animate Control Value over {}ms",
+                                    ms
+                                );
+                                control_macro_in_fixture.animation = Some(Animation::new(
+                                    Duration::from_millis(ms),
+                                    control_macro_in_fixture.current_value as f32 / u16::MAX as f32,
+                                    *control_macro_in_scene as f32 / u16::MAX as f32,
+                                    Box::new(QuadInOut),
+                                ))
+                            } else {
+                                debug!("No Animation specified; change Control Value immediately");
+                                control_macro_in_fixture.current_value = *control_macro_in_scene;
+                            }
+                        }
+                        SceneValue::ColourValue(_)
+                        | SceneValue::PositionValue(_)
+                        | SceneValue::TemperatureValue(_)
+                        | SceneValue::Palette(_) => {
+                            debug!("This is Control Macro for fixture; Colour/Position Macro from scene will not apply");
+                        }
+                    }
+                }
+            }
+            FixtureMacro::Colour(colour_macro_in_fixture) => {
+                if let Some((macro_in_scene, palette_label)) =
+                    resolve_scene_value(scene_state, &colour_macro_in_fixture.label, palettes)
+                {
+                    // A temperature renders as its equivalent colour
+                    let colour_in_scene = match macro_in_scene {
+                        SceneValue::ColourValue(c) => Some(*c),
+                        SceneValue::TemperatureValue(t) => Some(t.to_rgb()),
+                        _ => None,
+                    };
+                    match &colour_in_scene {
+                        None => {
+                            debug!("This is Colour Macro for fixture; Control/Position Macro from scene will not apply");
+                        }
+                        Some(colour_macro_in_scene) => {
+                            colour_macro_in_fixture.last_source = ValueSource::Scene;
+                            colour_macro_in_fixture.palette =
+                                palette_label.map(|p| (String::from(p), *colour_macro_in_scene));
+                            debug!(
+                                "With fixture {}, Scene sets colour macro {} to {:?}",
+                                &fixture.label,
+                                &colour_macro_in_fixture.label,
+                                colour_macro_in_scene
+                            );
+                            if let Some(ms) = animation_ms {
+                                debug!("Scene includes animation; animate Colour over {}ms", ms);
+                                let animation = Animation::new(
+                                    Duration::from_millis(ms),
+                                    0.0,
+                                    1.0,
+                                    Box::new(QuadInOut),
+                                );
+                                let start_colour = colour_macro_in_fixture.current_value;
+                                let end_colour = *colour_macro_in_scene;
+                                colour_macro_in_fixture.animation =
+                                    Some((animation, start_colour, end_colour));
+                            } else {
+                                debug!("No Animation specified; change Colour immediately");
+                                colour_macro_in_fixture.current_value = *colour_macro_in_scene;
+                            }
+                        }
+                    }
+                }
+            }
+            FixtureMacro::Position(position_macro_in_fixture) => {
+                let [mut pan, mut tilt] = position_macro_in_fixture.current_value;
+                let mut is_set = false;
+                let mut from_palette = None;
+                // Scenes saved with separate pan/tilt Control Macros
+                if let Some(SceneValue::ControlValue(v)) = scene_state.get(LEGACY_PAN) {
+                    pan = *v;
+                    is_set = true;
+                }
+                if let Some(SceneValue::ControlValue(v)) = scene_state.get(LEGACY_TILT) {
+                    tilt = *v;
+                    is_set = true;
+                }
+                if let Some((SceneValue::PositionValue(v), palette_label)) =
+                    resolve_scene_value(scene_state, &position_macro_in_fixture.label, palettes)
+                {
+                    [pan, tilt] = *v;
+                    is_set = true;
+                    from_palette = palette_label;
+                }
+                if is_set {
+                    debug!(
+                        "With fixture {}, Scene sets position macro {} to {:?}",
+                        &fixture.label,
+                        &position_macro_in_fixture.label,
+                        [pan, tilt]
+                    );
+                    position_macro_in_fixture.last_source = ValueSource::Scene;
+                    position_macro_in_fixture.palette =
+                        from_palette.map(|p| (String::from(p), [pan, tilt]));
+                    go_to_position(position_macro_in_fixture, [pan, tilt], animation_ms);
+                }
+            }
+            FixtureMacro::Temperature(temperature_macro_in_fixture) => {
+                if let Some((macro_in_scene, palette_label)) =
+                    resolve_scene_value(scene_state, &temperature_macro_in_fixture.label, palettes)
+                {
+                    // A colour renders as its nearest temperature
+                    let temperature_in_scene = match macro_in_scene {
+                        SceneValue::TemperatureValue(t) => Some(*t),
+                        SceneValue::ColourValue(c) => Temperature::from_rgb(*c),
+                        _ => None,
+                    };
+                    match temperature_in_scene {
+                        Some(target) => {
+                            debug!(
+                                "With fixture {}, Scene sets temperature macro {} to {:?}",
+                                &fixture.label, &temperature_macro_in_fixture.label, target
+                            );
+                            temperature_macro_in_fixture.last_source = ValueSource::Scene;
+                            temperature_macro_in_fixture.palette =
+                                palette_label.map(|p| (String::from(p), target));
+                            go_to_temperature(temperature_macro_in_fixture, target, animation_ms);
+                        }
+                        None => {
+                            debug!("This is Temperature Macro for fixture; Control/Position Macro from scene will not apply");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Set a Position Macro immediately, or animate towards the target if a duration is given
+fn go_to_position(position_macro: &mut PositionMacro, target: [u16; 2], animation_ms: Option<u64>) {
+    match animation_ms {
+        Some(ms) => {
+            debug!("Animate Position over {}ms", ms);
+            let animation =
+                Animation::new(Duration::from_millis(ms), 0.0, 1.0, Box::new(QuadInOut));
+            position_macro.animation = Some((animation, position_macro.current_value, target));
+        }
+        None => {
+            debug!("No Animation specified; change Position immediately");
+            position_macro.animation = None;
+            position_macro.current_value = target;
+        }
+    }
+}
+
+/// Set a Temperature Macro immediately, or animate towards the target if a duration is given
+fn go_to_temperature(
+    temperature_macro: &mut TemperatureMacro,
+    target: Temperature,
+    animation_ms: Option<u64>,
+) {
+    match animation_ms {
+        Some(ms) => {
+            debug!("Animate Temperature over {}ms", ms);
+            let animation =
+                Animation::new(Duration::from_millis(ms), 0.0, 1.0, Box::new(QuadInOut));
+            temperature_macro.animation =
+                Some((animation, temperature_macro.current_value, target));
+        }
+        None => {
+            debug!("No Animation specified; change Temperature immediately");
+            temperature_macro.animation = None;
+            temperature_macro.current_value = target;
+        }
+    }
+}
+
+/// MIDI controllers address a fixture's macros in order, with a Position Macro
+/// taking two (pan, then tilt) as the separate macros it replaced did. Gives
+/// the macro, and which of its values (0 unless a Position Macro) to set.
+fn macro_for_controller(
+    macros: &mut [FixtureMacro],
+    offset: usize,
+) -> Option<(&mut FixtureMacro, usize)> {
+    let mut first = 0;
+    for m in macros.iter_mut() {
+        let width = if matches!(m, FixtureMacro::Position(_)) {
+            2
+        } else {
+            1
+        };
+        if offset < first + width {
+            return Some((m, offset - first));
+        }
+        first += width;
+    }
+    None
+}
+
+/// Which (one-indexed) DMX channels are used by any of the given fixtures
+fn assigned_channels(fixtures: &[FixtureInstance]) -> Vec<bool> {
+    let mut channels_assigned: Vec<bool> = [false].repeat(CHANNELS_PER_UNIVERSE as usize);
+    for fixture in fixtures.iter() {
+        for m in &fixture.config.active_mode.mappings {
+            let channel_index = m.channel + fixture.start_channel - 1;
+            if let Some(assigned) = channels_assigned.get_mut(channel_index as usize) {
+                *assigned = true;
+            }
+        }
+    }
+    channels_assigned
+}
+
+/// For spreading ("fanning") Control Values across an ordered list of fixtures:
+/// the first fixture gets `-spread / 2`, the last gets `+spread / 2`
+fn spread_offset(ordered_labels: &[String], fixture_label: &str, spread: Option<f32>) -> f32 {
+    let Some(spread) = spread else {
+        return 0.;
+    };
+    if ordered_labels.len() < 2 {
+        return 0.;
+    }
+    match ordered_labels
+        .iter()
+        .position(|l| l.eq_ignore_ascii_case(fixture_label))
+    {
+        Some(index) => {
+            let position = index as f32 / (ordered_labels.len() - 1) as f32;
+            spread * (position - 0.5)
+        }
+        None => 0.,
+    }
+}
+
+fn fixtures_list_contains(search_list: &Option<Vec<String>>, label_search_string: &str) -> bool {
+    if let Some(list) = search_list {
+        for label in list.iter() {
+            if label.eq_ignore_ascii_case(label_search_string) {
+                return true;
+            }
+        }
+        false
+    } else {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::{
+        artnet::{ArtNetMode, PortAddress},
+        clock::ManualClock,
+        project::{
+            curve::{OutputCurve, OutputLimits},
+            park::ParkTarget,
+        },
+        settings::DEFAULT_ARTNET_HERTZ,
+    };
+
+    /// The example project, sending to a loopback port nobody listens on,
+    /// with time moved on only by the test
+    fn test_engine() -> (Engine, Arc<ManualClock>) {
+        let config = EngineConfig {
+            tether_disable_autoconnect: true,
+            project_autosave_secs: 0,
+            ..Default::default()
+        };
+        let mut project =
+            Project::load(concat!(env!("CARGO_MANIFEST_DIR"), "/example.project.json")).unwrap();
+        project.artnet_config = None;
+        let mut engine = Engine::with_project(config, project, None);
+
+        let clock = Arc::new(ManualClock::new());
+        let shared: SharedClock = clock.clone();
+        let artnet = ArtNetInterface::new(
+            ArtNetMode::Unicast(
+                SocketAddr::from(([127, 0, 0, 1], 0)),
+                vec![SocketAddr::from(([127, 0, 0, 1], 9))],
+            ),
+            DEFAULT_ARTNET_HERTZ,
+            PortAddress::default(),
+        )
+        .unwrap();
+        engine.artnet = Some(artnet.with_clock(shared.clone()));
+        engine.clock = shared;
+        (engine, clock)
+    }
+
+    fn sent(engine: &Engine, channel: usize) -> u8 {
+        engine.artnet.as_ref().unwrap().get_state()[channel - 1]
+    }
+
+    #[test]
+    fn output_limits_are_applied_once() {
+        let (mut engine, clock) = test_engine();
+        // "Hex Right" starts at channel 1; channel 23 is not used by any macro
+        let fixture = engine
+            .project
+            .fixtures
+            .iter_mut()
+            .find(|f| f.label == "Hex Right")
+            .unwrap();
+        fixture.output_overrides.insert(
+            23,
+            OutputLimits {
+                curve: Some(OutputCurve::SquareLaw),
+                ..Default::default()
+            },
+        );
+        engine.channels_state[22] = 128;
+        engine.apply_macros = true;
+
+        for _ in 0..5 {
+            clock.advance_ms(100);
+            engine.update();
+            assert_eq!(sent(&engine, 23), 64);
+            assert_eq!(engine.channels_state[22], 128);
+        }
+    }
+
+    #[test]
+    fn released_park_goes_back_to_live_value() {
+        let (mut engine, clock) = test_engine();
+        engine.channels_state[22] = 50;
+        engine.apply_macros = true;
+        engine
+            .project
+            .set_park(ParkTarget::Channel { channel: 23 }, Some(200));
+
+        for _ in 0..3 {
+            clock.advance_ms(100);
+            engine.update();
+            assert_eq!(sent(&engine, 23), 200);
+            assert_eq!(engine.channels_state[22], 50);
+        }
+
+        engine
+            .project
+            .set_park(ParkTarget::Channel { channel: 23 }, None);
+        clock.advance_ms(100);
+        engine.update();
+        assert_eq!(sent(&engine, 23), 50);
+    }
+
+    #[test]
+    fn parked_channels_follow_the_patch() {
+        let (mut engine, _) = test_engine();
+        engine.project.set_park(
+            ParkTarget::Attribute {
+                fixture_label: String::from("Hex Right"),
+                attribute: String::from("Zoom"),
+            },
+            Some(10),
+        );
+        assert_eq!(engine.project.parked_channels(), &[(21, 10)]);
+
+        let fixture = engine
+            .project
+            .fixtures
+            .iter_mut()
+            .find(|f| f.label == "Hex Right")
+            .unwrap();
+        fixture.start_channel = 101;
+        assert_eq!(engine.project.parked_channels(), &[(121, 10)]);
+
+        engine.project.parks[0].value = 20;
+        assert_eq!(engine.project.parked_channels(), &[(121, 20)]);
+    }
+
+    #[test]
+    fn safety_can_only_be_disabled_remotely_if_allowed() {
+        let (mut engine, _) = test_engine();
+        engine.project.safety = None;
+        engine.handle_safety_message(RemoteSafetyMessage { enabled: false });
+        assert!(engine.project.safety.is_none());
+
+        engine.handle_safety_message(RemoteSafetyMessage { enabled: true });
+        assert_eq!(engine.project.safety, Some(SafetyConfig::default()));
+        engine.handle_safety_message(RemoteSafetyMessage { enabled: false });
+        assert!(engine.project.safety.as_ref().unwrap().enabled);
+
+        engine
+            .project
+            .safety
+            .as_mut()
+            .unwrap()
+            .allow_remote_override = true;
+        engine.handle_safety_message(RemoteSafetyMessage { enabled: false });
+        assert!(!engine.project.safety.as_ref().unwrap().enabled);
+        engine.handle_safety_message(RemoteSafetyMessage { enabled: true });
+        assert!(engine.project.safety.as_ref().unwrap().enabled);
+    }
+
+    fn hero_left(engine: &Engine) -> &[FixtureMacro] {
+        &engine
+            .project
+            .fixtures
+            .iter()
+            .find(|f| f.label == "Hero Left")
+            .unwrap()
+            .config
+            .active_mode
+            .macros
+    }
+
+    fn hero_left_position(engine: &Engine) -> [u16; 2] {
+        hero_left(engine)
+            .iter()
+            .find_map(|m| match m {
+                FixtureMacro::Position(p) => Some(p.current_value),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn midi_reaches_pan_and_tilt_in_their_old_places() {
+        let (mut engine, _) = test_engine();
+        // "Hero Left" is the first fixture; its knobs go brightness 0, pan 1,
+        // tilt 2, zoom 3, as they did when pan and tilt were separate macros
+        let knob = |engine: &Engine, label: &str| {
+            hero_left(engine).iter().find_map(|m| match m {
+                FixtureMacro::Control(c) if c.label == label => c.midi_knob_index,
+                FixtureMacro::Position(p) if p.label == label => p.midi_knob_index,
+                _ => None,
+            })
+        };
+        assert_eq!(knob(&engine, "brightness"), Some(0));
+        assert_eq!(knob(&engine, "position"), Some(1));
+        assert_eq!(knob(&engine, "zoom"), Some(3));
+
+        let [pan, _] = hero_left_position(&engine);
+        engine.handle_midi_message(TetherMidiMessage::Knob(TetherKnobPayload {
+            index: 2,
+            position: 1.0,
+        }));
+        assert_eq!(hero_left_position(&engine), [pan, u16::MAX]);
+        engine.handle_midi_message(TetherMidiMessage::Knob(TetherKnobPayload {
+            index: 1,
+            position: 0.0,
+        }));
+        assert_eq!(hero_left_position(&engine), [0, u16::MAX]);
+
+        // Controllers count macros the same way, so pan and tilt take two
+        // and zoom (next after position) is still where it was
+        let pan_offset = hero_left(&engine)
+            .iter()
+            .position(|m| matches!(m, FixtureMacro::Position(_)))
+            .unwrap() as u8;
+        let controller = engine.project.midi_config.controller_start + pan_offset;
+        engine.selected_macro_group_index = 0;
+        for (controller, value) in [
+            (controller, 127),
+            (controller + 1, 0),
+            (controller + 2, 127),
+        ] {
+            engine.handle_midi_message(TetherMidiMessage::ControlChange(
+                TetherControlChangePayload {
+                    channel: 0,
+                    controller,
+                    value,
+                },
+            ));
+        }
+        assert_eq!(hero_left_position(&engine), [u16::MAX, 0]);
+        let zoom = hero_left(&engine).iter().find_map(|m| match m {
+            FixtureMacro::Control(c) if c.label == "zoom" => Some(c.current_value),
+            _ => None,
+        });
+        assert_eq!(zoom, Some(u16::MAX));
+    }
+
+    #[test]
+    fn output_plan_follows_edits_once_told() {
+        let (mut engine, clock) = test_engine();
+        let planned = |engine: &Engine| engine.artnet.as_ref().unwrap().output_plan().macros.len();
+        clock.advance_ms(100);
+        engine.update();
+        let before = planned(&engine);
+
+        engine.project.fixtures.pop();
+        clock.advance_ms(100);
+        engine.update();
+        assert_eq!(planned(&engine), before);
+
+        engine.project_changed();
+        clock.advance_ms(100);
+        engine.update();
+        assert!(planned(&engine) < before);
+    }
+
+    #[test]
+    fn reload_zeroes_channels_of_removed_fixtures() {
+        let (mut engine, _) = test_engine();
+        let dir = std::env::temp_dir().join(format!("reload-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("reload.project.json");
+        let path = path.to_str().unwrap();
+
+        // "Hex Right" uses channels 1-27; "Hero Left" starts at 28
+        engine.channels_state[0] = 77;
+        engine.channels_state[27] = 88;
+        let mut edited = engine.project.clone();
+        edited.fixtures.retain(|f| f.label != "Hex Right");
+        Project::save_with_backup(path, &edited, 0).unwrap();
+        engine.current_project_path = Some(String::from(path));
+
+        engine.reload_project();
+        assert!(!engine
+            .project
+            .fixtures
+            .iter()
+            .any(|f| f.label == "Hex Right"));
+        assert!(engine.channels_state[..27].iter().all(|c| *c == 0));
+        assert_eq!(engine.channels_state[27], 88);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use log::debug;
+
+use crate::project::Project;
+
+/// Maximum number of undo steps kept in memory
+const MAX_HISTORY: usize = 100;
+
+struct HistoryEntry {
+    label: String,
+    project: Project,
+    /// The project path to go back to, for steps which change it (e.g. "New project")
+    project_path: Option<Option<String>>,
+}
+
+/// Undo/redo history for project mutations, stored as whole-project snapshots
+/// (which include current macro values, but not running animations).
+///
+/// Snapshots must be recorded _before_ the change they describe.
+#[derive(Default)]
+pub struct History {
+    undo_stack: Vec<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    /// Key of the interaction currently in progress (e.g. dragging a slider),
+    /// so that every change it makes is grouped into a single undo step
+    current_group: Option<String>,
+}
+
+impl History {
+    /// Record a snapshot for a single, discrete change
+    pub fn record(&mut self, label: &str, project_before: &Project) {
+        self.current_group = None;
+        self.push(label, project_before, None);
+    }
+
+    /// Record a snapshot for a change which also changes the current project
+    /// path, so that undoing it goes back to the old path too
+    pub fn record_with_path(
+        &mut self,
+        label: &str,
+        project_before: &Project,
+        project_path_before: Option<&str>,
+    ) {
+        self.current_group = None;
+        self.push(
+            label,
+            project_before,
+            Some(project_path_before.map(String::from)),
+        );
+    }
+
+    /// Record a snapshot only if this is the first change with this key since
+    /// the last call to [`History::end_group`], e.g. the start of a slider drag
+    pub fn record_grouped(&mut self, label: &str, project_before: &Project) {
+        if self.current_group.as_deref() == Some(label) {
+            return;
+        }
+        self.current_group = Some(String::from(label));
+        self.push(label, project_before, None);
+    }
+
+    /// True unless a grouped change with this key is already in progress, i.e.
+    /// if [`History::record_grouped`] would record a new snapshot
+    pub fn starts_group(&self, label: &str) -> bool {
+        self.current_group.as_deref() != Some(label)
+    }
+
+    /// The current interaction (if any) is finished; the next grouped change
+    /// will be a new undo step even if it has the same key
+    pub fn end_group(&mut self) {
+        self.current_group = None;
+    }
+
+    fn push(
+        &mut self,
+        label: &str,
+        project_before: &Project,
+        project_path: Option<Option<String>>,
+    ) {
+        debug!("Record undo step \"{}\"", label);
+        self.undo_stack.push(HistoryEntry {
+            label: String::from(label),
+            project: project_before.clone(),
+            project_path,
+        });
+        if self.undo_stack.len() > MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Restore the most recent snapshot (and project path, if that step changed
+    /// it), returning the label of the step undone
+    pub fn undo(
+        &mut self,
+        current: &mut Project,
+        current_path: &mut Option<String>,
+    ) -> Option<String> {
+        let entry = self.undo_stack.pop()?;
+        self.current_group = None;
+        let redo = swap(entry, current, current_path);
+        let label = redo.label.clone();
+        self.redo_stack.push(redo);
+        Some(label)
+    }
+
+    /// Re-apply the most recently undone step, returning its label
+    pub fn redo(
+        &mut self,
+        current: &mut Project,
+        current_path: &mut Option<String>,
+    ) -> Option<String> {
+        let entry = self.redo_stack.pop()?;
+        self.current_group = None;
+        let undo = swap(entry, current, current_path);
+        let label = undo.label.clone();
+        self.undo_stack.push(undo);
+        Some(label)
+    }
+
+    pub fn undo_label(&self) -> Option<&str> {
+        self.undo_stack.last().map(|e| e.label.as_str())
+    }
+
+    pub fn redo_label(&self) -> Option<&str> {
+        self.redo_stack.last().map(|e| e.label.as_str())
+    }
+}
+
+/// Put a snapshot in place, returning the entry which takes it back again
+fn swap(
+    entry: HistoryEntry,
+    current: &mut Project,
+    current_path: &mut Option<String>,
+) -> HistoryEntry {
+    HistoryEntry {
+        project: std::mem::replace(current, entry.project),
+        project_path: entry
+            .project_path
+            .map(|path| std::mem::replace(current_path, path)),
+        label: entry.label,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undoing_new_project_restores_its_path() {
+        let mut history = History::default();
+        let mut project = Project::new();
+        let mut path = Some(String::from("show.json"));
+
+        history.record_with_path("New project", &project, path.as_deref());
+        project = Project::new();
+        path = None;
+        history.record("Add group", &project);
+
+        // Only the step which changed it puts the path back
+        history.undo(&mut project, &mut path);
+        assert_eq!(path, None);
+        history.undo(&mut project, &mut path);
+        assert_eq!(path.as_deref(), Some("show.json"));
+
+        history.redo(&mut project, &mut path);
+        assert_eq!(path, None);
+        history.undo(&mut project, &mut path);
+        assert_eq!(path.as_deref(), Some("show.json"));
+    }
+
+    #[test]
+    fn grouped_changes_are_one_step() {
+        let mut history = History::default();
+        let project = Project::new();
+        assert!(history.starts_group("Adjust"));
+        history.record_grouped("Adjust", &project);
+        assert!(!history.starts_group("Adjust"));
+        history.record_grouped("Adjust", &project);
+        history.end_group();
+        assert!(history.starts_group("Adjust"));
+        assert_eq!(history.undo_stack.len(), 1);
+    }
+}
@@ -0,0 +1,24 @@
+//! The lighting engine behind Tether ArtNet Controller: projects and fixture
+//! configurations, macros, scenes, palettes, schedules and timelines, remote
+//! control via Tether, and ArtNet output.
+//!
+//! [`engine::Engine`] runs everything without a GUI; the egui application
+//! ([`model::Model`]) is only built with the `gui` feature (on by default).
+
+pub mod animation;
+pub mod artnet;
+pub mod clock;
+pub mod colour;
+pub mod engine;
+pub mod history;
+#[cfg(feature = "gui")]
+pub mod model;
+pub mod output_plan;
+pub mod project;
+pub mod receiver;
+pub mod recording;
+pub mod settings;
+pub mod tether_interface;
+pub mod timecode;
+#[cfg(feature = "gui")]
+pub mod ui;
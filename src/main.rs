@@ -1,19 +1,17 @@
+mod commands;
+
 use std::{sync::mpsc, time::Duration};
 
 use env_logger::Env;
-use log::{debug, info};
+use log::{debug, error, info, warn};
 
 use clap::Parser;
 
-use crate::{model::Model, settings::Cli, ui::NARROW_WINDOW};
-
-mod animation;
-mod artnet;
-mod model;
-pub mod project;
-mod settings;
-mod tether_interface;
-mod ui;
+use tether_artnet_controller::{
+    engine::Engine,
+    project::autosave::newer_autosave,
+    settings::{Cli, Command, EngineConfig, FixturesCommand, SceneCommand},
+};
 
 fn main() {
     let cli = Cli::parse();
@@ -26,50 +24,169 @@ fn main() {
         .init();
 
     debug!("Started with settings: {:?}", cli);
+    let config = engine_config(&cli);
+
+    let result = match cli.command {
+        None => {
+            run(config);
+            Ok(())
+        }
+        Some(Command::Run { project_path }) => {
+            let mut config = config;
+            if let Some(path) = project_path {
+                config.project_path = path;
+            }
+            run(config);
+            Ok(())
+        }
+        Some(Command::Validate { project_path }) => commands::validate(&project_path),
+        Some(Command::Fixtures { command }) => match command {
+            FixturesCommand::List => {
+                commands::list_fixtures();
+                Ok(())
+            }
+            FixturesCommand::Show { name } => commands::show_fixture(&name),
+        },
+        Some(Command::Patch { project_path }) => commands::print_patch(&project_path),
+        Some(Command::Scene { command }) => match command {
+            SceneCommand::Go {
+                label,
+                ms,
+                fixture_labels,
+            } => commands::scene_go(&config, &label, ms, fixture_labels),
+        },
+        Some(Command::Blackout { project_path }) => {
+            commands::blackout(&config, project_path.as_deref())
+        }
+        Some(Command::Schedule {
+            project_path,
+            count,
+        }) => commands::print_schedule(&project_path, count),
+        Some(Command::Replay {
+            recording_path,
+            looping,
+            speed,
+            from_secs,
+        }) => commands::replay(&config, &recording_path, looping, speed, from_secs),
+        Some(Command::Monitor {
+            port,
+            gui,
+            all,
+            count,
+        }) => commands::monitor(port, gui, all, count),
+        Some(Command::ExportCsv {
+            recording_path,
+            output_path,
+        }) => commands::export_csv(&recording_path, output_path.as_deref()),
+    };
+
+    if let Err(e) = result {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
 
-    let mut model = Model::new(cli.clone());
+/// The command-line options which the Engine (and GUI) use
+fn engine_config(cli: &Cli) -> EngineConfig {
+    EngineConfig {
+        project_path: cli.project_path.clone(),
+        headless_mode: cli.headless_mode,
+        project_disable_watch: cli.project_disable_watch,
+        project_autosave_secs: cli.project_autosave_secs,
+        project_backups: cli.project_backups,
+        artnet_broadcast: cli.artnet_broadcast,
+        artnet_universe: cli.artnet_universe,
+        unicast_src: cli.unicast_src,
+        unicast_dst: cli.unicast_dst.clone(),
+        artnet_update_frequency: cli.artnet_update_frequency,
+        artnet_on_change: cli.artnet_on_change,
+        artnet_keep_alive: cli.artnet_keep_alive,
+        artnet_sync: cli.artnet_sync,
+        artnet_timecode: cli.artnet_timecode,
+        record_path: cli.record_path.clone(),
+        auto_zero: cli.auto_zero,
+        auto_random: cli.auto_random,
+        tether_disable_autoconnect: cli.tether_disable_autoconnect,
+        tether_subscribe_id: cli.tether_subscribe_id.clone(),
+        tether_host: cli.tether_host.clone(),
+        tether_port: cli.tether_port,
+        tether_user: cli.tether_user.clone(),
+        tether_pass: cli.tether_pass.clone(),
+    }
+}
 
-    if cli.artnet_broadcast && (cli.unicast_src.is_some() || cli.unicast_dst.is_some()) {
+fn run(config: EngineConfig) {
+    if config.artnet_broadcast && (config.unicast_src.is_some() || !config.unicast_dst.is_empty()) {
         panic!("You cannot enabled Broadcast mode AND set Unicast details at the same time");
     }
 
-    if cli.headless_mode {
-        info!("Running in headless mode; Ctrl+C to quit");
-        let mut should_quit = false;
-        let (quit_cli_tx, quit_cli_rx) = mpsc::channel();
-
-        ctrlc::set_handler(move || {
-            quit_cli_tx
-                .send(())
-                .expect("failed to send quit message via channel");
-        })
-        .expect("failed to set Ctrl+C handler");
-        std::thread::sleep(Duration::from_secs(2));
-        while !should_quit {
-            if quit_cli_rx.try_recv().is_ok() {
-                info!("Headless loop should quit");
-                should_quit = true;
-            }
-            std::thread::sleep(Duration::from_millis(1));
-            model.update();
+    if config.headless_mode || cfg!(not(feature = "gui")) {
+        if !config.headless_mode {
+            warn!("Built without the GUI; running in headless mode");
         }
+        run_headless(config);
     } else {
-        info!("Running graphics mode; close the window to quit");
-        let options = eframe::NativeOptions {
-            viewport: egui::ViewportBuilder::default().with_inner_size(NARROW_WINDOW),
-            run_and_return: true,
-            ..Default::default()
-        };
-        eframe::run_native(
-            "Tether ArtNet Controller",
-            options,
-            Box::new(|_cc| Box::<Model>::new(model)),
-        )
-        .expect("Failed to launch GUI");
-        info!("GUI ended; exit soon...");
+        #[cfg(feature = "gui")]
+        run_gui(config);
     }
 
     std::thread::sleep(Duration::from_secs(1));
     info!("...Exit now");
     std::process::exit(0);
 }
+
+fn run_headless(config: EngineConfig) {
+    let mut engine = Engine::new(config);
+    if let Some(path) = engine
+        .current_project_path
+        .as_deref()
+        .and_then(newer_autosave)
+    {
+        warn!(
+            "Autosave \"{}\" is newer than the project; load it manually to recover",
+            path
+        );
+    }
+
+    info!("Running in headless mode; Ctrl+C to quit");
+    let mut should_quit = false;
+    let (quit_cli_tx, quit_cli_rx) = mpsc::channel();
+
+    ctrlc::set_handler(move || {
+        quit_cli_tx
+            .send(())
+            .expect("failed to send quit message via channel");
+    })
+    .expect("failed to set Ctrl+C handler");
+    std::thread::sleep(Duration::from_secs(2));
+    while !should_quit {
+        if quit_cli_rx.try_recv().is_ok() {
+            info!("Headless loop should quit");
+            should_quit = true;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+        engine.update();
+    }
+    // Finish any recording now, since process::exit skips destructors
+    engine.recorder = None;
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(config: EngineConfig) {
+    use tether_artnet_controller::{model::Model, ui::NARROW_WINDOW};
+
+    let model = Model::new(config);
+    info!("Running graphics mode; close the window to quit");
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size(NARROW_WINDOW),
+        run_and_return: true,
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Tether ArtNet Controller",
+        options,
+        Box::new(|_cc| Box::<Model>::new(model)),
+    )
+    .expect("Failed to launch GUI");
+    info!("GUI ended; exit soon...");
+}
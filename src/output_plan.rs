@@ -0,0 +1,524 @@
+use ecolor::Color32;
+use log::*;
+
+use crate::{
+    colour::{mix_emitters, Emitter, EmitterCalibration, Temperature},
+    project::curve::OutputLimits,
+    project::fixture::{ChannelList, ChannelWithResolution, FixtureInstance, FixtureMacro},
+    settings::CHANNELS_PER_UNIVERSE,
+};
+
+/// A channel written by a macro, as a zero-based index into the universe
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlannedChannel {
+    LoRes(usize),
+    HiRes(usize, usize),
+}
+
+/// Where a colour (or colour temperature) macro writes to
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedColour {
+    /// 8-bit emitters, with the channels for each
+    Additive {
+        emitters: Vec<Emitter>,
+        channels: Vec<Vec<usize>>,
+    },
+    Subtractive {
+        cyan: Vec<usize>,
+        magenta: Vec<usize>,
+        yellow: Vec<usize>,
+    },
+    /// Coarse and fine channels for red, green and blue
+    HiResRGB([(usize, usize); 3]),
+    WarmCool {
+        warm: Vec<usize>,
+        cool: Vec<usize>,
+        warm_kelvin: u16,
+        cool_kelvin: u16,
+    },
+    Cct {
+        temperature: PlannedChannel,
+        tint: Option<PlannedChannel>,
+        min_kelvin: u16,
+        max_kelvin: u16,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlannedWrites {
+    Control(Vec<PlannedChannel>),
+    Position {
+        pan: PlannedChannel,
+        tilt: PlannedChannel,
+    },
+    /// Used by both colour and colour temperature macros
+    Colour(PlannedColour),
+}
+
+/// One fixture macro, with its channels already resolved; the value itself is
+/// read from the macro each frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedMacro {
+    pub fixture_index: usize,
+    pub macro_index: usize,
+    pub writes: PlannedWrites,
+}
+
+/// The patch compiled into a flat list of absolute channel writes, so that
+/// each frame only has to look up macro values and write them out. Compiled
+/// once and then only rebuilt after [`OutputPlan::invalidate`], which must be
+/// called whenever the patch changes (fixtures added, removed, moved or
+/// switched mode, or output limits edited).
+#[derive(Default, Clone, Debug)]
+pub struct OutputPlan {
+    compiled: bool,
+    pub macros: Vec<PlannedMacro>,
+    /// Output curves and clamps, per (zero-based) channel
+    pub limits: Vec<(usize, OutputLimits)>,
+}
+
+impl OutputPlan {
+    pub fn compile(fixtures: &[FixtureInstance]) -> Self {
+        let mut macros = Vec::new();
+        let mut limits = Vec::new();
+
+        for (fixture_index, fixture) in fixtures.iter().enumerate() {
+            for (macro_index, m) in fixture.config.active_mode.macros.iter().enumerate() {
+                match compile_macro(fixture.start_channel, m) {
+                    Some(writes) => macros.push(PlannedMacro {
+                        fixture_index,
+                        macro_index,
+                        writes,
+                    }),
+                    None => warn!(
+                        "Fixture \"{}\" at channel {} has a macro outside the universe; it will not be output",
+                        fixture.label, fixture.start_channel
+                    ),
+                }
+            }
+            for (channel, channel_limits) in fixture.output_limits() {
+                if let Some(index) = absolute(fixture.start_channel, channel) {
+                    limits.push((index, channel_limits));
+                }
+            }
+        }
+
+        debug!(
+            "Compiled output plan: {} macros, {} limited channels for {} fixtures",
+            macros.len(),
+            limits.len(),
+            fixtures.len()
+        );
+
+        OutputPlan {
+            compiled: true,
+            macros,
+            limits,
+        }
+    }
+
+    /// The patch has changed; compile again before the next frame
+    pub fn invalidate(&mut self) {
+        self.compiled = false;
+    }
+
+    /// Compile if not yet done since the last invalidate(); true if it was
+    pub fn refresh(&mut self, fixtures: &[FixtureInstance]) -> bool {
+        if self.compiled {
+            return false;
+        }
+        *self = OutputPlan::compile(fixtures);
+        true
+    }
+
+    /// Write the current value of every macro to the universe
+    pub fn write_macros(&self, channels: &mut [u8], fixtures: &[FixtureInstance]) {
+        for planned in &self.macros {
+            let Some(fixture) = fixtures.get(planned.fixture_index) else {
+                continue;
+            };
+            let Some(m) = fixture.config.active_mode.macros.get(planned.macro_index) else {
+                continue;
+            };
+            match (&planned.writes, m) {
+                (PlannedWrites::Control(targets), FixtureMacro::Control(control_macro)) => {
+                    for target in targets {
+                        write_value(channels, target, control_macro.current_value);
+                    }
+                }
+                (PlannedWrites::Position { pan, tilt }, FixtureMacro::Position(position_macro)) => {
+                    let [pan_value, tilt_value] =
+                        fixture.output_position(position_macro.current_value);
+                    write_value(channels, pan, pan_value);
+                    write_value(channels, tilt, tilt_value);
+                }
+                (PlannedWrites::Colour(colour), FixtureMacro::Colour(colour_macro)) => {
+                    write_colour(
+                        channels,
+                        colour,
+                        colour_macro.current_value,
+                        None,
+                        fixture.calibration.as_ref(),
+                    );
+                }
+                (PlannedWrites::Colour(colour), FixtureMacro::Temperature(temperature_macro)) => {
+                    let temperature = temperature_macro.current_value;
+                    write_colour(
+                        channels,
+                        colour,
+                        temperature.to_rgb(),
+                        Some(temperature),
+                        fixture.calibration.as_ref(),
+                    );
+                }
+                _ => {
+                    // The patch changed since compiling, without invalidating the plan
+                }
+            }
+        }
+    }
+
+    /// The final output stage: apply every fixture's curves and clamps to the
+    /// universe, after macros, sliders and remote values have all been written
+    pub fn apply_output_limits(&self, channels: &mut [u8]) {
+        for (index, limits) in &self.limits {
+            if let Some(value) = channels.get_mut(*index) {
+                *value = limits.apply(*value);
+            }
+        }
+    }
+}
+
+/// Zero-based universe index of a (one-indexed, fixture-relative) channel,
+/// if it is inside the universe
+fn absolute(start_channel: u16, channel: u16) -> Option<usize> {
+    (channel as usize + start_channel as usize)
+        .checked_sub(2)
+        .filter(|index| *index < CHANNELS_PER_UNIVERSE as usize)
+}
+
+fn absolute_all(start_channel: u16, channels: &[u16]) -> Option<Vec<usize>> {
+    channels
+        .iter()
+        .map(|c| absolute(start_channel, *c))
+        .collect()
+}
+
+fn absolute_with_resolution(
+    start_channel: u16,
+    c: &ChannelWithResolution,
+) -> Option<PlannedChannel> {
+    Some(match c {
+        ChannelWithResolution::LoRes(single) => {
+            PlannedChannel::LoRes(absolute(start_channel, *single)?)
+        }
+        ChannelWithResolution::HiRes((c1, c2)) => {
+            PlannedChannel::HiRes(absolute(start_channel, *c1)?, absolute(start_channel, *c2)?)
+        }
+    })
+}
+
+/// None if any of the macro's channels are outside the universe
+fn compile_macro(start_channel: u16, m: &FixtureMacro) -> Option<PlannedWrites> {
+    Some(match m {
+        FixtureMacro::Control(control_macro) => PlannedWrites::Control(
+            control_macro
+                .channels
+                .iter()
+                .map(|c| absolute_with_resolution(start_channel, c))
+                .collect::<Option<_>>()?,
+        ),
+        FixtureMacro::Position(position_macro) => PlannedWrites::Position {
+            pan: absolute_with_resolution(start_channel, &position_macro.pan)?,
+            tilt: absolute_with_resolution(start_channel, &position_macro.tilt)?,
+        },
+        FixtureMacro::Colour(colour_macro) => {
+            PlannedWrites::Colour(compile_colour(start_channel, &colour_macro.channels)?)
+        }
+        FixtureMacro::Temperature(temperature_macro) => {
+            PlannedWrites::Colour(compile_colour(start_channel, &temperature_macro.channels)?)
+        }
+    })
+}
+
+fn compile_colour(start_channel: u16, list: &ChannelList) -> Option<PlannedColour> {
+    if let Some(mut emitters) = list.emitter_channels() {
+        emitters.retain(|(e, _)| e.is_mixed());
+        let mut channels = Vec::with_capacity(emitters.len());
+        for (_, fixture_channels) in emitters.iter() {
+            channels.push(absolute_all(start_channel, fixture_channels)?);
+        }
+        return Some(PlannedColour::Additive {
+            emitters: emitters.iter().map(|(e, _)| *e).collect(),
+            channels,
+        });
+    }
+
+    Some(match list {
+        ChannelList::Subtractive(cmy) => PlannedColour::Subtractive {
+            cyan: absolute_all(start_channel, &cmy.cyan)?,
+            magenta: absolute_all(start_channel, &cmy.magenta)?,
+            yellow: absolute_all(start_channel, &cmy.yellow)?,
+        },
+        ChannelList::AdditiveRGB16(rgb16) => {
+            let pair = |(c1, c2): (u16, u16)| {
+                Some((absolute(start_channel, c1)?, absolute(start_channel, c2)?))
+            };
+            PlannedColour::HiResRGB([pair(rgb16.red)?, pair(rgb16.green)?, pair(rgb16.blue)?])
+        }
+        ChannelList::WarmCool(warm_cool) => PlannedColour::WarmCool {
+            warm: absolute_all(start_channel, &warm_cool.warm)?,
+            cool: absolute_all(start_channel, &warm_cool.cool)?,
+            warm_kelvin: warm_cool.warm_kelvin,
+            cool_kelvin: warm_cool.cool_kelvin,
+        },
+        ChannelList::Cct(cct) => PlannedColour::Cct {
+            temperature: absolute_with_resolution(start_channel, &cct.temperature)?,
+            tint: match &cct.tint {
+                Some(tint) => Some(absolute_with_resolution(start_channel, tint)?),
+                None => None,
+            },
+            min_kelvin: cct.min_kelvin,
+            max_kelvin: cct.max_kelvin,
+        },
+        ChannelList::AdditiveRGBW8(_)
+        | ChannelList::AdditiveRGBL8(_)
+        | ChannelList::AdditiveRGBWA8(_)
+        | ChannelList::AdditiveRGBWAUV8(_)
+        | ChannelList::AdditiveRGBAL8(_) => {
+            unreachable!("8-bit additive channels are compiled as emitters, above")
+        }
+    })
+}
+
+fn set(channels: &mut [u8], indices: &[usize], value: u8) {
+    for index in indices {
+        if let Some(c) = channels.get_mut(*index) {
+            *c = value;
+        }
+    }
+}
+
+/// Write a colour to whichever kind of colour channels a fixture has. Additive
+/// fixtures get emitter levels mixed to match the colour, CMY fixtures get its
+/// inverse; warm/cool and native CCT fixtures get the given
+/// temperature, or the nearest temperature to the colour if none is given.
+fn write_colour(
+    channels: &mut [u8],
+    planned: &PlannedColour,
+    colour: Color32,
+    temperature: Option<Temperature>,
+    calibration: Option<&EmitterCalibration>,
+) {
+    // Convert all rgb values from "opaque" version (ignoring alpha)
+    let opaque = colour.to_opaque();
+
+    match planned {
+        PlannedColour::Additive {
+            emitters,
+            channels: emitter_channels,
+        } => {
+            let levels = mix_emitters(colour, emitters, calibration);
+            for (emitter, indices) in emitters.iter().zip(emitter_channels) {
+                set(channels, indices, levels.dmx_value(*emitter));
+            }
+        }
+        PlannedColour::Subtractive {
+            cyan,
+            magenta,
+            yellow,
+        } => {
+            let levels = mix_emitters(opaque, &[], calibration);
+            set(channels, cyan, 255 - levels.dmx_value(Emitter::Red));
+            set(channels, magenta, 255 - levels.dmx_value(Emitter::Green));
+            set(channels, yellow, 255 - levels.dmx_value(Emitter::Blue));
+        }
+        PlannedColour::HiResRGB(rgb16) => {
+            let levels = mix_emitters(opaque, &[], calibration);
+            for ((c1, c2), level) in rgb16.iter().zip([levels.red, levels.green, levels.blue]) {
+                // Assume coarse+fine 16-bit values are "big endian" (be):
+                let [b1, b2] = ((level.clamp(0., 1.) * u16::MAX as f32) as u16).to_be_bytes();
+                set(channels, &[*c1], b1);
+                set(channels, &[*c2], b2);
+            }
+        }
+        PlannedColour::WarmCool {
+            warm,
+            cool,
+            warm_kelvin,
+            cool_kelvin,
+        } => {
+            // A colour also sets the level, the way it would on an RGB fixture
+            let (temperature, level) = match temperature {
+                Some(t) => (t, 1.0),
+                None => (
+                    Temperature::from_rgb(opaque).unwrap_or_default(),
+                    opaque.r().max(opaque.g()).max(opaque.b()) as f32 / 255.,
+                ),
+            };
+            let (warm_level, cool_level) = temperature.warm_cool_levels(*warm_kelvin, *cool_kelvin);
+            set(channels, warm, (warm_level * level * 255.) as u8);
+            set(channels, cool, (cool_level * level * 255.) as u8);
+        }
+        PlannedColour::Cct {
+            temperature: temperature_channel,
+            tint,
+            min_kelvin,
+            max_kelvin,
+        } => {
+            let temperature =
+                temperature.unwrap_or_else(|| Temperature::from_rgb(opaque).unwrap_or_default());
+            let fraction = temperature.fraction_of_range(*min_kelvin, *max_kelvin);
+            write_value(
+                channels,
+                temperature_channel,
+                (fraction * u16::MAX as f32) as u16,
+            );
+            if let Some(tint) = tint {
+                let fraction = (temperature.tint + 1.) / 2.;
+                write_value(channels, tint, (fraction * u16::MAX as f32) as u16);
+            }
+        }
+    }
+}
+
+/// Write a 16-bit macro value to a channel (scaled down if LoRes)
+fn write_value(channels: &mut [u8], target: &PlannedChannel, value: u16) {
+    match target {
+        PlannedChannel::LoRes(index) => {
+            let scaled_value = ((value as f32 / u16::MAX as f32) * 255.0) as u8;
+            trace!(
+                "Apply LoRes macro value to channel index {}, value {} => {}",
+                index,
+                value,
+                scaled_value
+            );
+            set(channels, &[*index], scaled_value);
+        }
+        PlannedChannel::HiRes(coarse, fine) => {
+            // Assume coarse+fine 16-bit values are "big endian" (be):
+            let [b1, b2] = value.to_be_bytes();
+            set(channels, &[*coarse], b1);
+            set(channels, &[*fine], b2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::Project;
+
+    fn example_fixtures() -> Vec<FixtureInstance> {
+        Project::load(concat!(env!("CARGO_MANIFEST_DIR"), "/example.project.json"))
+            .unwrap()
+            .fixtures
+    }
+
+    #[test]
+    fn control_channels_are_absolute() {
+        let fixtures = example_fixtures();
+        let plan = OutputPlan::compile(&fixtures);
+        let mut checked = 0;
+        for planned in &plan.macros {
+            let fixture = &fixtures[planned.fixture_index];
+            let (PlannedWrites::Control(targets), FixtureMacro::Control(control_macro)) = (
+                &planned.writes,
+                &fixture.config.active_mode.macros[planned.macro_index],
+            ) else {
+                continue;
+            };
+            for (target, c) in targets.iter().zip(&control_macro.channels) {
+                let expected = match c {
+                    ChannelWithResolution::LoRes(c) => {
+                        PlannedChannel::LoRes((*c - 1 + fixture.start_channel - 1) as usize)
+                    }
+                    ChannelWithResolution::HiRes((c1, c2)) => PlannedChannel::HiRes(
+                        (*c1 - 1 + fixture.start_channel - 1) as usize,
+                        (*c2 - 1 + fixture.start_channel - 1) as usize,
+                    ),
+                };
+                assert_eq!(*target, expected);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn macros_outside_the_universe_are_skipped() {
+        let mut fixtures = example_fixtures();
+        let complete = OutputPlan::compile(&fixtures).macros.len();
+        fixtures[0].start_channel = 510;
+        fixtures[1].start_channel = 0;
+        let plan = OutputPlan::compile(&fixtures);
+        assert!(plan.macros.len() < complete);
+
+        let mut channels = vec![0; CHANNELS_PER_UNIVERSE as usize];
+        plan.write_macros(&mut channels, &fixtures);
+        plan.apply_output_limits(&mut channels);
+    }
+
+    #[test]
+    fn uv_is_left_to_its_own_control() {
+        let mut fixtures = example_fixtures();
+        let hex = fixtures.iter().position(|f| f.label == "Hex Left").unwrap();
+        let start = fixtures[hex].start_channel as usize;
+        let index = |channel: usize| start + channel - 2;
+        for m in fixtures[hex].config.active_mode.macros.iter_mut() {
+            match m {
+                FixtureMacro::Colour(c) => c.current_value = Color32::from_rgb(128, 0, 255),
+                FixtureMacro::Control(c) if c.label == "uv" => c.current_value = u16::MAX,
+                _ => {}
+            }
+        }
+
+        let plan = OutputPlan::compile(&fixtures);
+        let emitters = plan
+            .macros
+            .iter()
+            .find_map(|m| match &m.writes {
+                PlannedWrites::Colour(PlannedColour::Additive { emitters, .. })
+                    if m.fixture_index == hex =>
+                {
+                    Some(emitters.clone())
+                }
+                _ => None,
+            })
+            .unwrap();
+        use Emitter::*;
+        assert_eq!(emitters, vec![Red, Green, Blue, White, Amber]);
+
+        let mut channels = vec![0; CHANNELS_PER_UNIVERSE as usize];
+        plan.write_macros(&mut channels, &fixtures);
+        // Red, green, blue, white and amber, then UV, for the centre and outer LEDs
+        for first in [5, 11] {
+            assert_eq!(
+                channels[index(first)..=index(first + 5)],
+                [128, 0, 255, 0, 0, 255]
+            );
+        }
+    }
+
+    #[test]
+    fn rebuilt_only_when_invalidated() {
+        let mut fixtures = example_fixtures();
+        let mut plan = OutputPlan::default();
+        assert!(plan.refresh(&fixtures));
+        assert!(!plan.refresh(&fixtures));
+
+        if let Some(FixtureMacro::Control(control_macro)) =
+            fixtures[0].config.active_mode.macros.first_mut()
+        {
+            control_macro.current_value = u16::MAX;
+        }
+        assert!(!plan.refresh(&fixtures));
+
+        let macro_count = plan.macros.len();
+        fixtures.pop();
+        plan.invalidate();
+        assert!(plan.refresh(&fixtures));
+        assert!(plan.macros.len() < macro_count);
+        assert!(!plan.refresh(&fixtures));
+    }
+}
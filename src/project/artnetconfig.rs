@@ -5,11 +5,12 @@ use std::{
 };
 
 use log::{debug, error, info, warn};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    artnet::{ArtNetInterface, ArtNetMode},
-    settings::Cli,
+    artnet::{ArtNetInterface, ArtNetMode, PortAddress},
+    receiver::ARTNET_PORT,
+    settings::{EngineConfig, DEFAULT_PORT_ADDRESS},
 };
 
 use super::Project;
@@ -17,30 +18,90 @@ use super::Project;
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum ArtNetConfigMode {
     Broadcast,
-    Unicast(String, String),
+    /// Interface IP, and the destination IP(s); a single destination is
+    /// stored as a plain string, as in older project files
+    Unicast(
+        String,
+        #[serde(
+            serialize_with = "serialize_destinations",
+            deserialize_with = "deserialize_destinations"
+        )]
+        Vec<String>,
+    ),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn serialize_destinations<S: Serializer>(
+    destinations: &[String],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match destinations {
+        [one] => OneOrMany::One(one.clone()),
+        many => OneOrMany::Many(many.to_vec()),
+    }
+    .serialize(serializer)
+}
+
+fn deserialize_destinations<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// Destination IPs, at the ArtNet port
+pub fn destination_addresses<T: AsRef<str>>(ips: &[T]) -> anyhow::Result<Vec<SocketAddr>> {
+    ips.iter()
+        .map(|ip| {
+            Ipv4Addr::from_str(ip.as_ref().trim())
+                .map(|ip| SocketAddr::from((ip, ARTNET_PORT)))
+                .map_err(|_| anyhow!("Invalid destination IP address \"{}\"", ip.as_ref()))
+        })
+        .collect()
+}
+
+/// The Port-Address from the config if given, otherwise the project's
+pub fn output_port_address(config: &EngineConfig, project: &Project) -> PortAddress {
+    config
+        .artnet_universe
+        .or(project.artnet_port_address)
+        .unwrap_or(DEFAULT_PORT_ADDRESS)
 }
 
 pub fn get_artnet_interface(
-    cli: &Cli,
+    config: &EngineConfig,
     project: &Project,
 ) -> Result<ArtNetInterface, anyhow::Error> {
     debug!("get_artnet_interface");
-    if cli.artnet_broadcast {
+    let port_address = output_port_address(config, project);
+    if config.artnet_broadcast {
         warn!("CLI artnetBroadcast flag overrides any Project ArtNet settings");
         ArtNetInterface::new(
             ArtNetMode::Broadcast,
-            cli.artnet_update_frequency,
-            cli.artnet_universe,
+            config.artnet_update_frequency,
+            port_address,
         )
-    } else if cli.unicast_src.is_some() && cli.unicast_dst.is_some() {
+    } else if config.unicast_src.is_some() && !config.unicast_dst.is_empty() {
         warn!("CLI unicastSrc + unicastDst options override any Project ArtNet settings");
         ArtNetInterface::new(
             ArtNetMode::Unicast(
-                SocketAddr::from((cli.unicast_src.unwrap(), 6453)),
-                SocketAddr::from((cli.unicast_dst.unwrap(), 6454)),
+                SocketAddr::from((config.unicast_src.unwrap(), 6453)),
+                config
+                    .unicast_dst
+                    .iter()
+                    .map(|ip| SocketAddr::from((*ip, ARTNET_PORT)))
+                    .collect(),
             ),
-            cli.artnet_update_frequency,
-            cli.artnet_universe,
+            config.artnet_update_frequency,
+            port_address,
         )
     } else {
         debug!("No CLI overrides, attempt to use Project ArtNet config...");
@@ -50,20 +111,17 @@ pub fn get_artnet_interface(
                 match artnet_mode {
                     ArtNetConfigMode::Broadcast => ArtNetInterface::new(
                         ArtNetMode::Broadcast,
-                        cli.artnet_update_frequency,
-                        cli.artnet_universe,
+                        config.artnet_update_frequency,
+                        port_address,
                     ),
-                    ArtNetConfigMode::Unicast(interface_ip, destination_ip) => {
+                    ArtNetConfigMode::Unicast(interface_ip, destination_ips) => {
                         ArtNetInterface::new(
                             ArtNetMode::Unicast(
                                 SocketAddr::from((Ipv4Addr::from_str(interface_ip).unwrap(), 6453)),
-                                SocketAddr::from((
-                                    Ipv4Addr::from_str(destination_ip).unwrap(),
-                                    6454,
-                                )),
+                                destination_addresses(destination_ips)?,
                             ),
-                            cli.artnet_update_frequency,
-                            cli.artnet_universe,
+                            config.artnet_update_frequency,
+                            port_address,
                         )
                     }
                 }
@@ -82,9 +140,43 @@ impl From<&ArtNetInterface> for ArtNetConfigMode {
     fn from(value: &ArtNetInterface) -> Self {
         match value.mode_in_use() {
             ArtNetMode::Broadcast => ArtNetConfigMode::Broadcast,
-            ArtNetMode::Unicast(src, dst) => {
-                ArtNetConfigMode::Unicast(src.ip().to_string(), dst.ip().to_string())
-            }
+            ArtNetMode::Unicast(src, destinations) => ArtNetConfigMode::Unicast(
+                src.ip().to_string(),
+                destinations.iter().map(|d| d.ip().to_string()).collect(),
+            ),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_destination_is_a_plain_string() {
+        let old: ArtNetConfigMode =
+            serde_json::from_str(r#"{"Unicast":["10.0.0.100","10.0.0.99"]}"#).unwrap();
+        assert_eq!(
+            old,
+            ArtNetConfigMode::Unicast("10.0.0.100".into(), vec!["10.0.0.99".into()])
+        );
+        assert_eq!(
+            serde_json::to_string(&old).unwrap(),
+            r#"{"Unicast":["10.0.0.100","10.0.0.99"]}"#
+        );
+
+        let several = ArtNetConfigMode::Unicast(
+            "10.0.0.100".into(),
+            vec!["10.0.0.99".into(), "10.0.0.98".into()],
+        );
+        let json = serde_json::to_string(&several).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Unicast":["10.0.0.100",["10.0.0.99","10.0.0.98"]]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ArtNetConfigMode>(&json).unwrap(),
+            several
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+
+use super::{write_atomic, Project};
+
+const AUTOSAVE_SUFFIX: &str = ".autosave";
+
+/// Periodically saves the project to a sidecar file next to the real one,
+/// so that unsaved changes can be recovered after a crash.
+pub struct Autosave {
+    interval: Duration,
+    last_checked: Instant,
+    last_saved_json: Option<String>,
+}
+
+impl Autosave {
+    pub fn new(interval: Duration, project: &Project) -> Self {
+        Autosave {
+            interval,
+            last_checked: Instant::now(),
+            last_saved_json: project.to_json().ok(),
+        }
+    }
+
+    /// Save the project to the sidecar file if the interval has elapsed and
+    /// anything has changed since the last autosave.
+    pub fn update(&mut self, project_path: Option<&str>, project: &Project) {
+        if self.last_checked.elapsed() < self.interval {
+            return;
+        }
+        self.last_checked = Instant::now();
+
+        let Some(project_path) = project_path else {
+            debug!("No project path; nothing to autosave");
+            return;
+        };
+
+        let json = match project.to_json() {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Failed to serialise project for autosave: {}", e);
+                return;
+            }
+        };
+
+        if self.last_saved_json.as_ref() == Some(&json) {
+            return;
+        }
+
+        let path = autosave_path(project_path);
+        match write_atomic(&path, &json) {
+            Ok(()) => {
+                debug!("Autosaved project to \"{}\"", &path);
+                self.last_saved_json = Some(json);
+            }
+            Err(e) => error!("Autosave to \"{}\" failed: {}", &path, e),
+        }
+    }
+
+    /// Call after a real save, so that the saved state is the new starting point
+    pub fn reset(&mut self, project_path: &str, project: &Project) {
+        self.last_saved_json = project.to_json().ok();
+        remove_autosave(project_path);
+    }
+}
+
+pub fn autosave_path(project_path: &str) -> String {
+    format!("{}{}", project_path, AUTOSAVE_SUFFIX)
+}
+
+/// If there is an autosave for the given project which is newer than the
+/// project file itself, return its path
+pub fn newer_autosave(project_path: &str) -> Option<String> {
+    let path = autosave_path(project_path);
+    let autosave_modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+    let project_modified = fs::metadata(project_path).and_then(|m| m.modified()).ok();
+
+    match project_modified {
+        Some(project_modified) if project_modified >= autosave_modified => None,
+        _ => {
+            info!("Found autosave \"{}\" newer than project", &path);
+            Some(path)
+        }
+    }
+}
+
+pub fn remove_autosave(project_path: &str) {
+    let path = autosave_path(project_path);
+    if fs::metadata(&path).is_ok() {
+        match fs::remove_file(&path) {
+            Ok(()) => debug!("Removed autosave \"{}\"", &path),
+            Err(e) => warn!("Failed to remove autosave \"{}\": {}", &path, e),
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use super::fixture::FixtureInstance;
+
+/// How a channel's value maps onto what is actually sent, e.g. so that dimmers
+/// with different responses at low levels fade alike
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputCurve {
+    #[default]
+    Linear,
+    /// Output proportional to the square of the value; finer control near the bottom
+    SquareLaw,
+    /// Slow at both ends, fast in the middle (smoothstep)
+    SCurve,
+    /// Output values spread evenly across the input range, interpolated in between,
+    /// e.g. `[0, 10, 60, 255]`
+    Table(Vec<u8>),
+}
+
+impl OutputCurve {
+    pub fn apply(&self, value: u8) -> u8 {
+        let x = value as f32 / 255.;
+        let y = match self {
+            OutputCurve::Linear => return value,
+            OutputCurve::SquareLaw => x * x,
+            OutputCurve::SCurve => x * x * (3. - 2. * x),
+            OutputCurve::Table(points) => match points.len() {
+                0 => return value,
+                1 => return points[0],
+                n => {
+                    let position = x * (n - 1) as f32;
+                    let i = (position.floor() as usize).min(n - 2);
+                    let t = position - i as f32;
+                    (points[i] as f32 + t * (points[i + 1] as f32 - points[i] as f32)) / 255.
+                }
+            },
+        };
+        (y.clamp(0., 1.) * 255.).round() as u8
+    }
+}
+
+/// Output curve and clamps for a single channel. Set on a Mapping in the fixture
+/// config, and optionally overridden (field by field) per fixture in the project.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub curve: Option<OutputCurve>,
+    /// Lowest value ever sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u8>,
+    /// Highest value ever sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u8>,
+}
+
+impl OutputLimits {
+    pub fn is_empty(&self) -> bool {
+        self.curve.is_none() && self.min.is_none() && self.max.is_none()
+    }
+
+    /// These limits, with any fields set in `overrides` taking precedence
+    pub fn merged(&self, overrides: Option<&OutputLimits>) -> OutputLimits {
+        match overrides {
+            None => self.clone(),
+            Some(o) => OutputLimits {
+                curve: o.curve.clone().or_else(|| self.curve.clone()),
+                min: o.min.or(self.min),
+                max: o.max.or(self.max),
+            },
+        }
+    }
+
+    pub fn apply(&self, value: u8) -> u8 {
+        let value = match &self.curve {
+            Some(curve) => curve.apply(value),
+            None => value,
+        };
+        value
+            .max(self.min.unwrap_or(0))
+            .min(self.max.unwrap_or(u8::MAX))
+    }
+}
+
+impl FixtureInstance {
+    /// The (one-indexed, fixture-relative) channels which have output limits,
+    /// after applying this fixture's overrides
+    pub fn output_limits(&self) -> Vec<(u16, OutputLimits)> {
+        self.config
+            .active_mode
+            .mappings
+            .iter()
+            .map(|m| {
+                (
+                    m.channel,
+                    m.output.merged(self.output_overrides.get(&m.channel)),
+                )
+            })
+            .filter(|(_, limits)| !limits.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_keep_their_endpoints_and_never_go_down() {
+        for curve in [
+            OutputCurve::Linear,
+            OutputCurve::SquareLaw,
+            OutputCurve::SCurve,
+            OutputCurve::Table(vec![0, 10, 60, 255]),
+        ] {
+            assert_eq!(curve.apply(0), 0, "{:?}", curve);
+            assert_eq!(curve.apply(255), 255, "{:?}", curve);
+            for value in 0..255 {
+                assert!(
+                    curve.apply(value + 1) >= curve.apply(value),
+                    "{:?} goes down after {}",
+                    curve,
+                    value
+                );
+            }
+        }
+        assert_eq!(OutputCurve::Linear.apply(77), 77);
+        assert_eq!(OutputCurve::SquareLaw.apply(128), 64);
+        assert_eq!(OutputCurve::SCurve.apply(128), 128);
+        assert!(OutputCurve::SCurve.apply(32) < 32);
+        assert!(OutputCurve::SCurve.apply(224) > 224);
+    }
+
+    #[test]
+    fn table_interpolates_between_points() {
+        let table = OutputCurve::Table(vec![0, 10, 60, 255]);
+        assert_eq!(table.apply(85), 10);
+        assert_eq!(table.apply(170), 60);
+        assert_eq!(table.apply(42), 5);
+        assert_eq!(table.apply(128), 35);
+
+        assert_eq!(OutputCurve::Table(vec![]).apply(100), 100);
+        assert_eq!(OutputCurve::Table(vec![40]).apply(100), 40);
+        assert_eq!(OutputCurve::Table(vec![255, 0]).apply(255), 0);
+    }
+
+    #[test]
+    fn clamps_after_the_curve() {
+        let limits = OutputLimits {
+            curve: Some(OutputCurve::SquareLaw),
+            min: Some(10),
+            max: Some(200),
+        };
+        assert_eq!(limits.apply(0), 10);
+        assert_eq!(limits.apply(128), 64);
+        assert_eq!(limits.apply(255), 200);
+
+        // Such limits fail validation; if used anyway, max wins
+        let inverted = OutputLimits {
+            curve: None,
+            min: Some(200),
+            max: Some(100),
+        };
+        assert_eq!(inverted.apply(0), 100);
+        assert_eq!(inverted.apply(255), 100);
+    }
+
+    #[test]
+    fn overrides_replace_only_the_fields_they_set() {
+        let base = OutputLimits {
+            curve: Some(OutputCurve::SquareLaw),
+            min: None,
+            max: Some(230),
+        };
+        assert_eq!(base.merged(None), base);
+
+        let merged = base.merged(Some(&OutputLimits {
+            curve: None,
+            min: Some(8),
+            max: None,
+        }));
+        assert_eq!(merged.curve, Some(OutputCurve::SquareLaw));
+        assert_eq!(merged.min, Some(8));
+        assert_eq!(merged.max, Some(230));
+
+        let merged = base.merged(Some(&OutputLimits {
+            curve: Some(OutputCurve::SCurve),
+            min: None,
+            max: Some(255),
+        }));
+        assert_eq!(merged.curve, Some(OutputCurve::SCurve));
+        assert_eq!(merged.max, Some(255));
+
+        assert!(OutputLimits::default().merged(None).is_empty());
+    }
+}
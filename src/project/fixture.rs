@@ -1,9 +1,14 @@
 use std::cmp::Ordering;
 
-use egui::Color32;
+use ecolor::Color32;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use crate::animation::Animation;
+use crate::{
+    animation::Animation,
+    colour::{Emitter, EmitterCalibration, Temperature, MAX_KELVIN, MIN_KELVIN},
+    project::{curve::OutputLimits, scene::SceneValue},
+};
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +22,25 @@ pub struct FixtureInstance {
     pub start_channel: u16,
     #[serde(default)]
     pub mode_index: usize,
+    /// Position on the stage plot, normalised to `[0,1]` in both axes
+    /// (x left-to-right, y upstage-to-downstage); None if not yet placed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage_position: Option<[f32; 2]>,
+    /// Reverse the pan direction, e.g. for fixtures hung the other way round
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub invert_pan: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub invert_tilt: bool,
+    /// Send the pan value to the tilt channel(s) and vice versa
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub swap_pan_tilt: bool,
+    /// Corrections to colour mixing, so this fixture matches others
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<EmitterCalibration>,
+    /// Per-channel changes to the fixture config's output curves and clamps,
+    /// keyed by (one-indexed) fixture channel
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub output_overrides: IndexMap<u16, OutputLimits>,
     #[serde(skip)]
     /// The actual configuration, once loaded via the path
     pub config: FixtureConfig,
@@ -43,6 +67,36 @@ impl Ord for FixtureInstance {
     }
 }
 
+impl FixtureInstance {
+    /// The **one-indexed** first and last DMX channels occupied by this fixture
+    /// in its active mode, or None if the mode has no mappings at all
+    pub fn channel_range(&self) -> Option<(u16, u16)> {
+        let mappings = &self.config.active_mode.mappings;
+        let first = mappings.iter().map(|m| m.channel).min()?;
+        let last = mappings.iter().map(|m| m.channel).max()?;
+        Some((
+            first + self.start_channel - 1,
+            last + self.start_channel - 1,
+        ))
+    }
+
+    /// The (pan, tilt) values actually sent for a Position Macro value,
+    /// after applying this fixture's invert and swap settings
+    pub fn output_position(&self, [pan, tilt]: [u16; 2]) -> [u16; 2] {
+        let pan = if self.invert_pan { u16::MAX - pan } else { pan };
+        let tilt = if self.invert_tilt {
+            u16::MAX - tilt
+        } else {
+            tilt
+        };
+        if self.swap_pan_tilt {
+            [tilt, pan]
+        } else {
+            [pan, tilt]
+        }
+    }
+}
+
 impl From<&FixtureConfig> for FixtureInstance {
     fn from(config: &FixtureConfig) -> Self {
         FixtureInstance {
@@ -50,6 +104,12 @@ impl From<&FixtureConfig> for FixtureInstance {
             config_name: String::from(&config.name),
             start_channel: 0,
             mode_index: 0,
+            stage_position: None,
+            invert_pan: false,
+            invert_tilt: false,
+            swap_pan_tilt: false,
+            calibration: None,
+            output_overrides: IndexMap::new(),
             config: config.clone(),
         }
     }
@@ -83,6 +143,9 @@ pub struct Mapping {
     pub notes: Option<String>,
     pub home: Option<u8>,
     pub ranges: Option<Vec<RangeDescription>>,
+    /// Output curve and min/max clamps, e.g. for dimmer channels
+    #[serde(default, flatten)]
+    pub output: OutputLimits,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -91,6 +154,33 @@ pub enum ChannelWithResolution {
     HiRes((u16, u16)),
 }
 
+impl ChannelWithResolution {
+    pub fn channels(&self) -> Vec<u16> {
+        match self {
+            ChannelWithResolution::LoRes(c) => vec![*c],
+            ChannelWithResolution::HiRes((c1, c2)) => vec![*c1, *c2],
+        }
+    }
+}
+
+/// What last set a channel (or macro) value; shown in the output monitor
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum ValueSource {
+    #[default]
+    None,
+    /// Fixture home values (or All > HOME)
+    Home,
+    /// Channel sliders, or ZERO/RANDOM
+    Slider,
+    /// Macro controls in the GUI
+    Macro,
+    Scene,
+    /// Tether messages, including MIDI
+    Remote,
+    /// Held at a fixed value, overriding everything else
+    Parked,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ValueMacro {
     pub label: String,
@@ -101,6 +191,11 @@ pub struct ValueMacro {
     pub animation: Option<Animation>,
     #[serde(skip)]
     pub midi_knob_index: Option<usize>,
+    #[serde(skip)]
+    pub last_source: ValueSource,
+    /// The Palette last applied, and the value it gave
+    #[serde(skip)]
+    pub palette: Option<(String, u16)>,
 }
 
 // Cloning an Animation is tricky, and we don't need it anyway
@@ -112,6 +207,8 @@ impl Clone for ValueMacro {
             current_value: self.current_value,
             animation: None, // Just ignore
             midi_knob_index: self.midi_knob_index,
+            last_source: self.last_source,
+            palette: self.palette.clone(),
         }
     }
 }
@@ -136,6 +233,41 @@ pub struct GroupedRGBLChannels {
     pub lime: Vec<u16>,
 }
 
+/// These represent 8-bit channels which are controlled
+/// TOGETHER as a "single value" per colour channel.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GroupedRGBWAChannels {
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+    pub white: Vec<u16>,
+    pub amber: Vec<u16>,
+}
+
+/// These represent 8-bit channels which are controlled
+/// TOGETHER as a "single value" per colour channel.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GroupedRGBWAUVChannels {
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+    pub white: Vec<u16>,
+    pub amber: Vec<u16>,
+    /// Not set by colour mixing; give these channels a control macro of their own
+    pub uv: Vec<u16>,
+}
+
+/// These represent 8-bit channels which are controlled
+/// TOGETHER as a "single value" per colour channel.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GroupedRGBALChannels {
+    pub red: Vec<u16>,
+    pub green: Vec<u16>,
+    pub blue: Vec<u16>,
+    pub amber: Vec<u16>,
+    pub lime: Vec<u16>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GroupedCMYChannels {
     pub cyan: Vec<u16>,
@@ -155,13 +287,126 @@ pub struct HiResRGBChannels {
     pub blue: (u16, u16),
 }
 
+/// Pairs of warm and cool white emitters (e.g. bi-colour panels), mixed
+/// to reach a colour temperature in between
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupedWarmCoolChannels {
+    pub warm: Vec<u16>,
+    pub cool: Vec<u16>,
+    pub warm_kelvin: u16,
+    pub cool_kelvin: u16,
+}
+
+/// A native colour temperature channel, where the fixture does its own mixing
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CctChannels {
+    pub temperature: ChannelWithResolution,
+    /// Kelvin at the lowest and highest channel values
+    pub min_kelvin: u16,
+    pub max_kelvin: u16,
+    /// Green/magenta shift, with no shift at the middle of the range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tint: Option<ChannelWithResolution>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum ChannelList {
     AdditiveRGBW8(GroupedRGBWChannels),
     AdditiveRGBL8(GroupedRGBLChannels),
+    AdditiveRGBWA8(GroupedRGBWAChannels),
+    AdditiveRGBWAUV8(GroupedRGBWAUVChannels),
+    AdditiveRGBAL8(GroupedRGBALChannels),
     AdditiveRGB16(HiResRGBChannels),
     Subtractive(GroupedCMYChannels),
+    WarmCool(GroupedWarmCoolChannels),
+    Cct(CctChannels),
+}
+
+impl ChannelList {
+    /// The 8-bit additive emitters and the (one-indexed, fixture-relative)
+    /// channels for each, if this is such a list
+    pub fn emitter_channels(&self) -> Option<Vec<(Emitter, &Vec<u16>)>> {
+        use Emitter::*;
+        Some(match self {
+            ChannelList::AdditiveRGBW8(c) => vec![
+                (Red, &c.red),
+                (Green, &c.green),
+                (Blue, &c.blue),
+                (White, &c.white),
+            ],
+            ChannelList::AdditiveRGBL8(c) => vec![
+                (Red, &c.red),
+                (Green, &c.green),
+                (Blue, &c.blue),
+                (Lime, &c.lime),
+            ],
+            ChannelList::AdditiveRGBWA8(c) => vec![
+                (Red, &c.red),
+                (Green, &c.green),
+                (Blue, &c.blue),
+                (White, &c.white),
+                (Amber, &c.amber),
+            ],
+            ChannelList::AdditiveRGBWAUV8(c) => vec![
+                (Red, &c.red),
+                (Green, &c.green),
+                (Blue, &c.blue),
+                (White, &c.white),
+                (Amber, &c.amber),
+                (Uv, &c.uv),
+            ],
+            ChannelList::AdditiveRGBAL8(c) => vec![
+                (Red, &c.red),
+                (Green, &c.green),
+                (Blue, &c.blue),
+                (Amber, &c.amber),
+                (Lime, &c.lime),
+            ],
+            _ => return None,
+        })
+    }
+
+    /// All (one-indexed, fixture-relative) channels written for a colour
+    pub fn channels(&self) -> Vec<u16> {
+        if let Some(emitters) = self.emitter_channels() {
+            return emitters
+                .into_iter()
+                .filter(|(e, _)| e.is_mixed())
+                .flat_map(|(_, c)| c)
+                .copied()
+                .collect();
+        }
+        match self {
+            ChannelList::AdditiveRGB16(c) => {
+                vec![c.red.0, c.red.1, c.green.0, c.green.1, c.blue.0, c.blue.1]
+            }
+            ChannelList::Subtractive(c) => [&c.cyan, &c.magenta, &c.yellow]
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect(),
+            ChannelList::WarmCool(c) => c.warm.iter().chain(c.cool.iter()).copied().collect(),
+            ChannelList::Cct(c) => c
+                .temperature
+                .channels()
+                .into_iter()
+                .chain(c.tint.iter().flat_map(|t| t.channels()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The colour temperatures these channels can actually produce
+    pub fn kelvin_range(&self) -> (u16, u16) {
+        match self {
+            ChannelList::WarmCool(c) => (c.warm_kelvin, c.cool_kelvin),
+            ChannelList::Cct(c) => (c.min_kelvin, c.max_kelvin),
+            _ => (MIN_KELVIN, MAX_KELVIN),
+        }
+    }
 }
 
 fn default_rgb() -> Color32 {
@@ -176,6 +421,10 @@ pub struct ColourMacro {
     pub current_value: Color32,
     #[serde(skip)]
     pub animation: Option<(Animation, Color32, Color32)>,
+    #[serde(skip)]
+    pub last_source: ValueSource,
+    #[serde(skip)]
+    pub palette: Option<(String, Color32)>,
 }
 
 impl Clone for ColourMacro {
@@ -185,6 +434,88 @@ impl Clone for ColourMacro {
             channels: self.channels.clone(),
             current_value: self.current_value,
             animation: None,
+            last_source: self.last_source,
+            palette: self.palette.clone(),
+        }
+    }
+}
+
+/// Colour temperature (Kelvin plus tint), which also renders on RGB(W/L)
+/// and CMY fixtures via an equivalent colour
+#[derive(Serialize, Deserialize)]
+pub struct TemperatureMacro {
+    pub label: String,
+    pub channels: ChannelList,
+    #[serde(skip)]
+    pub current_value: Temperature,
+    #[serde(skip)]
+    pub animation: Option<(Animation, Temperature, Temperature)>,
+    #[serde(skip)]
+    pub last_source: ValueSource,
+    #[serde(skip)]
+    pub palette: Option<(String, Temperature)>,
+}
+
+impl Clone for TemperatureMacro {
+    fn clone(&self) -> Self {
+        Self {
+            label: self.label.clone(),
+            channels: self.channels.clone(),
+            current_value: self.current_value,
+            animation: None,
+            last_source: self.last_source,
+            palette: self.palette.clone(),
+        }
+    }
+}
+
+pub const LEGACY_PAN: &str = "pan";
+pub const LEGACY_TILT: &str = "tilt";
+
+fn default_position() -> [u16; 2] {
+    [u16::MAX / 2, u16::MAX / 2]
+}
+
+/// Pan and tilt, controlled together (e.g. via an XY pad)
+#[derive(Serialize, Deserialize)]
+pub struct PositionMacro {
+    pub label: String,
+    pub pan: ChannelWithResolution,
+    pub tilt: ChannelWithResolution,
+    /// Pan, tilt
+    #[serde(skip, default = "default_position")]
+    pub current_value: [u16; 2],
+    #[serde(skip)]
+    pub animation: Option<(Animation, [u16; 2], [u16; 2])>,
+    /// Knob for pan; tilt is on the next one, as when they were separate macros
+    #[serde(skip)]
+    pub midi_knob_index: Option<usize>,
+    #[serde(skip)]
+    pub last_source: ValueSource,
+    #[serde(skip)]
+    pub palette: Option<(String, [u16; 2])>,
+}
+
+impl Clone for PositionMacro {
+    fn clone(&self) -> Self {
+        Self {
+            label: self.label.clone(),
+            pan: self.pan.clone(),
+            tilt: self.tilt.clone(),
+            current_value: self.current_value,
+            animation: None,
+            midi_knob_index: self.midi_knob_index,
+            last_source: self.last_source,
+            palette: self.palette.clone(),
+        }
+    }
+}
+
+impl PositionMacro {
+    /// Move by the given (pan, tilt) offset, clamped to the full range
+    pub fn nudge(&mut self, delta: [i32; 2]) {
+        for (value, d) in self.current_value.iter_mut().zip(delta) {
+            *value = (*value as i32 + d).clamp(0, u16::MAX as i32) as u16;
         }
     }
 }
@@ -194,6 +525,95 @@ impl Clone for ColourMacro {
 pub enum FixtureMacro {
     Control(ValueMacro),
     Colour(ColourMacro),
+    Position(PositionMacro),
+    Temperature(TemperatureMacro),
+}
+
+impl FixtureMacro {
+    pub fn label(&self) -> &str {
+        match self {
+            FixtureMacro::Control(m) => &m.label,
+            FixtureMacro::Colour(m) => &m.label,
+            FixtureMacro::Position(m) => &m.label,
+            FixtureMacro::Temperature(m) => &m.label,
+        }
+    }
+
+    /// Position Macros also answer to the legacy "pan" and "tilt" labels,
+    /// so that scenes saved before these existed still apply
+    pub fn matches_label(&self, label: &str) -> bool {
+        self.label().eq_ignore_ascii_case(label)
+            || matches!(self, FixtureMacro::Position(_))
+                && (label.eq_ignore_ascii_case(LEGACY_PAN)
+                    || label.eq_ignore_ascii_case(LEGACY_TILT))
+    }
+
+    /// The current value, as stored in a Scene: a reference to the Palette
+    /// which set it, unless the value has since been changed
+    pub fn scene_value(&self) -> SceneValue {
+        match self.palette_label() {
+            Some(label) => SceneValue::Palette(String::from(label)),
+            None => match self {
+                FixtureMacro::Control(m) => SceneValue::ControlValue(m.current_value),
+                FixtureMacro::Colour(m) => SceneValue::ColourValue(m.current_value),
+                FixtureMacro::Position(m) => SceneValue::PositionValue(m.current_value),
+                FixtureMacro::Temperature(m) => SceneValue::TemperatureValue(m.current_value),
+            },
+        }
+    }
+
+    /// The Palette this macro's current value came from, if any
+    pub fn palette_label(&self) -> Option<&str> {
+        match self {
+            FixtureMacro::Control(m) => match &m.palette {
+                Some((label, v)) if *v == m.current_value => Some(label),
+                _ => None,
+            },
+            FixtureMacro::Colour(m) => match &m.palette {
+                Some((label, c)) if *c == m.current_value => Some(label),
+                _ => None,
+            },
+            FixtureMacro::Position(m) => match &m.palette {
+                Some((label, p)) if *p == m.current_value => Some(label),
+                _ => None,
+            },
+            FixtureMacro::Temperature(m) => match &m.palette {
+                Some((label, t)) if *t == m.current_value => Some(label),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn last_source(&self) -> ValueSource {
+        match self {
+            FixtureMacro::Control(m) => m.last_source,
+            FixtureMacro::Colour(m) => m.last_source,
+            FixtureMacro::Position(m) => m.last_source,
+            FixtureMacro::Temperature(m) => m.last_source,
+        }
+    }
+
+    /// All (one-indexed, fixture-relative) channels written by this macro
+    pub fn channels(&self) -> Vec<u16> {
+        match self {
+            FixtureMacro::Control(m) => m.channels.iter().flat_map(|c| c.channels()).collect(),
+            FixtureMacro::Position(m) => [&m.pan, &m.tilt]
+                .into_iter()
+                .flat_map(|c| c.channels())
+                .collect(),
+            FixtureMacro::Colour(m) => m.channels.channels(),
+            FixtureMacro::Temperature(m) => m.channels.channels(),
+        }
+    }
+
+    pub fn set_last_source(&mut self, source: ValueSource) {
+        match self {
+            FixtureMacro::Control(m) => m.last_source = source,
+            FixtureMacro::Colour(m) => m.last_source = source,
+            FixtureMacro::Position(m) => m.last_source = source,
+            FixtureMacro::Temperature(m) => m.last_source = source,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -202,4 +622,7 @@ pub struct RangeDescription {
     pub label: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub notes: Option<String>,
+    /// Values in this range make the fixture strobe or flash by itself
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strobe: bool,
 }
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use super::Project;
+
+/// A named, ordered list of fixtures, e.g. "stage left washes". Groups can be
+/// used anywhere a list of fixture labels is accepted; the order of fixtures
+/// is kept, e.g. for spreading values across the group.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FixtureGroup {
+    pub label: String,
+    /// Fixture instance labels, in order
+    pub fixtures: Vec<String>,
+}
+
+impl FixtureGroup {
+    pub fn contains(&self, fixture_label: &str) -> bool {
+        self.fixtures
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(fixture_label))
+    }
+}
+
+impl Project {
+    pub fn find_group(&self, label: &str) -> Option<&FixtureGroup> {
+        self.groups
+            .iter()
+            .find(|g| g.label.eq_ignore_ascii_case(label))
+    }
+
+    /// Expand any group labels in the list into their member fixture labels,
+    /// keeping the order and removing duplicates. Labels which are not groups
+    /// are assumed to be fixture labels and passed through unchanged.
+    pub fn resolve_fixture_labels(&self, labels: &[String]) -> Vec<String> {
+        let mut resolved: Vec<String> = Vec::new();
+        for label in labels.iter() {
+            let members = match self.find_group(label) {
+                Some(group) => group.fixtures.clone(),
+                None => vec![label.clone()],
+            };
+            for m in members {
+                if !resolved.iter().any(|r| r.eq_ignore_ascii_case(&m)) {
+                    resolved.push(m);
+                }
+            }
+        }
+        resolved
+    }
+}
@@ -1,5 +1,7 @@
-use std::fs;
+use std::{fs, io::Write, path::Path};
 
+use anyhow::anyhow;
+use chrono::Local;
 use log::{debug, error, info, warn};
 use scene::Scene;
 use serde::{Deserialize, Serialize};
@@ -8,21 +10,63 @@ use crate::project::fixture::{FixtureConfig, FixtureMacro};
 
 use self::artnetconfig::ArtNetConfigMode;
 use self::fixture::FixtureInstance;
+use self::group::FixtureGroup;
 use self::midiconfig::MidiConfig;
+use self::palette::Palette;
+use self::park::{Park, ParkedChannels};
+use self::safety::SafetyConfig;
+use self::schedule::Schedule;
+use self::timeline::Timeline;
+use crate::artnet::PortAddress;
 
 pub mod artnetconfig;
+pub mod autosave;
+pub mod curve;
 pub mod fixture;
+pub mod group;
 pub mod midiconfig;
+pub mod palette;
+pub mod park;
+pub mod reload;
+pub mod safety;
 pub mod scene;
+pub mod schedule;
+pub mod timeline;
+pub mod validation;
+
+const BACKUP_INFIX: &str = ".backup-";
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Project {
     pub fixtures: Vec<FixtureInstance>,
     pub scenes: Vec<Scene>,
+    /// Named, ordered groups of fixtures
+    #[serde(default)]
+    pub groups: Vec<FixtureGroup>,
+    /// Named presets which Scenes can refer to
+    #[serde(default)]
+    pub palettes: Vec<Palette>,
+    /// Channels and fixture attributes held at a fixed value
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parks: Vec<Park>,
+    #[serde(skip)]
+    pub parked: ParkedChannels,
+    /// Limits on flashing and strobing, applied to the output last of all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<SafetyConfig>,
+    /// Scenes and blackouts triggered at times of day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
+    /// Cues fired by timecode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<Timeline>,
     #[serde(default)]
     pub midi_config: MidiConfig,
     pub artnet_config: Option<ArtNetConfigMode>,
+    /// Where to send the output universe (unless given on the command line)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artnet_port_address: Option<PortAddress>,
 }
 
 impl Project {
@@ -30,8 +74,16 @@ impl Project {
         Project {
             fixtures: Vec::new(),
             scenes: Vec::new(),
+            groups: Vec::new(),
+            palettes: Vec::new(),
+            parks: Vec::new(),
+            parked: ParkedChannels::default(),
+            safety: None,
+            schedule: None,
+            timeline: None,
             midi_config: MidiConfig::default(),
             artnet_config: None,
+            artnet_port_address: None,
         }
     }
 
@@ -39,8 +91,8 @@ impl Project {
         match fs::read_to_string(path) {
             Ok(d) => {
                 info!("Found project {}; parsing...", &path);
-                let mut project =
-                    serde_json::from_str::<Project>(&d).expect("failed to parse project file");
+                let mut project = serde_json::from_str::<Project>(&d)
+                    .map_err(|e| anyhow!("failed to parse project file: {}", e))?;
                 info!(
                     "... loaded project with {} fixtures OK",
                     project.fixtures.len()
@@ -66,15 +118,23 @@ impl Project {
                         .find(|x| x.name.eq_ignore_ascii_case(&fixture_ref.config_name))
                     {
                         fixture_ref.config = fixture_config.clone();
-                        fixture_ref.config.active_mode =
-                            fixture_ref.config.modes[fixture_ref.mode_index].clone();
+                        fixture_ref.config.active_mode = fixture_ref
+                            .config
+                            .modes
+                            .get(fixture_ref.mode_index)
+                            .ok_or(anyhow!(
+                                "fixture \"{}\" has mode index {} but \"{}\" only has {} mode(s)",
+                                &fixture_ref.label,
+                                fixture_ref.mode_index,
+                                &fixture_config.name,
+                                fixture_config.modes.len()
+                            ))?
+                            .clone();
 
                         for m in fixture_ref.config.active_mode.macros.iter_mut() {
                             match m {
                                 FixtureMacro::Control(control_macro) => {
                                     if control_macro.label == "brightness"
-                                        || control_macro.label == "pan"
-                                        || control_macro.label == "tilt"
                                         || control_macro.label == "zoom"
                                     {
                                         control_macro.midi_knob_index = Some(global_index);
@@ -83,8 +143,14 @@ impl Project {
                                         control_macro.midi_knob_index = None;
                                     }
                                 }
-                                FixtureMacro::Colour(_colour_macro) => {
-                                    // Ignore colour macros for now
+                                FixtureMacro::Position(position_macro) => {
+                                    // Pan, then tilt, in place of the separate
+                                    // macros they used to be
+                                    position_macro.midi_knob_index = Some(global_index);
+                                    global_index += 2;
+                                }
+                                FixtureMacro::Colour(_) | FixtureMacro::Temperature(_) => {
+                                    // Ignore colour and temperature macros for now
                                 }
                             }
                         }
@@ -101,10 +167,9 @@ impl Project {
                 // Sort macros in Fixtures, alphabetically...
                 for fixture in project.fixtures.iter_mut() {
                     let mut mode_macros_ordered = fixture.config.active_mode.clone();
-                    mode_macros_ordered.macros.sort_by_key(|m| match m {
-                        fixture::FixtureMacro::Control(cm) => cm.label.clone(),
-                        fixture::FixtureMacro::Colour(cm) => cm.label.clone(),
-                    });
+                    mode_macros_ordered
+                        .macros
+                        .sort_by_key(|m| String::from(m.label()));
                     fixture.config.active_mode = mode_macros_ordered;
                 }
 
@@ -150,19 +215,115 @@ impl Project {
         }
     }
 
+    /// Save the project as JSON. The file is written atomically (to a temporary
+    /// file which is synced to disk, then renamed), so a crash or power cut
+    /// mid-save cannot corrupt it.
     pub fn save(path: &str, project: &Project) -> anyhow::Result<()> {
-        let mut new_project = project.clone();
-        new_project.fixtures.sort();
-
-        let json = serde_json::to_string_pretty(&new_project)?;
+        let json = project.to_json()?;
         debug!("{}", json);
 
-        fs::write(path, json)?;
+        write_atomic(path, &json)?;
 
         info!("Saved Project JSON to \"{}\" OK", &path);
 
         Ok(())
     }
+
+    /// Like [`Project::save`], but first copy any existing file at the path to a
+    /// timestamped backup, keeping only the most recent `keep_backups` of these.
+    pub fn save_with_backup(
+        path: &str,
+        project: &Project,
+        keep_backups: usize,
+    ) -> anyhow::Result<()> {
+        if keep_backups > 0 && Path::new(path).is_file() {
+            let stamp = Local::now().format("%Y%m%d-%H%M%S-%3f");
+            let mut backup_path = format!("{}{}{}", path, BACKUP_INFIX, stamp);
+            // Saved twice within a millisecond; don't overwrite the first backup
+            let mut count = 1;
+            while Path::new(&backup_path).exists() {
+                backup_path = format!("{}{}{}-{}", path, BACKUP_INFIX, stamp, count);
+                count += 1;
+            }
+            fs::copy(path, &backup_path)?;
+            debug!("Backed up \"{}\" to \"{}\"", path, &backup_path);
+            remove_old_backups(path, keep_backups);
+        }
+        Project::save(path, project)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let mut new_project = self.clone();
+        new_project.fixtures.sort();
+        Ok(serde_json::to_string_pretty(&new_project)?)
+    }
+}
+
+/// Write to a temporary file alongside the target, then rename over the target.
+/// The file is synced to disk before the rename, and the directory after it,
+/// so that after a power cut the target is either the old or the new version.
+pub fn write_atomic(path: &str, contents: &str) -> anyhow::Result<()> {
+    let temp_path = format!("{}.tmp", path);
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)?;
+    sync_parent_dir(path);
+    Ok(())
+}
+
+/// Make a rename in this file's directory durable. Only possible on Unix,
+/// where a directory can be opened like a file.
+fn sync_parent_dir(path: &str) {
+    #[cfg(unix)]
+    {
+        let dir = match Path::new(path).parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        if let Err(e) = fs::File::open(dir).and_then(|d| d.sync_all()) {
+            warn!("Could not sync directory {:?}: {}", dir, e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Backups are named like `my.project.json.backup-20240315-093000-125`, so that
+/// sorting them by name also sorts them by age.
+fn remove_old_backups(path: &str, keep_backups: usize) {
+    let project_path = Path::new(path);
+    let dir = match project_path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let Some(file_name) = project_path.file_name().and_then(|n| n.to_str()) else {
+        return;
+    };
+    let prefix = format!("{}{}", file_name, BACKUP_INFIX);
+
+    let mut backups: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|e| e.path())
+            .collect(),
+        Err(e) => {
+            warn!("Failed to list backups in {:?}: {}", dir, e);
+            return;
+        }
+    };
+    backups.sort();
+
+    if backups.len() > keep_backups {
+        for old_backup in backups.iter().take(backups.len() - keep_backups) {
+            debug!("Remove old backup {:?}", old_backup);
+            if let Err(e) = fs::remove_file(old_backup) {
+                warn!("Failed to remove old backup {:?}: {}", old_backup, e);
+            }
+        }
+    }
 }
 
 impl Default for Project {
@@ -186,3 +347,40 @@ pub fn load_all_fixture_configs() -> Vec<FixtureConfig> {
     );
     all_fixture_configs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quick_saves_keep_separate_backups() {
+        let dir = std::env::temp_dir().join(format!("backups-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("quick.project.json");
+        let path = path.to_str().unwrap();
+        let count_backups = || {
+            fs::read_dir(&dir)
+                .unwrap()
+                .filter(|e| {
+                    e.as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .contains(BACKUP_INFIX)
+                })
+                .count()
+        };
+
+        let project = Project::new();
+        for _ in 0..10 {
+            Project::save_with_backup(path, &project, 20).unwrap();
+        }
+        // Every save but the first backs up the one before
+        assert_eq!(count_backups(), 9);
+
+        Project::save_with_backup(path, &project, 3).unwrap();
+        assert_eq!(count_backups(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use ecolor::Color32;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fixture::{FixtureInstance, FixtureMacro},
+    scene::{SceneState, SceneValue},
+    Project,
+};
+
+pub const PALETTE_KINDS: [PaletteKind; 4] = [
+    PaletteKind::Colour,
+    PaletteKind::Position,
+    PaletteKind::Beam,
+    PaletteKind::Intensity,
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PaletteKind {
+    Colour,
+    Position,
+    /// e.g. zoom, focus, shutter
+    Beam,
+    Intensity,
+}
+
+/// A named preset of macro values, which Scenes can refer to by label
+/// (via `SceneValue::Palette`) instead of storing the raw values
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Palette {
+    pub label: String,
+    pub kind: PaletteKind,
+    /// { "macro label": value }
+    pub values: SceneState,
+}
+
+/// Macro labels treated as Intensity (rather than Beam) when capturing a palette
+pub const INTENSITY_MACROS: [&str; 3] = ["brightness", "dimmer", "intensity"];
+
+impl Palette {
+    /// Take the current values of the relevant macros from a fixture
+    pub fn capture(label: &str, kind: PaletteKind, fixture: &FixtureInstance) -> Palette {
+        let values = fixture
+            .config
+            .active_mode
+            .macros
+            .iter()
+            .filter_map(|m| {
+                let is_intensity = INTENSITY_MACROS
+                    .iter()
+                    .any(|l| m.label().eq_ignore_ascii_case(l));
+                let value = match (kind, m) {
+                    (PaletteKind::Colour, FixtureMacro::Colour(c)) => {
+                        SceneValue::ColourValue(c.current_value)
+                    }
+                    (PaletteKind::Colour, FixtureMacro::Temperature(t)) => {
+                        SceneValue::TemperatureValue(t.current_value)
+                    }
+                    (PaletteKind::Position, FixtureMacro::Position(p)) => {
+                        SceneValue::PositionValue(p.current_value)
+                    }
+                    (PaletteKind::Intensity, FixtureMacro::Control(c)) if is_intensity => {
+                        SceneValue::ControlValue(c.current_value)
+                    }
+                    (PaletteKind::Beam, FixtureMacro::Control(c)) if !is_intensity => {
+                        SceneValue::ControlValue(c.current_value)
+                    }
+                    _ => return None,
+                };
+                Some((String::from(m.label()), value))
+            })
+            .collect();
+        Palette {
+            label: String::from(label),
+            kind,
+            values,
+        }
+    }
+
+    /// The colour to show for this palette's swatch, if it sets a colour
+    pub fn swatch_colour(&self) -> Option<Color32> {
+        self.values
+            .values()
+            .find_map(|v| v.as_colour().map(|c| c.to_opaque()))
+    }
+}
+
+/// Look up a macro value in a Scene entry, following a palette reference if
+/// necessary. Returns the concrete value plus the palette label, if any.
+pub fn resolve_scene_value<'a>(
+    scene_state: &'a SceneState,
+    macro_label: &str,
+    palettes: &'a [Palette],
+) -> Option<(&'a SceneValue, Option<&'a str>)> {
+    match scene_state.get(macro_label)? {
+        SceneValue::Palette(palette_label) => {
+            let palette = palettes
+                .iter()
+                .find(|p| p.label.eq_ignore_ascii_case(palette_label))?;
+            match palette.values.get(macro_label)? {
+                // Palettes do not nest
+                SceneValue::Palette(_) => None,
+                value => Some((value, Some(&palette.label))),
+            }
+        }
+        value => Some((value, None)),
+    }
+}
+
+impl Project {
+    pub fn find_palette(&self, label: &str) -> Option<&Palette> {
+        self.palettes
+            .iter()
+            .find(|p| p.label.eq_ignore_ascii_case(label))
+    }
+
+    /// Rename a palette, keeping every Scene reference to it
+    pub fn rename_palette(&mut self, index: usize, new_label: &str) {
+        let old_label = std::mem::replace(&mut self.palettes[index].label, String::from(new_label));
+        for value in self.scene_values_mut() {
+            if let SceneValue::Palette(label) = value {
+                if label.eq_ignore_ascii_case(&old_label) {
+                    *label = String::from(new_label);
+                }
+            }
+        }
+    }
+
+    /// Remove a palette. Scenes which refer to it get its values instead,
+    /// so they look the same as before.
+    pub fn remove_palette(&mut self, index: usize) {
+        let palette = self.palettes.remove(index);
+        for scene in self.scenes.iter_mut() {
+            for fixture_state in scene.state.values_mut() {
+                for (macro_label, value) in fixture_state.iter_mut() {
+                    if let SceneValue::Palette(label) = value {
+                        if label.eq_ignore_ascii_case(&palette.label) {
+                            if let Some(v) = palette.values.get(macro_label) {
+                                *value = v.clone();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Labels of Scenes which refer to the given palette
+    pub fn scenes_using_palette(&self, palette_label: &str) -> Vec<&str> {
+        self.scenes
+            .iter()
+            .filter(|scene| {
+                scene.state.values().any(|fixture_state| {
+                    fixture_state.values().any(|v| match v {
+                        SceneValue::Palette(label) => label.eq_ignore_ascii_case(palette_label),
+                        _ => false,
+                    })
+                })
+            })
+            .map(|scene| scene.label.as_str())
+            .collect()
+    }
+
+    fn scene_values_mut(&mut self) -> impl Iterator<Item = &mut SceneValue> {
+        self.scenes
+            .iter_mut()
+            .flat_map(|scene| scene.state.values_mut())
+            .flat_map(|fixture_state| fixture_state.values_mut())
+    }
+}
@@ -0,0 +1,180 @@
+use serde::{Deserialize, Serialize};
+
+use super::{fixture::FixtureInstance, Project};
+
+/// What a Park applies to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum ParkTarget {
+    /// A single (one-indexed) DMX channel
+    Channel { channel: u16 },
+    /// A fixture's channel or macro, by its label, e.g. "lamp" or "brightness"
+    #[serde(rename_all = "camelCase")]
+    Attribute {
+        fixture_label: String,
+        attribute: String,
+    },
+}
+
+/// A channel (or fixture attribute) forced to a fixed value, whatever scenes,
+/// macros, sliders or the exit behaviour would otherwise send
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Park {
+    #[serde(flatten)]
+    pub target: ParkTarget,
+    pub value: u8,
+}
+
+impl ParkTarget {
+    pub fn describe(&self) -> String {
+        match self {
+            ParkTarget::Channel { channel } => format!("CH{}", channel),
+            ParkTarget::Attribute {
+                fixture_label,
+                attribute,
+            } => format!("{} {}", fixture_label, attribute),
+        }
+    }
+
+    /// Same target, ignoring case of labels
+    pub fn matches(&self, other: &ParkTarget) -> bool {
+        match (self, other) {
+            (ParkTarget::Channel { channel: a }, ParkTarget::Channel { channel: b }) => a == b,
+            (
+                ParkTarget::Attribute {
+                    fixture_label: fa,
+                    attribute: aa,
+                },
+                ParkTarget::Attribute {
+                    fixture_label: fb,
+                    attribute: ab,
+                },
+            ) => fa.eq_ignore_ascii_case(fb) && aa.eq_ignore_ascii_case(ab),
+            _ => false,
+        }
+    }
+
+    /// The (one-indexed) DMX channels this refers to. Attributes match a mapping
+    /// label first, otherwise every channel of the macro with that label.
+    pub fn channels(&self, fixtures: &[FixtureInstance]) -> Vec<u16> {
+        match self {
+            ParkTarget::Channel { channel } => vec![*channel],
+            ParkTarget::Attribute {
+                fixture_label,
+                attribute,
+            } => {
+                let Some(fixture) = fixtures
+                    .iter()
+                    .find(|f| f.label.eq_ignore_ascii_case(fixture_label))
+                else {
+                    return Vec::new();
+                };
+                let mode = &fixture.config.active_mode;
+                let fixture_channels = match mode
+                    .mappings
+                    .iter()
+                    .find(|m| m.label.eq_ignore_ascii_case(attribute))
+                {
+                    Some(mapping) => vec![mapping.channel],
+                    None => mode
+                        .macros
+                        .iter()
+                        .find(|m| m.label().eq_ignore_ascii_case(attribute))
+                        .map(|m| m.channels())
+                        .unwrap_or_default(),
+                };
+                fixture_channels
+                    .into_iter()
+                    .map(|c| c + fixture.start_channel - 1)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The parts of a fixture instance which attribute parks depend on
+#[derive(Clone, Debug, PartialEq)]
+struct PatchKey {
+    label: String,
+    config_name: String,
+    mode_index: usize,
+    start_channel: u16,
+}
+
+impl PatchKey {
+    fn new(fixture: &FixtureInstance) -> Self {
+        PatchKey {
+            label: fixture.label.clone(),
+            config_name: fixture.config_name.clone(),
+            mode_index: fixture.mode_index,
+            start_channel: fixture.start_channel,
+        }
+    }
+
+    fn matches(&self, fixture: &FixtureInstance) -> bool {
+        self.start_channel == fixture.start_channel
+            && self.mode_index == fixture.mode_index
+            && self.label == fixture.label
+            && self.config_name == fixture.config_name
+    }
+}
+
+/// Parks resolved to DMX channels, worked out again only when the parks or
+/// the patch change rather than on every frame
+#[derive(Clone, Debug, Default)]
+pub struct ParkedChannels {
+    parks: Vec<Park>,
+    patch: Vec<PatchKey>,
+    channels: Vec<(u16, u8)>,
+}
+
+impl ParkedChannels {
+    fn is_current(&self, parks: &[Park], fixtures: &[FixtureInstance]) -> bool {
+        self.parks == parks
+            && self.patch.len() == fixtures.len()
+            && self.patch.iter().zip(fixtures).all(|(k, f)| k.matches(f))
+    }
+
+    /// Every parked (one-indexed) DMX channel and its value, rebuilt first if
+    /// anything has changed; later parks win
+    pub fn get(&mut self, parks: &[Park], fixtures: &[FixtureInstance]) -> &[(u16, u8)] {
+        if !self.is_current(parks, fixtures) {
+            self.channels = parks
+                .iter()
+                .flat_map(|park| {
+                    park.target
+                        .channels(fixtures)
+                        .into_iter()
+                        .map(|c| (c, park.value))
+                })
+                .collect();
+            self.parks = parks.to_vec();
+            self.patch = fixtures.iter().map(PatchKey::new).collect();
+        }
+        &self.channels
+    }
+}
+
+impl Project {
+    /// Every parked (one-indexed) DMX channel and its value; later parks win
+    pub fn parked_channels(&mut self) -> &[(u16, u8)] {
+        self.parked.get(&self.parks, &self.fixtures)
+    }
+
+    pub fn find_park(&self, target: &ParkTarget) -> Option<usize> {
+        self.parks.iter().position(|p| p.target.matches(target))
+    }
+
+    /// Park a target at the given value, or release it if None
+    pub fn set_park(&mut self, target: ParkTarget, value: Option<u8>) {
+        match (self.find_park(&target), value) {
+            (Some(index), Some(value)) => self.parks[index].value = value,
+            (Some(index), None) => {
+                self.parks.remove(index);
+            }
+            (None, Some(value)) => self.parks.push(Park { target, value }),
+            (None, None) => {}
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{debug, warn};
+
+use super::{fixture::FixtureMacro, Project};
+
+/// How often to check the project file for modifications
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Polls the modification time of a project file, so that edits made on disk
+/// (e.g. on a headless server) can be reloaded without restarting.
+pub struct ProjectWatcher {
+    path: String,
+    last_modified: Option<SystemTime>,
+    last_checked: Instant,
+}
+
+impl ProjectWatcher {
+    pub fn new(path: &str) -> Self {
+        ProjectWatcher {
+            path: String::from(path),
+            last_modified: modified_time(path),
+            last_checked: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns true (once) if the file has been modified since the last time
+    /// this returned true. Only actually checks the file every WATCH_INTERVAL.
+    pub fn has_changed(&mut self) -> bool {
+        if self.last_checked.elapsed() < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_checked = Instant::now();
+
+        let modified = modified_time(&self.path);
+        if modified.is_some() && modified != self.last_modified {
+            debug!(
+                "Project file \"{}\" modified: {:?} => {:?}",
+                &self.path, self.last_modified, modified
+            );
+            self.last_modified = modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(t) => Some(t),
+        Err(e) => {
+            warn!("Could not get modification time for \"{}\": {}", path, e);
+            None
+        }
+    }
+}
+
+impl Project {
+    /// Human-readable list of differences between this (older) project and a newer version
+    pub fn describe_changes(&self, newer: &Project) -> Vec<String> {
+        let mut changes = Vec::new();
+
+        for fixture in newer.fixtures.iter() {
+            match self.fixtures.iter().find(|f| f.label == fixture.label) {
+                Some(existing) => {
+                    if !existing
+                        .config_name
+                        .eq_ignore_ascii_case(&fixture.config_name)
+                        || existing.mode_index != fixture.mode_index
+                    {
+                        changes.push(format!(
+                            "Fixture \"{}\" changed type/mode: {} (mode {}) => {} (mode {})",
+                            &fixture.label,
+                            &existing.config_name,
+                            existing.mode_index,
+                            &fixture.config_name,
+                            fixture.mode_index
+                        ));
+                    }
+                    if existing.start_channel != fixture.start_channel {
+                        changes.push(format!(
+                            "Fixture \"{}\" moved: CH{} => CH{}",
+                            &fixture.label, existing.start_channel, fixture.start_channel
+                        ));
+                    }
+                }
+                None => changes.push(format!(
+                    "Fixture \"{}\" added at CH{}",
+                    &fixture.label, fixture.start_channel
+                )),
+            }
+        }
+        for fixture in self.fixtures.iter() {
+            if !newer.fixtures.iter().any(|f| f.label == fixture.label) {
+                changes.push(format!("Fixture \"{}\" removed", &fixture.label));
+            }
+        }
+
+        for scene in newer.scenes.iter() {
+            match self.scenes.iter().find(|s| s.label == scene.label) {
+                Some(existing) => {
+                    if existing.state != scene.state {
+                        changes.push(format!("Scene \"{}\" changed", &scene.label));
+                    }
+                }
+                None => changes.push(format!("Scene \"{}\" added", &scene.label)),
+            }
+        }
+        for scene in self.scenes.iter() {
+            if !newer.scenes.iter().any(|s| s.label == scene.label) {
+                changes.push(format!("Scene \"{}\" removed", &scene.label));
+            }
+        }
+
+        if self.groups != newer.groups {
+            changes.push(String::from("Fixture groups changed"));
+        }
+        if self.palettes != newer.palettes {
+            changes.push(String::from("Palettes changed"));
+        }
+        if self.parks != newer.parks {
+            changes.push(String::from("Parks changed"));
+        }
+        if self.safety != newer.safety {
+            changes.push(String::from("Safety limits changed"));
+        }
+        if self.schedule != newer.schedule {
+            changes.push(String::from("Schedule changed"));
+        }
+        if self.timeline != newer.timeline {
+            changes.push(String::from("Timeline changed"));
+        }
+        if self.artnet_config != newer.artnet_config {
+            changes.push(format!(
+                "ArtNet config changed: {:?} => {:?}",
+                self.artnet_config, newer.artnet_config
+            ));
+        }
+        if self.artnet_port_address != newer.artnet_port_address {
+            changes.push(format!(
+                "ArtNet Port-Address changed: {:?} => {:?}",
+                self.artnet_port_address, newer.artnet_port_address
+            ));
+        }
+        if self.midi_config != newer.midi_config {
+            changes.push(String::from("MIDI config changed"));
+        }
+
+        changes
+    }
+
+    /// Move live state (current macro values, running animations and scene
+    /// status) from a previous version of the project into this one, for any
+    /// fixtures, macros and scenes which still exist with the same type.
+    pub fn carry_over_state(&mut self, previous: &mut Project) {
+        for fixture in self.fixtures.iter_mut() {
+            let Some(old_fixture) = previous.fixtures.iter_mut().find(|f| {
+                f.label == fixture.label
+                    && f.config_name.eq_ignore_ascii_case(&fixture.config_name)
+                    && f.mode_index == fixture.mode_index
+            }) else {
+                continue;
+            };
+            for m in fixture.config.active_mode.macros.iter_mut() {
+                let old_macro = old_fixture
+                    .config
+                    .active_mode
+                    .macros
+                    .iter_mut()
+                    .find(|old| old.label() == m.label());
+                match (m, old_macro) {
+                    (FixtureMacro::Control(new), Some(FixtureMacro::Control(old))) => {
+                        new.current_value = old.current_value;
+                        new.animation = old.animation.take();
+                        new.last_source = old.last_source;
+                    }
+                    (FixtureMacro::Colour(new), Some(FixtureMacro::Colour(old))) => {
+                        new.current_value = old.current_value;
+                        new.animation = old.animation.take();
+                        new.last_source = old.last_source;
+                    }
+                    (FixtureMacro::Position(new), Some(FixtureMacro::Position(old))) => {
+                        new.current_value = old.current_value;
+                        new.animation = old.animation.take();
+                        new.last_source = old.last_source;
+                    }
+                    (FixtureMacro::Temperature(new), Some(FixtureMacro::Temperature(old))) => {
+                        new.current_value = old.current_value;
+                        new.animation = old.animation.take();
+                        new.last_source = old.last_source;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for scene in self.scenes.iter_mut() {
+            if let Some(old_scene) = previous.scenes.iter().find(|s| s.label == scene.label) {
+                scene.last_active = old_scene.last_active;
+                scene.next_transition = old_scene.next_transition;
+            }
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    fixture::{FixtureInstance, FixtureMacro},
+    palette::INTENSITY_MACROS,
+};
+
+/// How far back flashes are counted when deciding whether a fixture is flashing
+const FLASH_WINDOW: Duration = Duration::from_secs(1);
+
+/// Limiting is logged at most this often
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Protection against photosensitivity hazards, applied to the output last of all
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SafetyConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Highest rate (flashes per second) at which any fixture's intensity may
+    /// swing up and down
+    #[serde(default = "default_max_flash_hz")]
+    pub max_flash_hz: f32,
+    /// Largest fraction `[0,1]` of the rig's fixtures which may be flashing at once
+    #[serde(default = "default_max_flash_area")]
+    pub max_flash_area: f32,
+    /// Smallest change in intensity (out of 255) which counts as a flash
+    #[serde(default = "default_flash_threshold")]
+    pub flash_threshold: u8,
+    /// Allow fixtures' own strobe effects, i.e. values in ranges marked `strobe`
+    #[serde(default)]
+    pub allow_strobe: bool,
+    /// Allow the `safety` Tether Input Plug to disable the limiter
+    #[serde(default)]
+    pub allow_remote_override: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_flash_hz() -> f32 {
+    3.
+}
+
+fn default_max_flash_area() -> f32 {
+    0.25
+}
+
+fn default_flash_threshold() -> u8 {
+    64
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            enabled: true,
+            max_flash_hz: default_max_flash_hz(),
+            max_flash_area: default_max_flash_area(),
+            flash_threshold: default_flash_threshold(),
+            allow_strobe: false,
+            allow_remote_override: false,
+        }
+    }
+}
+
+struct FlashHistory {
+    level: u8,
+    flashes: VecDeque<Instant>,
+}
+
+/// Tracks flashing across the rig from one frame to the next, holding back
+/// any change which would break the limits in a SafetyConfig
+#[derive(Default)]
+pub struct SafetyLimiter {
+    previous: Vec<u8>,
+    history: HashMap<String, FlashHistory>,
+    /// When limiting last kicked in, and why
+    pub last_limited: Option<(Instant, String)>,
+    last_logged: Option<Instant>,
+}
+
+impl SafetyLimiter {
+    /// True if limiting kicked in within the last second or so
+    pub fn is_limiting(&self, now: Instant) -> bool {
+        self.last_limited
+            .as_ref()
+            .is_some_and(|(t, _)| now.saturating_duration_since(*t) < FLASH_WINDOW)
+    }
+
+    pub fn apply(
+        &mut self,
+        config: &SafetyConfig,
+        channels: &mut [u8],
+        fixtures: &[FixtureInstance],
+        now: Instant,
+    ) {
+        if !config.enabled {
+            self.history.clear();
+            self.previous = channels.to_vec();
+            return;
+        }
+
+        if !config.allow_strobe {
+            for fixture in fixtures {
+                for (channel, safe_value) in strobe_channels(fixture) {
+                    let Some(value) = (channel + fixture.start_channel)
+                        .checked_sub(2)
+                        .and_then(|i| channels.get_mut(i as usize))
+                    else {
+                        continue;
+                    };
+                    if let Some(safe_value) = safe_value(*value) {
+                        *value = safe_value;
+                        self.limited(now, format!("{} strobe effect blocked", fixture.label));
+                    }
+                }
+            }
+        }
+
+        for history in self.history.values_mut() {
+            while history
+                .flashes
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > FLASH_WINDOW)
+            {
+                history.flashes.pop_front();
+            }
+        }
+
+        let intensity: Vec<(&FixtureInstance, IntensityChannels)> = fixtures
+            .iter()
+            .filter_map(|f| Some((f, IntensityChannels::new(f)?)))
+            .collect();
+
+        let max_flashing =
+            (config.max_flash_area.clamp(0., 1.) * intensity.len() as f32).ceil() as usize;
+        let mut flashing = self
+            .history
+            .values()
+            .filter(|h| h.flashes.len() >= 2)
+            .count();
+        let min_interval = Duration::from_secs_f32(0.5 / config.max_flash_hz.max(0.1));
+
+        for (fixture, intensity_channels) in intensity {
+            let level = intensity_channels.level(channels);
+            let Some(history) = self.history.get_mut(&fixture.label) else {
+                self.history.insert(
+                    fixture.label.clone(),
+                    FlashHistory {
+                        level,
+                        flashes: VecDeque::new(),
+                    },
+                );
+                continue;
+            };
+            if level.abs_diff(history.level) < config.flash_threshold {
+                history.level = level;
+                continue;
+            }
+
+            let too_fast = history
+                .flashes
+                .back()
+                .is_some_and(|t| now.duration_since(*t) < min_interval);
+            let starts_flashing = history.flashes.len() == 1;
+            let too_wide = starts_flashing && flashing >= max_flashing;
+
+            if too_fast || too_wide {
+                for i in intensity_channels.hold {
+                    if let (Some(c), Some(p)) = (channels.get_mut(i), self.previous.get(i)) {
+                        *c = *p;
+                    }
+                }
+                let reason = if too_fast {
+                    format!(
+                        "{} flashing faster than {}Hz",
+                        fixture.label, config.max_flash_hz
+                    )
+                } else {
+                    format!(
+                        "{} flashing with more than {}% of the rig",
+                        fixture.label,
+                        (config.max_flash_area * 100.).round()
+                    )
+                };
+                self.limited(now, reason);
+            } else {
+                history.flashes.push_back(now);
+                history.level = level;
+                if starts_flashing {
+                    flashing += 1;
+                }
+            }
+        }
+
+        self.previous = channels.to_vec();
+    }
+
+    fn limited(&mut self, now: Instant, reason: String) {
+        if self
+            .last_logged
+            .is_none_or(|t| now.duration_since(t) >= LOG_INTERVAL)
+        {
+            warn!("Safety limiter: {}", reason);
+            self.last_logged = Some(now);
+        }
+        self.last_limited = Some((now, reason));
+    }
+}
+
+/// The channels (as zero-based universe indices) which together make up a
+/// fixture's light output
+struct IntensityChannels {
+    /// Coarse channels of its intensity macro, if it has one
+    dimmer: Vec<usize>,
+    /// Its additive colour emitters, if it has any
+    emitters: Vec<usize>,
+    /// Everything to hold back when it flashes too much
+    hold: Vec<usize>,
+}
+
+impl IntensityChannels {
+    /// None if the fixture has neither an intensity macro nor colour emitters
+    fn new(fixture: &FixtureInstance) -> Option<Self> {
+        let to_index = |c: u16| {
+            (c + fixture.start_channel)
+                .checked_sub(2)
+                .map(|i| i as usize)
+        };
+        let macros = &fixture.config.active_mode.macros;
+        let intensity_macro = macros.iter().find_map(|m| match m {
+            FixtureMacro::Control(c)
+                if INTENSITY_MACROS
+                    .iter()
+                    .any(|l| c.label.eq_ignore_ascii_case(l)) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        });
+        let dimmer: Vec<usize> = intensity_macro
+            .iter()
+            .flat_map(|m| m.channels.iter().map(|c| c.channels()[0]))
+            .filter_map(to_index)
+            .collect();
+        let emitters: Vec<usize> = macros
+            .iter()
+            .filter_map(|m| match m {
+                FixtureMacro::Colour(c) => c.channels.emitter_channels(),
+                FixtureMacro::Temperature(t) => t.channels.emitter_channels(),
+                _ => None,
+            })
+            .flatten()
+            .flat_map(|(_, c)| c.iter().copied())
+            .filter_map(to_index)
+            .collect();
+        if dimmer.is_empty() && emitters.is_empty() {
+            return None;
+        }
+        let hold = intensity_macro
+            .iter()
+            .flat_map(|m| m.channels.iter().flat_map(|c| c.channels()))
+            .filter_map(to_index)
+            .chain(emitters.iter().copied())
+            .collect();
+        Some(IntensityChannels {
+            dimmer,
+            emitters,
+            hold,
+        })
+    }
+
+    /// The light actually coming out (out of 255), from the final output: the
+    /// dimmer times the brightest emitter, whatever set them. A fixture with
+    /// only one of the two is measured on that alone.
+    fn level(&self, channels: &[u8]) -> u8 {
+        let fraction = |indices: &[usize]| {
+            if indices.is_empty() {
+                1.
+            } else {
+                indices
+                    .iter()
+                    .filter_map(|i| channels.get(*i))
+                    .copied()
+                    .max()
+                    .unwrap_or(0) as f32
+                    / 255.
+            }
+        };
+        (fraction(&self.dimmer) * fraction(&self.emitters) * 255.).round() as u8
+    }
+}
+
+/// Channels with strobe ranges, each with a function giving the value to send
+/// instead of a strobe value (None if the value is not in a strobe range).
+/// This is the channel's home value if that is safe, otherwise the lowest safe value.
+fn strobe_channels(fixture: &FixtureInstance) -> Vec<(u16, impl Fn(u8) -> Option<u8>)> {
+    fixture
+        .config
+        .active_mode
+        .mappings
+        .iter()
+        .filter_map(|m| {
+            let strobe_ranges: Vec<[u8; 2]> = m
+                .ranges
+                .as_ref()?
+                .iter()
+                .filter(|r| r.strobe)
+                .map(|r| r.range)
+                .collect();
+            if strobe_ranges.is_empty() {
+                return None;
+            }
+            let is_strobe = move |v: u8| strobe_ranges.iter().any(|[a, b]| (*a..=*b).contains(&v));
+            let safe = m
+                .home
+                .filter(|h| !is_strobe(*h))
+                .or_else(|| (0..=u8::MAX).find(|v| !is_strobe(*v)))
+                .unwrap_or(0);
+            Some((
+                m.channel,
+                move |v: u8| {
+                    if is_strobe(v) {
+                        Some(safe)
+                    } else {
+                        None
+                    }
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::{Clock, ManualClock},
+        project::Project,
+        settings::CHANNELS_PER_UNIVERSE,
+    };
+
+    /// Copies of "Hex Right" (a Vizi Hex Wash 7) from the example project, at
+    /// the given start channels
+    fn hex_washes(start_channels: &[u16]) -> Vec<FixtureInstance> {
+        let project =
+            Project::load(concat!(env!("CARGO_MANIFEST_DIR"), "/example.project.json")).unwrap();
+        let hex = project
+            .fixtures
+            .iter()
+            .find(|f| f.label == "Hex Right")
+            .unwrap();
+        start_channels
+            .iter()
+            .enumerate()
+            .map(|(i, start_channel)| {
+                let mut fixture = hex.clone();
+                fixture.label = format!("Hex {}", i);
+                fixture.start_channel = *start_channel;
+                fixture
+            })
+            .collect()
+    }
+
+    fn index(fixture: &FixtureInstance, channel: u16) -> usize {
+        (channel + fixture.start_channel - 2) as usize
+    }
+
+    /// Dimmer full, shutter open, and red (centre and outer) at the given
+    /// level, written straight to the channels as a slider would
+    fn set_red(channels: &mut [u8], fixture: &FixtureInstance, red: u8) {
+        channels[index(fixture, 18)] = 255;
+        channels[index(fixture, 19)] = 255;
+        channels[index(fixture, 5)] = red;
+        channels[index(fixture, 11)] = red;
+    }
+
+    /// Chase every fixture between red and black, switching every `toggle_ms`,
+    /// in 10ms frames; how often each fixture's red output actually changed
+    fn red_chase(
+        config: &SafetyConfig,
+        limiter: &mut SafetyLimiter,
+        clock: &ManualClock,
+        fixtures: &[FixtureInstance],
+        toggle_ms: u64,
+        total_ms: u64,
+    ) -> Vec<usize> {
+        let mut changes = vec![0; fixtures.len()];
+        let mut previous: Option<Vec<u8>> = None;
+        for t in (0..total_ms).step_by(10) {
+            let red = if (t / toggle_ms).is_multiple_of(2) {
+                255
+            } else {
+                0
+            };
+            let mut channels = vec![0; CHANNELS_PER_UNIVERSE as usize];
+            for fixture in fixtures {
+                set_red(&mut channels, fixture, red);
+            }
+            limiter.apply(config, &mut channels, fixtures, clock.now());
+            if let Some(previous) = &previous {
+                for (i, fixture) in fixtures.iter().enumerate() {
+                    if channels[index(fixture, 5)] != previous[index(fixture, 5)] {
+                        changes[i] += 1;
+                    }
+                }
+            }
+            previous = Some(channels);
+            clock.advance_ms(10);
+        }
+        changes
+    }
+
+    #[test]
+    fn colour_chase_at_full_dimmer_is_limited() {
+        let config = SafetyConfig::default();
+        let fixtures = hex_washes(&[1]);
+        let clock = ManualClock::new();
+        let mut limiter = SafetyLimiter::default();
+
+        // 10Hz; at most 3 flashes (6 changes) a second get through
+        let changes = red_chase(&config, &mut limiter, &clock, &fixtures, 50, 2000);
+        assert!(changes[0] > 0);
+        assert!(changes[0] <= 13, "{} changes", changes[0]);
+        assert!(limiter.is_limiting(clock.now()));
+    }
+
+    #[test]
+    fn slow_changes_are_left_alone() {
+        let config = SafetyConfig::default();
+        let fixtures = hex_washes(&[1]);
+        let clock = ManualClock::new();
+        let mut limiter = SafetyLimiter::default();
+
+        let changes = red_chase(&config, &mut limiter, &clock, &fixtures, 500, 2000);
+        assert_eq!(changes, vec![3]);
+        assert!(limiter.last_limited.is_none());
+
+        let disabled = SafetyConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        let changes = red_chase(&disabled, &mut limiter, &clock, &fixtures, 50, 2000);
+        assert_eq!(changes, vec![39]);
+    }
+
+    #[test]
+    fn only_part_of_the_rig_may_flash_at_once() {
+        let config = SafetyConfig::default();
+        let fixtures = hex_washes(&[1, 28, 55, 82]);
+        let clock = ManualClock::new();
+        let mut limiter = SafetyLimiter::default();
+
+        // 2Hz is slow enough, but a quarter of 4 fixtures is only 1
+        let changes = red_chase(&config, &mut limiter, &clock, &fixtures, 250, 2000);
+        assert_eq!(changes[0], 7);
+        for (i, c) in changes.iter().enumerate().skip(1) {
+            assert!(*c < 7, "fixture {} changed {} times", i, c);
+        }
+        assert!(limiter.is_limiting(clock.now()));
+    }
+
+    #[test]
+    fn strobe_values_are_replaced_unless_allowed() {
+        let fixtures = hex_washes(&[1]);
+        let shutter = index(&fixtures[0], 18);
+        let clock = ManualClock::new();
+
+        let run = |config: &SafetyConfig, value: u8| {
+            let mut channels = vec![0; CHANNELS_PER_UNIVERSE as usize];
+            channels[shutter] = value;
+            SafetyLimiter::default().apply(config, &mut channels, &fixtures, clock.now());
+            channels[shutter]
+        };
+
+        let config = SafetyConfig::default();
+        // "Strobing Speed" goes back to the home value, "LED On"
+        assert_eq!(run(&config, 70), 255);
+        assert_eq!(run(&config, 200), 255);
+        assert_eq!(run(&config, 40), 40);
+
+        let allowed = SafetyConfig {
+            allow_strobe: true,
+            ..Default::default()
+        };
+        assert_eq!(run(&allowed, 70), 70);
+    }
+}
@@ -1,11 +1,29 @@
-use egui::Color32;
+use ecolor::Color32;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::colour::Temperature;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum SceneValue {
     ControlValue(u16),
     ColourValue(Color32),
+    /// Pan, tilt
+    PositionValue([u16; 2]),
+    TemperatureValue(Temperature),
+    /// Whatever value the named Palette has for this macro
+    Palette(String),
+}
+
+impl SceneValue {
+    /// The colour this value shows as, for Colour or Temperature values
+    pub fn as_colour(&self) -> Option<Color32> {
+        match self {
+            SceneValue::ColourValue(c) => Some(*c),
+            SceneValue::TemperatureValue(t) => Some(t.to_rgb()),
+            _ => None,
+        }
+    }
 }
 
 /// { "macro label": value }
@@ -0,0 +1,671 @@
+use std::{f64::consts::PI, str::FromStr};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+/// How far ahead to look for the next occurrence of an entry
+const LOOKAHEAD_DAYS: i64 = 400;
+
+/// Scenes (or blackouts) triggered at fixed times of day, e.g. for installations
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    /// Where the installation is, for entries relative to sunrise or sunset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    pub entries: Vec<ScheduleEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    /// Degrees north (negative for south)
+    pub latitude: f64,
+    /// Degrees east (negative for west)
+    pub longitude: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleEntry {
+    /// When this happens (local time), e.g. "weekdays 08:00", "daily sunset-30m",
+    /// "sat,sun 10:15" or a five-field cron expression such as "0 8 * * 1-5"
+    pub when: String,
+    /// First date (inclusive) on which this entry applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<NaiveDate>,
+    /// Last date (inclusive) on which this entry applies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+    #[serde(flatten)]
+    pub action: ScheduleAction,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum ScheduleAction {
+    #[serde(rename_all = "camelCase")]
+    GoScene {
+        scene: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ms: Option<u64>,
+        /// Fixture and/or group labels. If no fixtures specified, assume all
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fixture_labels: Option<Vec<String>>,
+    },
+    /// Fade every intensity and colour macro to zero
+    Blackout {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ms: Option<u64>,
+    },
+}
+
+impl ScheduleAction {
+    pub fn describe(&self) -> String {
+        let duration = |ms: &Option<u64>| match ms {
+            Some(ms) => format!(" over {}s", *ms as f32 / 1000.),
+            None => String::new(),
+        };
+        match self {
+            ScheduleAction::GoScene { scene, ms, .. } => {
+                format!("go scene \"{}\"{}", scene, duration(ms))
+            }
+            ScheduleAction::Blackout { ms } => format!("blackout{}", duration(ms)),
+        }
+    }
+}
+
+/// A parsed `when`
+pub enum Recurrence {
+    /// On the given days of the week (Monday first)
+    Days {
+        days: [bool; 7],
+        time: TimeOfDay,
+    },
+    Cron(CronSpec),
+}
+
+pub enum TimeOfDay {
+    Fixed(NaiveTime),
+    /// Minutes after (or before, if negative) sunrise
+    Sunrise(i64),
+    Sunset(i64),
+}
+
+/// Minute, hour, day of month, month and day of week, as in crontab
+pub struct CronSpec {
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    /// Sunday first
+    days_of_week: Vec<bool>,
+    /// Day of month and day of week both restricted: either may match (as in cron)
+    either_day: bool,
+}
+
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+impl FromStr for Recurrence {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            [minute, hour, day_of_month, month, day_of_week] => Ok(Recurrence::Cron(CronSpec {
+                minutes: parse_cron_field(minute, 0, 59)?,
+                hours: parse_cron_field(hour, 0, 23)?,
+                days_of_month: parse_cron_field(day_of_month, 1, 31)?,
+                months: parse_cron_field(month, 1, 12)?,
+                days_of_week: {
+                    // 7 is also Sunday
+                    let mut days = parse_cron_field(&day_of_week.to_lowercase(), 0, 7)?;
+                    days[0] |= days[7];
+                    days.truncate(7);
+                    days
+                },
+                either_day: *day_of_month != "*" && *day_of_week != "*",
+            })),
+            [days, time] => Ok(Recurrence::Days {
+                days: parse_days(days)?,
+                time: parse_time_of_day(time)?,
+            }),
+            _ => Err(anyhow!(
+                "expected \"<days> <time>\" or a five-field cron expression, got \"{}\"",
+                s
+            )),
+        }
+    }
+}
+
+fn parse_days(s: &str) -> anyhow::Result<[bool; 7]> {
+    let s = s.to_lowercase();
+    match s.as_str() {
+        "daily" => return Ok([true; 7]),
+        "weekdays" => return Ok([true, true, true, true, true, false, false]),
+        "weekends" => return Ok([false, false, false, false, false, true, true]),
+        _ => {}
+    }
+    let day_index = |name: &str| {
+        DAY_NAMES
+            .iter()
+            .position(|d| name.starts_with(d))
+            .ok_or(anyhow!("unknown day \"{}\"", name))
+    };
+    let mut days = [false; 7];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (day_index(first)?, day_index(last)?);
+                // Ranges may wrap around the weekend, e.g. "fri-mon"
+                let mut d = first;
+                loop {
+                    days[d] = true;
+                    if d == last {
+                        break;
+                    }
+                    d = (d + 1) % 7;
+                }
+            }
+            None => days[day_index(part)?] = true,
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time_of_day(s: &str) -> anyhow::Result<TimeOfDay> {
+    let s = s.to_lowercase();
+    for (name, is_sunrise) in [("sunrise", true), ("sunset", false)] {
+        if let Some(offset) = s.strip_prefix(name) {
+            let minutes = if offset.is_empty() {
+                0
+            } else {
+                parse_offset_minutes(offset)?
+            };
+            return Ok(if is_sunrise {
+                TimeOfDay::Sunrise(minutes)
+            } else {
+                TimeOfDay::Sunset(minutes)
+            });
+        }
+    }
+    NaiveTime::parse_from_str(&s, "%H:%M")
+        .map(TimeOfDay::Fixed)
+        .map_err(|_| {
+            anyhow!(
+                "expected a time such as 08:00, sunrise or sunset+30m, got \"{}\"",
+                s
+            )
+        })
+}
+
+/// e.g. "+30m", "-1h", "+90" (minutes)
+fn parse_offset_minutes(s: &str) -> anyhow::Result<i64> {
+    let error = || anyhow!("invalid offset \"{}\"", s);
+    let (sign, rest) = match (s.strip_prefix('+'), s.strip_prefix('-')) {
+        (Some(rest), _) => (1, rest),
+        (_, Some(rest)) => (-1, rest),
+        _ => return Err(error()),
+    };
+    let (number, scale) = match rest.strip_suffix('h') {
+        Some(n) => (n, 60),
+        None => (rest.strip_suffix('m').unwrap_or(rest), 1),
+    };
+    let number: i64 = number.parse().map_err(|_| error())?;
+    Ok(sign * number * scale)
+}
+
+/// One crontab field: "*", "5", "1-5", "*/15", "0-30/10" or a comma-separated list
+/// of these; days of the week may also be given by name
+fn parse_cron_field(s: &str, min: u32, max: u32) -> anyhow::Result<Vec<bool>> {
+    let error = || anyhow!("invalid cron field \"{}\"", s);
+    let value = |v: &str| -> anyhow::Result<u32> {
+        match DAY_NAMES.iter().position(|d| v == *d) {
+            Some(i) => Ok((i as u32 + 1) % 7),
+            None => v.parse().map_err(|_| error()),
+        }
+    };
+    let mut matches = vec![false; max as usize + 1];
+    for part in s.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| error())?),
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (value(a)?, value(b)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if step == 0 || first < min || last > max || first > last {
+            return Err(error());
+        }
+        for v in (first..=last).step_by(step as usize) {
+            matches[v as usize] = true;
+        }
+    }
+    Ok(matches)
+}
+
+impl CronSpec {
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if !self.months[date.month() as usize] {
+            return false;
+        }
+        let day_of_month = self.days_of_month[date.day() as usize];
+        let day_of_week = self.days_of_week[date.weekday().num_days_from_sunday() as usize];
+        if self.either_day {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+impl Recurrence {
+    /// Every time this happens on the given (local) date, in order
+    fn times_on(&self, date: NaiveDate, location: Option<&Location>) -> Vec<DateTime<Local>> {
+        let local = |time: NaiveTime| Local.from_local_datetime(&date.and_time(time)).earliest();
+        match self {
+            Recurrence::Days { days, time } => {
+                if !days[date.weekday().num_days_from_monday() as usize] {
+                    return Vec::new();
+                }
+                let time = match time {
+                    TimeOfDay::Fixed(t) => local(*t),
+                    TimeOfDay::Sunrise(offset) | TimeOfDay::Sunset(offset) => location
+                        .and_then(|l| sun_times(date, l))
+                        .map(|(rise, set)| match time {
+                            TimeOfDay::Sunrise(_) => rise,
+                            _ => set,
+                        })
+                        .map(|t| t + Duration::minutes(*offset)),
+                };
+                time.into_iter().collect()
+            }
+            Recurrence::Cron(cron) => {
+                if !cron.matches_date(date) {
+                    return Vec::new();
+                }
+                let mut times = Vec::new();
+                for (hour, _) in cron.hours.iter().enumerate().filter(|(_, m)| **m) {
+                    for (minute, _) in cron.minutes.iter().enumerate().filter(|(_, m)| **m) {
+                        if let Some(t) =
+                            NaiveTime::from_hms_opt(hour as u32, minute as u32, 0).and_then(local)
+                        {
+                            times.push(t);
+                        }
+                    }
+                }
+                times
+            }
+        }
+    }
+
+    /// True if this depends on sunrise or sunset
+    pub fn needs_location(&self) -> bool {
+        matches!(
+            self,
+            Recurrence::Days {
+                time: TimeOfDay::Sunrise(_) | TimeOfDay::Sunset(_),
+                ..
+            }
+        )
+    }
+}
+
+/// Local sunrise and sunset on a date, using the standard sunrise equation;
+/// None if the sun does not rise or set that day
+pub fn sun_times(
+    date: NaiveDate,
+    location: &Location,
+) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    let to_radians = |d: f64| d * PI / 180.;
+    let days_since_j2000 = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64;
+
+    let mean_solar_time = days_since_j2000 - location.longitude / 360.;
+    let anomaly = (357.5291 + 0.98560028 * mean_solar_time).rem_euclid(360.);
+    let m = to_radians(anomaly);
+    let centre = 1.9148 * m.sin() + 0.02 * (2. * m).sin() + 0.0003 * (3. * m).sin();
+    let ecliptic_longitude = to_radians((anomaly + centre + 180. + 102.9372).rem_euclid(360.));
+    let transit =
+        2451545. + mean_solar_time + 0.0053 * m.sin() - 0.0069 * (2. * ecliptic_longitude).sin();
+    let declination = (ecliptic_longitude.sin() * to_radians(23.4397).sin()).asin();
+
+    let latitude = to_radians(location.latitude);
+    let cos_hour_angle = (to_radians(-0.833).sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1. ..=1.).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos() * 180. / PI;
+
+    let from_julian = |julian_day: f64| {
+        let seconds = (julian_day - 2440587.5) * 86400.;
+        Local.timestamp_opt(seconds.round() as i64, 0).single()
+    };
+    Some((
+        from_julian(transit - hour_angle / 360.)?,
+        from_julian(transit + hour_angle / 360.)?,
+    ))
+}
+
+impl ScheduleEntry {
+    /// The first time this entry happens strictly after the given time, if any
+    pub fn next_after(
+        &self,
+        after: DateTime<Local>,
+        location: Option<&Location>,
+    ) -> Option<DateTime<Local>> {
+        let recurrence: Recurrence = self.when.parse().ok()?;
+        // Start the day before, in case of offsets from sunset which cross midnight
+        let mut date = after.date_naive().pred_opt()?;
+        if let Some(from) = self.from {
+            date = date.max(from);
+        }
+        for _ in 0..LOOKAHEAD_DAYS {
+            if self.until.is_some_and(|until| date > until) {
+                return None;
+            }
+            if let Some(t) = recurrence
+                .times_on(date, location)
+                .into_iter()
+                .find(|t| *t > after)
+            {
+                return Some(t);
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+impl Schedule {
+    /// The next few events across all entries, in order
+    pub fn upcoming(
+        &self,
+        after: DateTime<Local>,
+        count: usize,
+    ) -> Vec<(DateTime<Local>, &ScheduleEntry)> {
+        let mut events = Vec::new();
+        for entry in self.entries.iter() {
+            let mut t = after;
+            for _ in 0..count {
+                let Some(next) = entry.next_after(t, self.location.as_ref()) else {
+                    break;
+                };
+                events.push((next, entry));
+                t = next;
+            }
+        }
+        events.sort_by_key(|(t, _)| *t);
+        events.truncate(count);
+        events
+    }
+}
+
+/// Runs a Schedule: remembers when each entry is next due, and hands over the
+/// actions of any which have come round
+#[derive(Default)]
+pub struct Scheduler {
+    schedule: Option<Schedule>,
+    next: Vec<Option<DateTime<Local>>>,
+}
+
+impl Scheduler {
+    /// Actions which have become due since the last call. Events missed while
+    /// not running (or before the schedule was changed) are not caught up on.
+    pub fn due(
+        &mut self,
+        schedule: Option<&Schedule>,
+        now: DateTime<Local>,
+    ) -> Vec<ScheduleAction> {
+        if schedule != self.schedule.as_ref() {
+            self.schedule = schedule.cloned();
+            self.next = match schedule {
+                Some(s) => s
+                    .entries
+                    .iter()
+                    .map(|e| e.next_after(now, s.location.as_ref()))
+                    .collect(),
+                None => Vec::new(),
+            };
+        }
+        let Some(schedule) = &self.schedule else {
+            return Vec::new();
+        };
+
+        let mut actions = Vec::new();
+        for (entry, next) in schedule.entries.iter().zip(self.next.iter_mut()) {
+            if next.is_some_and(|t| t <= now) {
+                actions.push(entry.action.clone());
+                // Skip over anything else that should have happened meanwhile
+                *next = entry.next_after(now, schedule.location.as_ref());
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+
+    fn local(y: i32, mo: u32, d: u32, h: u32, mi: u32, s: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, mo, d, h, mi, s).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn entry(when: &str) -> ScheduleEntry {
+        ScheduleEntry {
+            when: String::from(when),
+            from: None,
+            until: None,
+            action: ScheduleAction::Blackout { ms: None },
+        }
+    }
+
+    fn set(field: &[bool]) -> Vec<usize> {
+        field
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| **m)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn cron_fields() {
+        let field = |s| set(&parse_cron_field(s, 0, 59).unwrap());
+        assert_eq!(field("*").len(), 60);
+        assert_eq!(field("5"), [5]);
+        assert_eq!(field("1-5"), [1, 2, 3, 4, 5]);
+        assert_eq!(field("*/15"), [0, 15, 30, 45]);
+        assert_eq!(field("0-30/10"), [0, 10, 20, 30]);
+        assert_eq!(field("5/20"), [5, 25, 45]);
+        assert_eq!(field("1,3,40-42"), [1, 3, 40, 41, 42]);
+        assert_eq!(
+            set(&parse_cron_field("mon-fri", 0, 7).unwrap()),
+            [1, 2, 3, 4, 5]
+        );
+        assert_eq!(set(&parse_cron_field("sun", 0, 7).unwrap()), [0]);
+
+        for invalid in ["60", "5-1", "*/0", "x", "1-", "", "1,,2"] {
+            assert!(
+                parse_cron_field(invalid, 0, 59).is_err(),
+                "\"{}\" should not parse",
+                invalid
+            );
+        }
+        assert!(parse_cron_field("0", 1, 31).is_err());
+    }
+
+    #[test]
+    fn invalid_recurrences_are_rejected() {
+        for invalid in [
+            "* * * *",
+            "0 24 * * *",
+            "0 8 32 * *",
+            "0 8 * 13 *",
+            "0 8 * * 8",
+            "weekdays 25:00",
+            "funday 08:00",
+            "daily 8am",
+            "daily sunset+x",
+            "daily sunrise30m",
+            "daily",
+        ] {
+            assert!(
+                invalid.parse::<Recurrence>().is_err(),
+                "\"{}\" should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn days_and_ranges() {
+        let days = |s| parse_days(s).unwrap();
+        assert_eq!(
+            days("weekdays"),
+            [true, true, true, true, true, false, false]
+        );
+        assert_eq!(days("Sat,Sun"), days("weekends"));
+        assert_eq!(
+            days("fri-mon"),
+            [true, false, false, false, true, true, true]
+        );
+        assert_eq!(
+            days("tuesday"),
+            [false, true, false, false, false, false, false]
+        );
+    }
+
+    #[test]
+    fn cron_next_after() {
+        // Saturday morning, so the next weekday is Monday
+        let weekdays = entry("0 8 * * 1-5");
+        assert_eq!(
+            weekdays.next_after(local(2026, 10, 17, 10, 0, 0), None),
+            Some(local(2026, 10, 19, 8, 0, 0))
+        );
+
+        // Day of month and day of week both given: either matches
+        let either = entry("30 9 1 * mon");
+        let first = either.next_after(local(2026, 9, 30, 12, 0, 0), None);
+        assert_eq!(first, Some(local(2026, 10, 1, 9, 30, 0)));
+        assert_eq!(
+            either.next_after(first.unwrap(), None),
+            Some(local(2026, 10, 5, 9, 30, 0))
+        );
+
+        let quarters = entry("*/15 14 * * *");
+        assert_eq!(
+            quarters.next_after(local(2026, 10, 19, 14, 15, 0), None),
+            Some(local(2026, 10, 19, 14, 30, 0))
+        );
+    }
+
+    #[test]
+    fn date_ranges_are_inclusive() {
+        let mut daily = entry("daily 08:00");
+        daily.from = Some(date(2026, 10, 20));
+        daily.until = Some(date(2026, 10, 21));
+        assert_eq!(
+            daily.next_after(local(2026, 10, 1, 12, 0, 0), None),
+            Some(local(2026, 10, 20, 8, 0, 0))
+        );
+        assert_eq!(
+            daily.next_after(local(2026, 10, 20, 8, 0, 0), None),
+            Some(local(2026, 10, 21, 8, 0, 0))
+        );
+        assert_eq!(daily.next_after(local(2026, 10, 21, 9, 0, 0), None), None);
+    }
+
+    #[test]
+    fn london_midsummer() {
+        let (rise, set) = sun_times(date(2026, 6, 21), &LONDON).unwrap();
+        let utc = |h, m| Utc.with_ymd_and_hms(2026, 6, 21, h, m, 0).unwrap();
+        let near = |t: DateTime<Local>, expected: DateTime<Utc>| {
+            (t.with_timezone(&Utc) - expected).num_seconds().abs() < 60
+        };
+        assert!(
+            near(rise, utc(3, 43)),
+            "sunrise at {}",
+            rise.with_timezone(&Utc)
+        );
+        assert!(
+            near(set, utc(20, 21)),
+            "sunset at {}",
+            set.with_timezone(&Utc)
+        );
+
+        let before_sunset = entry("daily sunset-30m");
+        let next = before_sunset
+            .next_after(set - Duration::hours(1), Some(&LONDON))
+            .unwrap();
+        assert_eq!(next, set - Duration::minutes(30));
+    }
+
+    #[test]
+    fn no_sunrise_or_sunset_in_polar_day_and_night() {
+        let tromso = Location {
+            latitude: 69.65,
+            longitude: 18.96,
+        };
+        assert!(sun_times(date(2026, 6, 21), &tromso).is_none());
+        assert!(sun_times(date(2026, 12, 21), &tromso).is_none());
+        assert!(sun_times(date(2026, 3, 21), &tromso).is_some());
+
+        // Skipped until the sun sets again
+        let next = entry("daily sunset")
+            .next_after(local(2026, 6, 21, 12, 0, 0), Some(&tromso))
+            .unwrap();
+        assert!(next.date_naive() > date(2026, 7, 15));
+    }
+
+    #[test]
+    fn scheduler_fires_once_per_event() {
+        let schedule = Schedule {
+            location: None,
+            entries: vec![entry("* * * * *"), entry("daily 08:00")],
+        };
+        let mut scheduler = Scheduler::default();
+        assert!(scheduler
+            .due(Some(&schedule), local(2026, 10, 19, 7, 59, 50))
+            .is_empty());
+        assert_eq!(
+            scheduler
+                .due(Some(&schedule), local(2026, 10, 19, 8, 0, 0))
+                .len(),
+            2
+        );
+        for s in [1, 10, 59] {
+            assert!(scheduler
+                .due(Some(&schedule), local(2026, 10, 19, 8, 0, s))
+                .is_empty());
+        }
+        assert_eq!(
+            scheduler
+                .due(Some(&schedule), local(2026, 10, 19, 8, 1, 0))
+                .len(),
+            1
+        );
+
+        // Removing the schedule stops it
+        assert!(scheduler.due(None, local(2026, 10, 19, 8, 2, 0)).is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{system_clock, SharedClock},
+    timecode::{FrameRate, Timecode},
+};
+
+use super::schedule::ScheduleAction;
+
+/// Cues fired by timecode, e.g. for shows synced to video or audio
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Timeline {
+    /// Used for cue times and for timecode received via Tether
+    #[serde(default)]
+    pub frame_rate: FrameRate,
+    /// How long to keep running by ourselves after timecode stops arriving
+    #[serde(default = "default_freewheel_ms")]
+    pub freewheel_ms: u64,
+    /// A change in timecode bigger than this (either way) is a jump, rather than playback
+    #[serde(default = "default_jump_ms")]
+    pub jump_ms: u64,
+    /// After a jump, go straight to the state of the last cue before the new
+    /// position; otherwise only cues after it fire
+    #[serde(default = "default_true")]
+    pub chase: bool,
+    pub cues: Vec<TimelineCue>,
+}
+
+fn default_freewheel_ms() -> u64 {
+    2000
+}
+
+fn default_jump_ms() -> u64 {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineCue {
+    pub at: Timecode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub action: ScheduleAction,
+}
+
+impl Timeline {
+    /// Time of the last cue, in ms
+    pub fn duration_ms(&self) -> u64 {
+        self.cues
+            .iter()
+            .map(|c| c.at.to_ms(self.frame_rate))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimecodeSource {
+    ArtNet,
+    Tether,
+}
+
+/// Where the timeline's position comes from
+#[derive(Clone, Copy, Debug)]
+enum Clock {
+    Stopped(u64),
+    /// Playing from the given position (ms), since the given time
+    Internal(u64, Instant),
+    /// Last timecode received, and when; freewheels in between
+    External(u64, Instant, TimecodeSource),
+}
+
+/// Plays a Timeline: follows timecode (or its own clock) and hands over the
+/// actions of cues as their time comes round
+pub struct TimelinePlayer {
+    time: SharedClock,
+    clock: Clock,
+    /// Cues at or before this position (ms) have already fired
+    fired_until: Option<u64>,
+    /// Moved by locate() since the last call to due()
+    located: bool,
+}
+
+impl Default for TimelinePlayer {
+    fn default() -> Self {
+        TimelinePlayer::new(system_clock())
+    }
+}
+
+impl TimelinePlayer {
+    pub fn new(time: SharedClock) -> Self {
+        TimelinePlayer {
+            time,
+            clock: Clock::Stopped(0),
+            fired_until: None,
+            located: false,
+        }
+    }
+
+    fn since(&self, then: Instant) -> Duration {
+        self.time.now().saturating_duration_since(then)
+    }
+
+    pub fn position_ms(&self, timeline: &Timeline) -> u64 {
+        match self.clock {
+            Clock::Stopped(p) => p,
+            Clock::Internal(p, since) => p + self.since(since).as_millis() as u64,
+            Clock::External(p, since, _) => {
+                p + self
+                    .since(since)
+                    .min(Duration::from_millis(timeline.freewheel_ms))
+                    .as_millis() as u64
+            }
+        }
+    }
+
+    pub fn is_running(&self, timeline: &Timeline) -> bool {
+        match self.clock {
+            Clock::Stopped(_) => false,
+            Clock::Internal(..) => true,
+            Clock::External(_, since, _) => {
+                self.since(since) < Duration::from_millis(timeline.freewheel_ms)
+            }
+        }
+    }
+
+    /// A human-readable description of what the timeline is following
+    pub fn status(&self, timeline: &Timeline) -> String {
+        match self.clock {
+            Clock::Stopped(_) => String::from("Stopped"),
+            Clock::Internal(..) => String::from("Playing (internal clock)"),
+            Clock::External(_, since, source) => {
+                if self.since(since) < Duration::from_millis(100) {
+                    format!("Following {:?} timecode", source)
+                } else if self.is_running(timeline) {
+                    format!("Freewheeling ({:?} timecode lost)", source)
+                } else {
+                    format!("Stopped ({:?} timecode lost)", source)
+                }
+            }
+        }
+    }
+
+    /// Timecode has arrived from outside
+    pub fn receive(&mut self, position_ms: u64, source: TimecodeSource) {
+        self.clock = Clock::External(position_ms, self.time.now(), source);
+    }
+
+    pub fn play(&mut self, timeline: &Timeline) {
+        self.clock = Clock::Internal(self.position_ms(timeline), self.time.now());
+    }
+
+    pub fn stop(&mut self, timeline: &Timeline) {
+        self.clock = Clock::Stopped(self.position_ms(timeline));
+    }
+
+    /// Move to a new position (e.g. scrubbing), keeping the internal clock
+    /// running if it was
+    pub fn locate(&mut self, position_ms: u64) {
+        self.clock = match self.clock {
+            Clock::Internal(..) => Clock::Internal(position_ms, self.time.now()),
+            _ => Clock::Stopped(position_ms),
+        };
+        self.located = true;
+    }
+
+    /// Actions of any cues which have come round since the last call. After a
+    /// jump (or the first time), only the last cue before the new position
+    /// fires, immediately, if the timeline chases. A stopped timeline only
+    /// chases when located, so nothing fires before any timecode arrives.
+    pub fn due(&mut self, timeline: Option<&Timeline>) -> Vec<ScheduleAction> {
+        let Some(timeline) = timeline else {
+            self.fired_until = None;
+            return Vec::new();
+        };
+        let position = self.position_ms(timeline);
+        let rate = timeline.frame_rate;
+
+        let previous = match self.fired_until {
+            // Timecode arriving a little behind where we had freewheeled to
+            Some(p) if position <= p && p - position <= timeline.jump_ms => return Vec::new(),
+            Some(p) if position > p && position - p <= timeline.jump_ms => Some(p),
+            _ => None,
+        };
+        let located = std::mem::take(&mut self.located);
+        if previous.is_none() && !located && !self.is_running(timeline) {
+            return Vec::new();
+        }
+        self.fired_until = Some(position);
+
+        let Some(previous) = previous else {
+            if !timeline.chase {
+                return Vec::new();
+            }
+            let last_cue = timeline
+                .cues
+                .iter()
+                .filter(|c| c.at.to_ms(rate) <= position)
+                .max_by_key(|c| c.at);
+            return last_cue
+                .map(|c| match c.action.clone() {
+                    ScheduleAction::GoScene {
+                        scene,
+                        fixture_labels,
+                        ..
+                    } => ScheduleAction::GoScene {
+                        scene,
+                        ms: None,
+                        fixture_labels,
+                    },
+                    ScheduleAction::Blackout { .. } => ScheduleAction::Blackout { ms: None },
+                })
+                .into_iter()
+                .collect();
+        };
+
+        let mut cues: Vec<&TimelineCue> = timeline
+            .cues
+            .iter()
+            .filter(|c| {
+                let at = c.at.to_ms(rate);
+                at > previous && at <= position
+            })
+            .collect();
+        cues.sort_by_key(|c| c.at);
+        cues.into_iter().map(|c| c.action.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{TimecodeSource, Timeline, TimelineCue, TimelinePlayer};
+    use crate::{
+        clock::ManualClock,
+        project::schedule::ScheduleAction,
+        timecode::{FrameRate, Timecode},
+    };
+
+    fn blackout_at(at: &str) -> TimelineCue {
+        TimelineCue {
+            at: at.parse().unwrap(),
+            label: None,
+            action: ScheduleAction::Blackout { ms: Some(500) },
+        }
+    }
+
+    fn timeline() -> Timeline {
+        Timeline {
+            frame_rate: FrameRate::Ebu,
+            freewheel_ms: 2000,
+            jump_ms: 1000,
+            chase: true,
+            cues: vec![blackout_at("00:00:01:00"), blackout_at("00:00:02:00")],
+        }
+    }
+
+    #[test]
+    fn cues_fire_once_as_internal_clock_plays() {
+        let clock = Arc::new(ManualClock::new());
+        let mut player = TimelinePlayer::new(clock.clone());
+        let timeline = timeline();
+
+        player.play(&timeline);
+        // Nothing before the first cue to chase
+        assert!(player.due(Some(&timeline)).is_empty());
+
+        clock.advance_ms(999);
+        assert!(player.due(Some(&timeline)).is_empty());
+        clock.advance_ms(1);
+        assert_eq!(player.due(Some(&timeline)).len(), 1);
+        assert!(player.due(Some(&timeline)).is_empty());
+
+        clock.advance_ms(1000);
+        assert_eq!(player.position_ms(&timeline), 2000);
+        assert_eq!(player.due(Some(&timeline)).len(), 1);
+
+        player.stop(&timeline);
+        clock.advance_ms(1000);
+        assert_eq!(player.position_ms(&timeline), 2000);
+    }
+
+    #[test]
+    fn external_timecode_freewheels_then_stops() {
+        let clock = Arc::new(ManualClock::new());
+        let mut player = TimelinePlayer::new(clock.clone());
+        let timeline = timeline();
+
+        let at = Timecode::from_ms(10_000, timeline.frame_rate);
+        player.receive(at.to_ms(timeline.frame_rate), TimecodeSource::ArtNet);
+        clock.advance_ms(1500);
+        assert!(player.is_running(&timeline));
+        assert_eq!(player.position_ms(&timeline), 11_500);
+
+        clock.advance_ms(1500);
+        assert!(!player.is_running(&timeline));
+        assert_eq!(player.position_ms(&timeline), 12_000);
+    }
+
+    #[test]
+    fn nothing_fires_before_timecode_arrives() {
+        let clock = Arc::new(ManualClock::new());
+        let mut player = TimelinePlayer::new(clock.clone());
+        let mut timeline = timeline();
+        timeline.cues.push(blackout_at("00:00:00:00"));
+
+        for _ in 0..3 {
+            assert!(player.due(Some(&timeline)).is_empty());
+            clock.advance_ms(500);
+        }
+
+        // ...and then the cue at the start fires as soon as it does
+        player.receive(0, TimecodeSource::ArtNet);
+        assert_eq!(
+            player.due(Some(&timeline)),
+            vec![ScheduleAction::Blackout { ms: None }]
+        );
+    }
+
+    #[test]
+    fn jump_chases_last_cue_without_fade() {
+        let clock = Arc::new(ManualClock::new());
+        let mut player = TimelinePlayer::new(clock.clone());
+        let timeline = timeline();
+
+        player.due(Some(&timeline));
+        player.locate(30_000);
+        assert_eq!(
+            player.due(Some(&timeline)),
+            vec![ScheduleAction::Blackout { ms: None }]
+        );
+    }
+}
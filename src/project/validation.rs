@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+
+use crate::settings::CHANNELS_PER_UNIVERSE;
+
+use super::{
+    curve::OutputCurve,
+    scene::SceneValue,
+    schedule::{Recurrence, ScheduleAction},
+    Project,
+};
+
+/// Problems found by [`Project::validate`]. Errors mean the project will not
+/// work as intended; warnings are probably (but not definitely) mistakes.
+#[derive(Default)]
+pub struct ValidationReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Project {
+    /// Check a loaded project for problems that serde cannot catch, e.g. duplicate
+    /// labels, fixtures that do not match any known config, overlapping channels
+    /// and Scenes that refer to fixtures or macros which do not exist.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        let mut fixture_labels = HashSet::new();
+        for fixture in self.fixtures.iter() {
+            if !fixture_labels.insert(fixture.label.to_lowercase()) {
+                report
+                    .errors
+                    .push(format!("Duplicate fixture label \"{}\"", &fixture.label));
+            }
+            if fixture.config.name.is_empty() {
+                report.errors.push(format!(
+                    "Fixture \"{}\" has config name \"{}\" which does not match any known fixture",
+                    &fixture.label, &fixture.config_name
+                ));
+                continue;
+            }
+            if fixture.start_channel == 0 {
+                report.errors.push(format!(
+                    "Fixture \"{}\" has start channel 0, but channels are one-indexed",
+                    &fixture.label
+                ));
+                continue;
+            }
+            for channel in fixture.output_overrides.keys() {
+                if !fixture
+                    .config
+                    .active_mode
+                    .mappings
+                    .iter()
+                    .any(|m| m.channel == *channel)
+                {
+                    report.warnings.push(format!(
+                        "Fixture \"{}\" overrides output for channel {}, which it does not have",
+                        &fixture.label, channel
+                    ));
+                }
+            }
+            for (channel, limits) in fixture.output_limits() {
+                if limits.min.unwrap_or(0) > limits.max.unwrap_or(u8::MAX) {
+                    report.errors.push(format!(
+                        "Fixture \"{}\" channel {} has output min above max",
+                        &fixture.label, channel
+                    ));
+                }
+                if let Some(OutputCurve::Table(points)) = &limits.curve {
+                    if points.len() < 2 {
+                        report.errors.push(format!(
+                            "Fixture \"{}\" channel {} has an output table with fewer than 2 points",
+                            &fixture.label, channel
+                        ));
+                    }
+                }
+            }
+            if let Some((_first, last)) = fixture.channel_range() {
+                if last > CHANNELS_PER_UNIVERSE {
+                    report.errors.push(format!(
+                        "Fixture \"{}\" ends on channel {}, beyond the end of the universe",
+                        &fixture.label, last
+                    ));
+                }
+            }
+        }
+
+        for (i, a) in self.fixtures.iter().enumerate() {
+            for b in self.fixtures.iter().skip(i + 1) {
+                if let (Some((a_first, a_last)), Some((b_first, b_last))) =
+                    (a.channel_range(), b.channel_range())
+                {
+                    if a_first <= b_last && b_first <= a_last {
+                        report.warnings.push(format!(
+                            "Fixtures \"{}\" (CH{}-{}) and \"{}\" (CH{}-{}) overlap",
+                            &a.label, a_first, a_last, &b.label, b_first, b_last
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut group_labels = HashSet::new();
+        for group in self.groups.iter() {
+            if !group_labels.insert(group.label.to_lowercase()) {
+                report
+                    .errors
+                    .push(format!("Duplicate group label \"{}\"", &group.label));
+            }
+            if fixture_labels.contains(&group.label.to_lowercase()) {
+                report.errors.push(format!(
+                    "Group \"{}\" has the same label as a fixture",
+                    &group.label
+                ));
+            }
+            for member in group.fixtures.iter() {
+                if !fixture_labels.contains(&member.to_lowercase()) {
+                    report.warnings.push(format!(
+                        "Group \"{}\" refers to unknown fixture \"{}\"",
+                        &group.label, member
+                    ));
+                }
+            }
+        }
+
+        let mut palette_labels = HashSet::new();
+        for palette in self.palettes.iter() {
+            if !palette_labels.insert(palette.label.to_lowercase()) {
+                report
+                    .errors
+                    .push(format!("Duplicate palette label \"{}\"", &palette.label));
+            }
+            if palette
+                .values
+                .values()
+                .any(|v| matches!(v, SceneValue::Palette(_)))
+            {
+                report.errors.push(format!(
+                    "Palette \"{}\" refers to another palette",
+                    &palette.label
+                ));
+            }
+        }
+
+        for park in self.parks.iter() {
+            let channels = park.target.channels(&self.fixtures);
+            if channels.is_empty() {
+                report.warnings.push(format!(
+                    "Park \"{}\" does not match any fixture channel or macro",
+                    park.target.describe()
+                ));
+            }
+            if channels
+                .iter()
+                .any(|c| *c == 0 || *c > CHANNELS_PER_UNIVERSE)
+            {
+                report.errors.push(format!(
+                    "Park \"{}\" is outside the universe",
+                    park.target.describe()
+                ));
+            }
+        }
+
+        if let Some(safety) = &self.safety {
+            if safety.max_flash_hz <= 0. {
+                report
+                    .errors
+                    .push(String::from("Safety maxFlashHz must be more than zero"));
+            }
+            if !(0. ..=1.).contains(&safety.max_flash_area) {
+                report
+                    .errors
+                    .push(String::from("Safety maxFlashArea must be in the range 0-1"));
+            }
+            if safety.flash_threshold == 0 {
+                report
+                    .errors
+                    .push(String::from("Safety flashThreshold must be more than zero"));
+            }
+            if !safety.enabled {
+                report
+                    .warnings
+                    .push(String::from("Safety limiter is configured but disabled"));
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            if let Some(location) = &schedule.location {
+                if !(-90. ..=90.).contains(&location.latitude)
+                    || !(-180. ..=180.).contains(&location.longitude)
+                {
+                    report.errors.push(String::from(
+                        "Schedule location is not a valid latitude/longitude",
+                    ));
+                }
+            }
+            for entry in schedule.entries.iter() {
+                match entry.when.parse::<Recurrence>() {
+                    Ok(recurrence) => {
+                        if recurrence.needs_location() && schedule.location.is_none() {
+                            report.errors.push(format!(
+                                "Schedule entry \"{}\" needs a location for sunrise/sunset",
+                                &entry.when
+                            ));
+                        }
+                    }
+                    Err(e) => report
+                        .errors
+                        .push(format!("Schedule entry \"{}\": {}", &entry.when, e)),
+                }
+                if let (Some(from), Some(until)) = (entry.from, entry.until) {
+                    if from > until {
+                        report.warnings.push(format!(
+                            "Schedule entry \"{}\" ends before it starts, so never happens",
+                            &entry.when
+                        ));
+                    }
+                }
+                if let ScheduleAction::GoScene { scene, .. } = &entry.action {
+                    if !self
+                        .scenes
+                        .iter()
+                        .any(|s| s.label.eq_ignore_ascii_case(scene))
+                    {
+                        report.errors.push(format!(
+                            "Schedule entry \"{}\" refers to unknown scene \"{}\"",
+                            &entry.when, scene
+                        ));
+                    }
+                }
+            }
+        }
+
+        if let Some(timeline) = &self.timeline {
+            for cue in timeline.cues.iter() {
+                if !cue.at.is_valid(timeline.frame_rate) {
+                    report.errors.push(format!(
+                        "Timeline cue at {} is not a valid timecode at the timeline's frame rate",
+                        cue.at
+                    ));
+                }
+                if let ScheduleAction::GoScene { scene, .. } = &cue.action {
+                    if !self
+                        .scenes
+                        .iter()
+                        .any(|s| s.label.eq_ignore_ascii_case(scene))
+                    {
+                        report.errors.push(format!(
+                            "Timeline cue at {} refers to unknown scene \"{}\"",
+                            cue.at, scene
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut scene_labels = HashSet::new();
+        for scene in self.scenes.iter() {
+            if !scene_labels.insert(scene.label.to_lowercase()) {
+                report
+                    .warnings
+                    .push(format!("Duplicate scene label \"{}\"", &scene.label));
+            }
+            for (fixture_label, macros) in scene.state.iter() {
+                for (macro_label, value) in macros.iter() {
+                    if let SceneValue::Palette(palette_label) = value {
+                        match self.find_palette(palette_label) {
+                            None => report.errors.push(format!(
+                                "Scene \"{}\" refers to unknown palette \"{}\"",
+                                &scene.label, palette_label
+                            )),
+                            Some(palette) if !palette.values.contains_key(macro_label) => {
+                                report.warnings.push(format!(
+                                    "Scene \"{}\" uses palette \"{}\" for macro \"{}\", which it does not set",
+                                    &scene.label, palette_label, macro_label
+                                ))
+                            }
+                            Some(_) => {}
+                        }
+                    }
+                }
+                match self
+                    .fixtures
+                    .iter()
+                    .find(|f| f.label.eq_ignore_ascii_case(fixture_label))
+                {
+                    Some(fixture) => {
+                        for macro_label in macros.keys() {
+                            if !fixture
+                                .config
+                                .active_mode
+                                .macros
+                                .iter()
+                                .any(|m| m.matches_label(macro_label))
+                            {
+                                report.warnings.push(format!(
+                                    "Scene \"{}\" sets macro \"{}\" which fixture \"{}\" does not have",
+                                    &scene.label, macro_label, fixture_label
+                                ));
+                            }
+                        }
+                    }
+                    None => {
+                        if self.find_group(fixture_label).is_none() {
+                            report.warnings.push(format!(
+                                "Scene \"{}\" refers to unknown fixture or group \"{}\"",
+                                &scene.label, fixture_label
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(port_address) = &self.artnet_port_address {
+            if !port_address.is_valid() {
+                report.errors.push(format!(
+                    "ArtNet Port-Address {} is out of range (net 0-127, sub-net 0-15, universe 0-15)",
+                    port_address
+                ));
+            }
+        }
+
+        report
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use artnet_protocol::{ArtCommand, PollReply};
+use log::*;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::timecode::{FrameRate, Timecode};
+
+pub const ARTNET_PORT: u16 = 6454;
+
+/// Listen on this port of every interface, sharing it (SO_REUSEADDR) with
+/// anything else on this machine which listens for ArtNet the same way
+pub fn bind_shared(port: u16) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    Ok(socket.into())
+}
+
+/// What a VirtualReceiver calls itself in ArtPollReply
+const SHORT_NAME: &str = "Virtual receiver";
+
+/// The ArtNet packets a VirtualReceiver understands
+#[derive(Debug, Clone)]
+pub enum ReceivedPacket {
+    Dmx {
+        port_address: u16,
+        sequence: u8,
+        data: Vec<u8>,
+    },
+    Poll,
+    Sync,
+    TimeCode(Timecode, FrameRate),
+    /// Valid ArtNet, but not something we display
+    Other,
+}
+
+impl fmt::Display for ReceivedPacket {
+    /// One line per packet; DMX frames list only the non-zero channels
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceivedPacket::Dmx {
+                port_address,
+                sequence,
+                data,
+            } => {
+                write!(f, "ArtDmx universe {} seq {}:", port_address, sequence)?;
+                for (i, value) in data.iter().enumerate().filter(|(_, v)| **v > 0) {
+                    write!(f, " {}={}", i + 1, value)?;
+                }
+                Ok(())
+            }
+            ReceivedPacket::Poll => write!(f, "ArtPoll"),
+            ReceivedPacket::Sync => write!(f, "ArtSync"),
+            ReceivedPacket::TimeCode(timecode, rate) => {
+                write!(f, "ArtTimeCode {} ({} fps)", timecode, rate.fps())
+            }
+            ReceivedPacket::Other => write!(f, "(other ArtNet packet)"),
+        }
+    }
+}
+
+/// Latest data for a universe, as seen by a VirtualReceiver
+pub struct ReceivedUniverse {
+    pub data: Vec<u8>,
+    pub from: SocketAddr,
+    pub frames: usize,
+    pub last_received: Instant,
+}
+
+/// Listens for ArtNet the way a node would, for testing without one
+pub struct VirtualReceiver {
+    socket: UdpSocket,
+    pub universes: BTreeMap<u16, ReceivedUniverse>,
+    pub syncs: usize,
+    pub last_timecode: Option<(Timecode, FrameRate)>,
+}
+
+impl VirtualReceiver {
+    pub fn bind(port: u16) -> anyhow::Result<Self> {
+        let socket =
+            bind_shared(port).map_err(|e| anyhow!("Could not listen on port {}: {}", port, e))?;
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        Ok(VirtualReceiver {
+            socket,
+            universes: BTreeMap::new(),
+            syncs: 0,
+            last_timecode: None,
+        })
+    }
+
+    /// How long receive() waits for a packet
+    pub fn set_timeout(&self, timeout: Duration) -> anyhow::Result<()> {
+        self.socket.set_read_timeout(Some(timeout))?;
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Wait (briefly) for the next ArtNet packet, and keep track of what it
+    /// contains; None if nothing valid arrived in time
+    pub fn receive(&mut self) -> Option<(ReceivedPacket, SocketAddr)> {
+        let mut buffer = [0u8; 1024];
+        let (length, from) = self.socket.recv_from(&mut buffer).ok()?;
+        let bytes = &buffer[..length];
+
+        let packet = if let Some((timecode, rate)) = Timecode::from_art_net(bytes) {
+            self.last_timecode = Some((timecode, rate));
+            ReceivedPacket::TimeCode(timecode, rate)
+        } else {
+            match ArtCommand::from_buffer(bytes) {
+                Ok(ArtCommand::Output(output)) => {
+                    let port_address: u16 = output.port_address.into();
+                    let data: Vec<u8> = output.data.as_ref().clone();
+                    let universe =
+                        self.universes
+                            .entry(port_address)
+                            .or_insert_with(|| ReceivedUniverse {
+                                data: Vec::new(),
+                                from,
+                                frames: 0,
+                                last_received: Instant::now(),
+                            });
+                    universe.data.clone_from(&data);
+                    universe.from = from;
+                    universe.frames += 1;
+                    universe.last_received = Instant::now();
+                    ReceivedPacket::Dmx {
+                        port_address,
+                        sequence: output.sequence,
+                        data,
+                    }
+                }
+                Ok(ArtCommand::Poll(_)) => {
+                    self.reply_to_poll(from);
+                    ReceivedPacket::Poll
+                }
+                Ok(ArtCommand::Sync) => {
+                    self.syncs += 1;
+                    ReceivedPacket::Sync
+                }
+                Ok(_) => ReceivedPacket::Other,
+                Err(e) => {
+                    debug!("Ignoring packet from {}: {}", from, e);
+                    return None;
+                }
+            }
+        };
+        Some((packet, from))
+    }
+
+    /// Answer an ArtPoll the way a node would, so that the controller can see
+    /// this receiver is reachable. The reply goes back to the address the
+    /// poll came from.
+    fn reply_to_poll(&self, to: SocketAddr) {
+        let address = match self.socket.local_addr() {
+            Ok(SocketAddr::V4(local)) => *local.ip(),
+            _ => Ipv4Addr::UNSPECIFIED,
+        };
+        let mut short_name = [0u8; 18];
+        short_name[..SHORT_NAME.len()].copy_from_slice(SHORT_NAME.as_bytes());
+        let mut long_name = [0u8; 64];
+        long_name[..SHORT_NAME.len()].copy_from_slice(SHORT_NAME.as_bytes());
+        let reply = PollReply {
+            address,
+            port: ARTNET_PORT,
+            version: [0, 14],
+            port_address: [0, 0],
+            oem: [0, 0],
+            ubea_version: 0,
+            status_1: 0,
+            esta_code: 0,
+            short_name,
+            long_name,
+            node_report: [0; 64],
+            num_ports: [0, 1],
+            // One DMX output port
+            port_types: [0x80, 0, 0, 0],
+            good_input: [0; 4],
+            good_output: [0x80, 0, 0, 0],
+            swin: [0; 4],
+            swout: [0; 4],
+            sw_video: 0,
+            sw_macro: 0,
+            sw_remote: 0,
+            spare: [0; 3],
+            style: 0,
+            mac: [0; 6],
+            bind_ip: [0; 4],
+            bind_index: 0,
+            status_2: 0,
+            filler: [0; 26],
+        };
+        match ArtCommand::PollReply(Box::new(reply)).write_to_buffer() {
+            Ok(packet) => {
+                if let Err(e) = self.socket.send_to(&packet, to) {
+                    debug!("Could not reply to ArtPoll from {}: {}", to, e);
+                }
+            }
+            Err(e) => error!("Could not build ArtPollReply: {}", e),
+        }
+    }
+}
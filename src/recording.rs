@@ -0,0 +1,373 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use log::*;
+
+use crate::{artnet::PortAddress, settings::CHANNELS_PER_UNIVERSE};
+
+/// Start of every recording file, followed by a format version byte
+const MAGIC: &[u8; 6] = b"TACDMX";
+const VERSION: u8 = 2;
+
+/// How often the recording is flushed to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One output frame, stored as the channels which changed since the frame before
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    /// Time since the start of the recording
+    pub ms: u32,
+    /// Channel index (from 0) and new value
+    pub changes: Vec<(u16, u8)>,
+}
+
+/// Writes output frames to a file as they are sent.
+///
+/// The file is a header (magic, version, Port-Address as u16) then, for each
+/// frame, the time in ms (u32), the number of changed channels (u16) and each
+/// change as channel index (u16) plus value (u8); all little-endian.
+pub struct Recorder {
+    path: String,
+    writer: BufWriter<File>,
+    /// When the first frame was recorded
+    started: Option<Instant>,
+    last_flushed: Instant,
+    previous: Vec<u8>,
+    frames: usize,
+}
+
+impl Recorder {
+    pub fn create(path: &str, port_address: PortAddress) -> anyhow::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        writer.write_all(&u16::from(port_address).to_le_bytes())?;
+        info!("Recording output to \"{}\"", path);
+        Ok(Recorder {
+            path: String::from(path),
+            writer,
+            started: None,
+            last_flushed: Instant::now(),
+            previous: [0].repeat(CHANNELS_PER_UNIVERSE as usize),
+            frames: 0,
+        })
+    }
+
+    pub fn record(&mut self, channels: &[u8]) {
+        let ms = self
+            .started
+            .get_or_insert_with(Instant::now)
+            .elapsed()
+            .as_millis() as u32;
+        let changes: Vec<(u16, u8)> = channels
+            .iter()
+            .enumerate()
+            .filter(|(i, value)| self.previous.get(*i) != Some(value))
+            .map(|(i, value)| (i as u16, *value))
+            .collect();
+        if let Err(e) = self.write_frame(ms, &changes) {
+            error!("Error writing to recording \"{}\": {}", self.path, e);
+            return;
+        }
+        self.previous = channels.to_vec();
+        self.frames += 1;
+
+        if self.last_flushed.elapsed() > FLUSH_INTERVAL {
+            self.last_flushed = Instant::now();
+            if let Err(e) = self.writer.flush() {
+                error!("Error flushing recording \"{}\": {}", self.path, e);
+            }
+        }
+    }
+
+    fn write_frame(&mut self, ms: u32, changes: &[(u16, u8)]) -> std::io::Result<()> {
+        self.writer.write_all(&ms.to_le_bytes())?;
+        self.writer
+            .write_all(&(changes.len() as u16).to_le_bytes())?;
+        for (channel, value) in changes {
+            self.writer.write_all(&channel.to_le_bytes())?;
+            self.writer.write_all(&[*value])?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            error!("Error flushing recording \"{}\": {}", self.path, e);
+        }
+        info!("Recorded {} frames to \"{}\"", self.frames, self.path);
+    }
+}
+
+/// A recording loaded from file
+pub struct Recording {
+    pub port_address: PortAddress,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|file| BufReader::new(file).read_to_end(&mut bytes))
+            .map_err(|e| anyhow!("Could not read recording \"{}\": {}", path, e))?;
+
+        if bytes.len() < MAGIC.len() + 2 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(anyhow!("\"{}\" is not a DMX recording", path));
+        }
+        let version = bytes[MAGIC.len()];
+        let header = &bytes[MAGIC.len() + 1..];
+        let (port_address, mut rest) = match version {
+            VERSION if header.len() >= 2 => {
+                (u16::from_le_bytes([header[0], header[1]]), &header[2..])
+            }
+            VERSION => return Err(anyhow!("Recording \"{}\" is truncated", path)),
+            1 => {
+                return Err(anyhow!(
+                    "Recording \"{}\" is version 1 (universe only), which is no longer supported; record it again",
+                    path
+                ))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Recording \"{}\" has unsupported version {}",
+                    path,
+                    version
+                ))
+            }
+        };
+        let port_address = PortAddress::try_from(port_address)
+            .map_err(|e| anyhow!("Recording \"{}\": {}", path, e))?;
+
+        let mut frames = Vec::new();
+        while !rest.is_empty() {
+            let (frame, remaining) =
+                read_frame(rest).ok_or_else(|| anyhow!("Recording \"{}\" is truncated", path))?;
+            frames.push(frame);
+            rest = remaining;
+        }
+
+        Ok(Recording {
+            port_address,
+            frames,
+        })
+    }
+
+    pub fn duration_ms(&self) -> u32 {
+        self.frames.last().map(|f| f.ms).unwrap_or(0)
+    }
+
+    /// Write every frame as a row of all channel values
+    pub fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        write!(out, "ms,universe")?;
+        for channel in 1..=CHANNELS_PER_UNIVERSE {
+            write!(out, ",{}", channel)?;
+        }
+        writeln!(out)?;
+
+        let mut channels = [0].repeat(CHANNELS_PER_UNIVERSE as usize);
+        for frame in self.frames.iter() {
+            apply_changes(&mut channels, frame);
+            write!(out, "{},{}", frame.ms, u16::from(self.port_address))?;
+            for value in channels.iter() {
+                write!(out, ",{}", value)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_frame(bytes: &[u8]) -> Option<(RecordedFrame, &[u8])> {
+    let ms = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?);
+    let count = u16::from_le_bytes(bytes.get(4..6)?.try_into().ok()?) as usize;
+    let body = bytes.get(6..6 + count * 3)?;
+    let changes = body
+        .chunks_exact(3)
+        .map(|c| (u16::from_le_bytes([c[0], c[1]]), c[2]))
+        .collect();
+    Some((RecordedFrame { ms, changes }, &bytes[6 + count * 3..]))
+}
+
+fn apply_changes(channels: &mut [u8], frame: &RecordedFrame) {
+    for (channel, value) in frame.changes.iter() {
+        if let Some(c) = channels.get_mut(*channel as usize) {
+            *c = *value;
+        }
+    }
+}
+
+/// Steps through a Recording in time, keeping the current channel values
+pub struct RecordingPlayer<'a> {
+    recording: &'a Recording,
+    channels: Vec<u8>,
+    /// Index of the next frame to apply
+    next: usize,
+}
+
+impl<'a> RecordingPlayer<'a> {
+    pub fn new(recording: &'a Recording) -> Self {
+        RecordingPlayer {
+            recording,
+            channels: [0].repeat(CHANNELS_PER_UNIVERSE as usize),
+            next: 0,
+        }
+    }
+
+    pub fn channels(&self) -> &[u8] {
+        &self.channels
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.frames.len()
+    }
+
+    /// Go back to the start, with all channels at zero
+    pub fn rewind(&mut self) {
+        self.channels.fill(0);
+        self.next = 0;
+    }
+
+    /// Apply all frames up to the given time; true if any were applied
+    pub fn advance_to(&mut self, ms: u32) -> bool {
+        let mut applied = false;
+        while let Some(frame) = self.recording.frames.get(self.next) {
+            if frame.ms > ms {
+                break;
+            }
+            apply_changes(&mut self.channels, frame);
+            self.next += 1;
+            applied = true;
+        }
+        applied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}.dmxrec", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn frame(ms: u32, changes: &[(u16, u8)]) -> RecordedFrame {
+        RecordedFrame {
+            ms,
+            changes: changes.to_vec(),
+        }
+    }
+
+    fn test_recording() -> Recording {
+        Recording {
+            port_address: PortAddress::new(0, 0, 1).unwrap(),
+            frames: vec![
+                frame(0, &[(0, 10)]),
+                frame(100, &[(1, 20)]),
+                frame(200, &[(0, 30), (2, 40)]),
+            ],
+        }
+    }
+
+    #[test]
+    fn recorder_stores_only_changes() {
+        let path = temp_path("round-trip");
+        let port_address = PortAddress::new(1, 2, 3).unwrap();
+        let mut channels = [0].repeat(CHANNELS_PER_UNIVERSE as usize);
+        {
+            let mut recorder = Recorder::create(&path, port_address).unwrap();
+            channels[0] = 10;
+            recorder.record(&channels);
+            channels[5] = 20;
+            recorder.record(&channels);
+            recorder.record(&channels);
+        }
+
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.port_address, port_address);
+        let changes: Vec<&[(u16, u8)]> = recording
+            .frames
+            .iter()
+            .map(|f| f.changes.as_slice())
+            .collect();
+        assert_eq!(changes, [&[(0, 10)][..], &[(5, 20)], &[]]);
+        assert_eq!(recording.frames[0].ms, 0);
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        let path = temp_path("bad-header");
+        let frame_bytes = [0, 0, 0, 0, 1, 0, 3, 0, 9];
+        for (bytes, expected) in [
+            (b"NOTDMX\x02\x01\x00".to_vec(), "is not a DMX recording"),
+            (b"TACDMX\x01\x01".to_vec(), "no longer supported"),
+            (b"TACDMX\x09\x01\x00".to_vec(), "unsupported version 9"),
+            (b"TACDMX\x02\x01".to_vec(), "is truncated"),
+            (
+                [&b"TACDMX\x02\x01\x00"[..], &frame_bytes[..7]].concat(),
+                "is truncated",
+            ),
+        ] {
+            fs::write(&path, &bytes).unwrap();
+            let error = Recording::load(&path).err().unwrap().to_string();
+            assert!(error.contains(expected), "{:?}: {}", bytes, error);
+        }
+
+        fs::write(&path, [&b"TACDMX\x02\x01\x00"[..], &frame_bytes].concat()).unwrap();
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.frames[0].changes, [(3, 9)]);
+    }
+
+    #[test]
+    fn player_seeks_and_loops() {
+        let recording = test_recording();
+        let mut player = RecordingPlayer::new(&recording);
+
+        // As for --from 0.15
+        assert!(player.advance_to(150));
+        assert_eq!(&player.channels()[..3], &[10, 20, 0]);
+        assert!(!player.is_finished());
+        assert!(!player.advance_to(199));
+
+        assert!(player.advance_to(200));
+        assert_eq!(&player.channels()[..3], &[30, 20, 40]);
+        assert!(player.is_finished());
+        assert_eq!(recording.duration_ms(), 200);
+
+        // Looping starts again from the same point
+        player.rewind();
+        assert!(player.channels().iter().all(|c| *c == 0));
+        assert!(player.advance_to(150));
+        assert_eq!(&player.channels()[..3], &[10, 20, 0]);
+    }
+
+    #[test]
+    fn csv_has_a_row_of_every_channel_per_frame() {
+        let mut out = Vec::new();
+        test_recording().write_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+
+        assert_eq!(rows.len(), 4);
+        for row in rows.iter() {
+            assert_eq!(row.len(), 2 + CHANNELS_PER_UNIVERSE as usize);
+        }
+        assert_eq!(&rows[0][..4], ["ms", "universe", "1", "2"]);
+        assert_eq!(rows[0].last(), Some(&"512"));
+        assert_eq!(&rows[2][..5], ["100", "1", "10", "20", "0"]);
+        assert_eq!(&rows[3][..5], ["200", "1", "30", "20", "40"]);
+    }
+}
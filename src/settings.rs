@@ -1,75 +1,367 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use std::time::Duration;
+
+use log::warn;
+
+use crate::{
+    artnet::{PortAddress, SendPolicy},
+    receiver::ARTNET_PORT,
+};
 
 pub const UNICAST_SRC_STRING: &str = "127.0.0.1";
 pub const UNICAST_DST_STRING: &str = "127.0.0.1";
 
 pub const DEFAULT_ARTNET_HERTZ: u64 = 44;
+/// Used if neither the CLI nor the project gives a Port-Address
+pub const DEFAULT_PORT_ADDRESS: PortAddress = PortAddress {
+    net: 0,
+    sub_net: 0,
+    universe: 1,
+};
+pub const DEFAULT_ARTNET_KEEP_ALIVE_SECS: f32 = 1.0;
+/// Nodes may treat output as lost if nothing arrives for longer than this
+pub const MAX_ARTNET_KEEP_ALIVE_SECS: f32 = 4.0;
 
 pub const CHANNELS_PER_UNIVERSE: u16 = 512;
 
+pub const DEFAULT_PROJECT_PATH: &str = "./example.project.json";
+
+pub const DEFAULT_AUTOSAVE_SECS: u64 = 60;
+pub const DEFAULT_PROJECT_BACKUPS: usize = 5;
+
 #[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = "Tether Artnet Controller")]
 pub struct Cli {
+    /// Optional task to run instead of the normal (GUI or headless) controller
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Flag to enable headless (no GUI) mode, suitable for server-type
     /// process
-    #[arg(long = "headless")]
+    #[arg(long = "headless", global = true)]
     pub headless_mode: bool,
 
-    #[arg(default_value_t=String::from("./example.project.json"))]
+    #[arg(default_value_t=String::from(DEFAULT_PROJECT_PATH))]
     pub project_path: String,
 
-    #[arg(long = "loglevel",default_value_t=String::from("info"))]
+    /// Flag to disable reloading the project file when it changes on disk
+    /// (headless mode only)
+    #[arg(long = "project.noWatch", global = true)]
+    pub project_disable_watch: bool,
+
+    /// How often (in seconds) to autosave the project to a sidecar file; 0 to disable
+    #[arg(long = "project.autosave", default_value_t = DEFAULT_AUTOSAVE_SECS, global = true)]
+    pub project_autosave_secs: u64,
+
+    /// How many timestamped backups of the project file to keep when saving
+    #[arg(long = "project.backups", default_value_t = DEFAULT_PROJECT_BACKUPS, global = true)]
+    pub project_backups: usize,
+
+    #[arg(long = "loglevel",default_value_t=String::from("info"), global = true)]
     pub log_level: String,
 
     /// Flag to enable ArtNet broadcast mode (good for development)
-    #[arg(long = "artnet.broadcast")]
+    #[arg(long = "artnet.broadcast", global = true)]
     pub artnet_broadcast: bool,
 
-    /// Universe number for ArtNet, since there isn't a rigid standard for this
-    #[arg(long = "artnet.universe", default_value_t = 1)]
-    pub artnet_universe: u8,
+    /// ArtNet Port-Address to send to, as net:sub-net:universe (e.g. 1:0:3) or a plain
+    /// universe number; overrides the project's, otherwise universe 1
+    #[arg(long = "artnet.universe", value_parser = parse_port_address, global = true)]
+    pub artnet_universe: Option<PortAddress>,
 
     /// IP address for ArtNet source interface (ignored if broadcast enabled)
-    #[arg(long = "artnet.interface")]
+    #[arg(long = "artnet.interface", global = true)]
     pub unicast_src: Option<std::net::IpAddr>,
 
-    /// IP address for ArtNet destination node (ignored if broadcast enabled)
-    #[arg(long = "artnet.destination")]
-    pub unicast_dst: Option<std::net::IpAddr>,
+    /// IP address(es) for ArtNet destination nodes, comma-separated or repeated; all get the
+    /// same universe (ignored if broadcast enabled)
+    #[arg(long = "artnet.destination", value_delimiter = ',', global = true)]
+    pub unicast_dst: Vec<std::net::IpAddr>,
 
     /// Update frequency, in Hertz, for sending ArtNet data (gets converted to ms)
-    #[arg(long = "artnet.freq", default_value_t=DEFAULT_ARTNET_HERTZ)]
+    #[arg(long = "artnet.freq", default_value_t=DEFAULT_ARTNET_HERTZ, global = true)]
     pub artnet_update_frequency: u64,
 
-    // TODO: split tasks/commands such as "auto" into separate Clap Command
-    #[arg(long = "auto.zero")]
+    /// Only send ArtNet when the output changes (no faster than artnet.freq), plus a keep-alive
+    #[arg(long = "artnet.onChange", global = true)]
+    pub artnet_on_change: bool,
+
+    /// With artnet.onChange, seconds between keep-alive frames if nothing changes
+    #[arg(long = "artnet.keepAlive", default_value_t = DEFAULT_ARTNET_KEEP_ALIVE_SECS, global = true)]
+    pub artnet_keep_alive: f32,
+
+    /// Send ArtSync after each frame, so that nodes output all universes together
+    #[arg(long = "artnet.sync", global = true)]
+    pub artnet_sync: bool,
+
+    /// Send ArtTimeCode, from the time of day or the project's timeline
+    #[arg(long = "artnet.timecode", value_enum, global = true)]
+    pub artnet_timecode: Option<TimecodeOutput>,
+
+    /// Record the ArtNet output to this file, for replaying later
+    #[arg(long = "record", global = true)]
+    pub record_path: Option<String>,
+
+    /// Continuously send zero on all channels (useful for testing)
+    #[arg(long = "auto.zero", global = true)]
     pub auto_zero: bool,
 
-    #[arg(long = "auto.random")]
+    /// Continuously send random values on all channels (useful for testing)
+    #[arg(long = "auto.random", global = true)]
     pub auto_random: bool,
 
     /// Flag to disable Tether connect on start (GUI only)
-    #[arg(long = "tether.noAutoConnect")]
+    #[arg(long = "tether.noAutoConnect", global = true)]
     pub tether_disable_autoconnect: bool,
 
     /// Optionally set an ID/group for lighting-related Input Plugs (macros, scenes); useful for separating messages.
     /// Essentially defaults to wildcard (+) if omitted. Does NOT affect Tether MIDI subscriptions.
-    #[arg(long = "tether.subscribe.id")]
+    #[arg(long = "tether.subscribe.id", global = true)]
     pub tether_subscribe_id: Option<String>,
 
     /// Host/IP for Tether MQTT Broker
-    #[arg(long = "tether.host")]
+    #[arg(long = "tether.host", global = true)]
     pub tether_host: Option<String>,
 
     /// Port for Tether MQTT Broker
-    #[arg(long = "tether.port")]
+    #[arg(long = "tether.port", global = true)]
     pub tether_port: Option<u16>,
 
     /// Username for Tether MQTT Broker
-    #[arg(long = "tether.user")]
+    #[arg(long = "tether.user", global = true)]
     pub tether_user: Option<String>,
 
     /// Password for Tether MQTT Broker
-    #[arg(long = "tether.pass")]
+    #[arg(long = "tether.pass", global = true)]
+    pub tether_pass: Option<String>,
+}
+
+/// What the Engine needs from outside the project: ArtNet, Tether, recording
+/// and saving options. The application builds this from its command line.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Loaded by Engine::new
+    pub project_path: String,
+    /// No GUI; also enables watching the project file
+    pub headless_mode: bool,
+    pub project_disable_watch: bool,
+    /// 0 to disable autosave
+    pub project_autosave_secs: u64,
+    pub project_backups: usize,
+    /// Overrides any ArtNet settings in the project
+    pub artnet_broadcast: bool,
+    /// Overrides the project's Port-Address
+    pub artnet_universe: Option<PortAddress>,
+    /// With unicast_dst, overrides any ArtNet settings in the project
+    pub unicast_src: Option<std::net::IpAddr>,
+    pub unicast_dst: Vec<std::net::IpAddr>,
+    pub artnet_update_frequency: u64,
+    pub artnet_on_change: bool,
+    pub artnet_keep_alive: f32,
+    pub artnet_sync: bool,
+    pub artnet_timecode: Option<TimecodeOutput>,
+    pub record_path: Option<String>,
+    pub auto_zero: bool,
+    pub auto_random: bool,
+    pub tether_disable_autoconnect: bool,
+    pub tether_subscribe_id: Option<String>,
+    pub tether_host: Option<String>,
+    pub tether_port: Option<u16>,
+    pub tether_user: Option<String>,
     pub tether_pass: Option<String>,
 }
+
+impl Default for EngineConfig {
+    /// The same as running with no command-line arguments
+    fn default() -> Self {
+        EngineConfig {
+            project_path: String::from(DEFAULT_PROJECT_PATH),
+            headless_mode: false,
+            project_disable_watch: false,
+            project_autosave_secs: DEFAULT_AUTOSAVE_SECS,
+            project_backups: DEFAULT_PROJECT_BACKUPS,
+            artnet_broadcast: false,
+            artnet_universe: None,
+            unicast_src: None,
+            unicast_dst: Vec::new(),
+            artnet_update_frequency: DEFAULT_ARTNET_HERTZ,
+            artnet_on_change: false,
+            artnet_keep_alive: DEFAULT_ARTNET_KEEP_ALIVE_SECS,
+            artnet_sync: false,
+            artnet_timecode: None,
+            record_path: None,
+            auto_zero: false,
+            auto_random: false,
+            tether_disable_autoconnect: false,
+            tether_subscribe_id: None,
+            tether_host: None,
+            tether_port: None,
+            tether_user: None,
+            tether_pass: None,
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn send_policy(&self) -> SendPolicy {
+        if !self.artnet_on_change {
+            return SendPolicy::Continuous;
+        }
+        if self.artnet_keep_alive > MAX_ARTNET_KEEP_ALIVE_SECS {
+            warn!(
+                "Keep-alive of {}s is longer than the {}s nodes allow before treating output as lost",
+                self.artnet_keep_alive, MAX_ARTNET_KEEP_ALIVE_SECS
+            );
+        }
+        SendPolicy::OnChange {
+            keep_alive: Duration::from_secs_f32(self.artnet_keep_alive.max(0.)),
+        }
+    }
+}
+
+fn parse_port_address(s: &str) -> Result<PortAddress, String> {
+    s.parse().map_err(|e: anyhow::Error| e.to_string())
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum TimecodeOutput {
+    /// Local time of day
+    Internal,
+    /// Position of the project's timeline
+    Timeline,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the controller, in GUI or headless mode (this is the default if no command is given)
+    Run {
+        /// Project file to load; overrides the top-level project path if provided
+        project_path: Option<String>,
+    },
+    /// Load a project file, report any problems and exit with an error code if it is invalid
+    Validate { project_path: String },
+    /// Inspect the fixture configurations built into this application
+    Fixtures {
+        #[command(subcommand)]
+        command: FixturesCommand,
+    },
+    /// Print a patch sheet (DMX addresses for every fixture) for a project
+    Patch { project_path: String },
+    /// Control scenes on a running controller, via Tether
+    Scene {
+        #[command(subcommand)]
+        command: SceneCommand,
+    },
+    /// Send zero on all channels via ArtNet, then exit
+    Blackout {
+        /// Project file to take ArtNet settings from, if not provided via CLI
+        project_path: Option<String>,
+    },
+    /// List the next events in a project's schedule
+    Schedule {
+        project_path: String,
+        /// How many events to list
+        #[arg(long = "count", default_value_t = 10)]
+        count: usize,
+    },
+    /// Replay a recording (made with --record) via ArtNet, without a project
+    Replay {
+        recording_path: String,
+        /// Start again from the beginning when the end is reached
+        #[arg(long = "loop")]
+        looping: bool,
+        /// Playback speed, e.g. 0.5 for half speed
+        #[arg(long = "speed", default_value_t = 1.0)]
+        speed: f64,
+        /// Start this many seconds into the recording
+        #[arg(long = "from", default_value_t = 0.0)]
+        from_secs: f64,
+    },
+    /// Listen for ArtNet like a node would, and show what arrives (as text, or in a window)
+    Monitor {
+        /// UDP port to listen on
+        #[arg(long = "port", default_value_t = ARTNET_PORT)]
+        port: u16,
+        /// Show received universes in a window, instead of printing them
+        #[arg(long = "gui")]
+        gui: bool,
+        /// Print every packet, not just ArtDmx frames which have changed
+        #[arg(long = "all")]
+        all: bool,
+        /// Exit after printing this many packets
+        #[arg(long = "count")]
+        count: Option<usize>,
+    },
+    /// Export a recording (made with --record) as CSV, one row per frame
+    ExportCsv {
+        recording_path: String,
+        /// CSV file to write; defaults to the recording path with a .csv extension
+        #[arg(long = "output")]
+        output_path: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum FixturesCommand {
+    /// List the names of all known fixture configurations
+    List,
+    /// Show the modes, mappings and macros for a single fixture configuration
+    Show {
+        /// Fixture name (case-insensitive)
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SceneCommand {
+    /// Send a single Remote Scene message via Tether, then exit
+    Go {
+        /// Scene label (case-insensitive)
+        label: String,
+        /// Animation duration in ms; omit to go to the scene immediately
+        #[arg(long = "ms")]
+        ms: Option<u64>,
+        /// Limit the scene to these fixture labels (comma-separated)
+        #[arg(long = "fixtures", value_delimiter = ',')]
+        fixture_labels: Option<Vec<String>>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn controller_flags_go_after_run() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "tether-artnet-controller",
+            "run",
+            "--headless",
+            "my.project.json",
+            "--record",
+            "show.dmxrec",
+            "--artnet.timecode",
+            "timeline",
+            "--project.noWatch",
+            "--project.autosave",
+            "0",
+            "--tether.noAutoConnect",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run { project_path: Some(ref p) }) if p == "my.project.json"
+        ));
+        assert!(cli.headless_mode);
+        assert_eq!(cli.record_path.as_deref(), Some("show.dmxrec"));
+        assert_eq!(cli.artnet_timecode, Some(TimecodeOutput::Timeline));
+        assert!(cli.project_disable_watch);
+        assert_eq!(cli.project_autosave_secs, 0);
+        assert!(cli.tether_disable_autoconnect);
+    }
+}
@@ -9,11 +9,16 @@ use std::{
 };
 
 use anyhow::anyhow;
-use egui::Color32;
-use log::{debug, error, info};
+use ecolor::Color32;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use tether_agent::{PlugOptionsBuilder, TetherAgentOptionsBuilder};
 
+use crate::{
+    colour::{hsi_to_rgb, hsv_to_rgb, Temperature},
+    project::park::ParkTarget,
+};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TetherNotePayload {
     pub channel: u8,
@@ -34,16 +39,46 @@ pub struct TetherKnobPayload {
     pub position: f32,
 }
 
+// Variant names are part of the JSON/MessagePack format
+#[allow(clippy::enum_variant_names)]
 #[derive(Serialize, Deserialize, Debug)]
 pub enum RemoteMacroValue {
     ControlValue(f32),
     ColourValue(Color32),
+    /// Pan, tilt, each in the range `[0,1]`
+    PositionValue([f32; 2]),
+    TemperatureValue(Temperature),
+    /// Hue, saturation, value, each in the range `[0,1]`
+    HsvValue([f32; 3]),
+    /// Hue, saturation, intensity, each in the range `[0,1]`
+    HsiValue([f32; 3]),
+}
+
+impl RemoteMacroValue {
+    /// Any kind of colour value (including a temperature) as RGB, for Colour Macros
+    pub fn as_colour(&self) -> Option<Color32> {
+        match self {
+            RemoteMacroValue::ColourValue(c) => Some(*c),
+            RemoteMacroValue::TemperatureValue(t) => Some(t.to_rgb()),
+            RemoteMacroValue::HsvValue(hsv) => Some(hsv_to_rgb(*hsv)),
+            RemoteMacroValue::HsiValue(hsi) => Some(hsi_to_rgb(*hsi)),
+            RemoteMacroValue::ControlValue(_) | RemoteMacroValue::PositionValue(_) => None,
+        }
+    }
+
+    /// Any kind of colour value as the nearest temperature, for Temperature Macros
+    pub fn as_temperature(&self) -> Option<Temperature> {
+        match self {
+            RemoteMacroValue::TemperatureValue(t) => Some(*t),
+            _ => self.as_colour().and_then(Temperature::from_rgb),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RemoteMacroMessage {
-    /// If no fixtures specified, assume all
+    /// Fixture and/or group labels. If no fixtures specified, assume all
     pub fixture_labels: Option<Vec<String>>,
     pub macro_label: String,
     /// Start value will be "whatever the current value is";
@@ -51,6 +86,11 @@ pub struct RemoteMacroMessage {
     pub value: RemoteMacroValue,
     /// Animation duration in ms
     pub ms: Option<u64>,
+    /// Optionally spread ("fan") Control Values across the fixtures, in the order
+    /// given (groups keep their own order), e.g. 0.5 gives -25% for the first
+    /// fixture up to +25% for the last
+    #[serde(default)]
+    pub spread: Option<f32>,
 }
 #[allow(dead_code)]
 pub enum TetherMidiMessage {
@@ -67,14 +107,54 @@ pub enum TetherMidiMessage {
 pub struct RemoteSceneMessage {
     pub scene_label: String,
     pub ms: Option<u64>,
-    /// If no fixtures specified, assume all
+    /// Fixture and/or group labels. If no fixtures specified, assume all
+    pub fixture_labels: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemotePaletteMessage {
+    pub palette_label: String,
+    pub ms: Option<u64>,
+    /// Fixture and/or group labels. If no fixtures specified, assume all
     pub fixture_labels: Option<Vec<String>>,
 }
 
+/// Park a channel or fixture attribute at a value, or release it if no value is given
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteParkMessage {
+    #[serde(flatten)]
+    pub target: ParkTarget,
+    #[serde(default)]
+    pub value: Option<u8>,
+}
+
+/// Turn the safety limiter on or off. Turning it off is refused unless the
+/// project allows remote override.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSafetyMessage {
+    pub enabled: bool,
+}
+
+/// Timecode for the project's timeline, "HH:MM:SS:FF" at the timeline's frame rate
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteTimecodeMessage {
+    pub timecode: String,
+}
+
 pub enum RemoteControlMessage {
     Midi(TetherMidiMessage),
     MacroAnimation(RemoteMacroMessage),
     SceneAnimation(RemoteSceneMessage),
+    Palette(RemotePaletteMessage),
+    Park(RemoteParkMessage),
+    Safety(RemoteSafetyMessage),
+    Timecode(RemoteTimecodeMessage),
+    /// Reload the current project from disk; the payload is ignored
+    ReloadProject,
 }
 
 pub struct TetherInterface {
@@ -82,6 +162,12 @@ pub struct TetherInterface {
     message_tx: Sender<RemoteControlMessage>,
 }
 
+impl Default for TetherInterface {
+    fn default() -> Self {
+        TetherInterface::new()
+    }
+}
+
 impl TetherInterface {
     pub fn new() -> Self {
         let (message_tx, message_rx) = sync::mpsc::channel();
@@ -133,6 +219,31 @@ impl TetherInterface {
                 .build(&mut tether_agent)
                 .expect("failed to create Input Plug");
 
+            let input_palettes = PlugOptionsBuilder::create_input("palettes")
+                .id(lighting_id)
+                .build(&mut tether_agent)
+                .expect("failed to create Input Plug");
+
+            let input_parks = PlugOptionsBuilder::create_input("park")
+                .id(lighting_id)
+                .build(&mut tether_agent)
+                .expect("failed to create Input Plug");
+
+            let input_safety = PlugOptionsBuilder::create_input("safety")
+                .id(lighting_id)
+                .build(&mut tether_agent)
+                .expect("failed to create Input Plug");
+
+            let input_timecode = PlugOptionsBuilder::create_input("timecode")
+                .id(lighting_id)
+                .build(&mut tether_agent)
+                .expect("failed to create Input Plug");
+
+            let input_reload = PlugOptionsBuilder::create_input("reload")
+                .id(lighting_id)
+                .build(&mut tether_agent)
+                .expect("failed to create Input Plug");
+
             let tx = self.message_tx.clone();
 
             spawn(move || {
@@ -167,6 +278,46 @@ impl TetherInterface {
                             tx.send(RemoteControlMessage::SceneAnimation(m))
                                 .expect("failed to send from Tether Interface thread");
                         }
+                        if input_palettes.matches(&topic) {
+                            debug!("Remote Palette message");
+                            match rmp_serde::from_slice::<RemotePaletteMessage>(&message) {
+                                Ok(m) => tx
+                                    .send(RemoteControlMessage::Palette(m))
+                                    .expect("failed to send from Tether Interface thread"),
+                                Err(e) => warn!("Ignoring invalid Palette message: {}", e),
+                            }
+                        }
+                        if input_parks.matches(&topic) {
+                            debug!("Remote Park message");
+                            match rmp_serde::from_slice::<RemoteParkMessage>(&message) {
+                                Ok(m) => tx
+                                    .send(RemoteControlMessage::Park(m))
+                                    .expect("failed to send from Tether Interface thread"),
+                                Err(e) => warn!("Ignoring invalid Park message: {}", e),
+                            }
+                        }
+                        if input_safety.matches(&topic) {
+                            debug!("Remote Safety message");
+                            match rmp_serde::from_slice::<RemoteSafetyMessage>(&message) {
+                                Ok(m) => tx
+                                    .send(RemoteControlMessage::Safety(m))
+                                    .expect("failed to send from Tether Interface thread"),
+                                Err(e) => warn!("Ignoring invalid Safety message: {}", e),
+                            }
+                        }
+                        if input_timecode.matches(&topic) {
+                            match rmp_serde::from_slice::<RemoteTimecodeMessage>(&message) {
+                                Ok(m) => tx
+                                    .send(RemoteControlMessage::Timecode(m))
+                                    .expect("failed to send from Tether Interface thread"),
+                                Err(e) => warn!("Ignoring invalid Timecode message: {}", e),
+                            }
+                        }
+                        if input_reload.matches(&topic) {
+                            debug!("Remote Reload Project message");
+                            tx.send(RemoteControlMessage::ReloadProject)
+                                .expect("failed to send from Tether Interface thread");
+                        }
                         if input_midi_kobs.matches(&topic) {
                             debug!("Remote Knobs (MIDI) message");
                             let m = rmp_serde::from_slice::<TetherKnobPayload>(&message).unwrap();
@@ -186,3 +337,31 @@ impl TetherInterface {
         }
     }
 }
+
+/// Connect a temporary Tether Agent just long enough to publish a single
+/// Remote Scene message, e.g. for the `scene go` CLI command
+pub fn send_scene_message(
+    tether_host: Option<&str>,
+    tether_port: Option<&u16>,
+    tether_user: Option<&str>,
+    tether_pass: Option<&str>,
+    lighting_id: Option<&str>,
+    msg: RemoteSceneMessage,
+) -> Result<(), anyhow::Error> {
+    let mut tether_agent = TetherAgentOptionsBuilder::new("ArtnetController")
+        .host(tether_host)
+        .port(tether_port.copied())
+        .username(tether_user)
+        .password(tether_pass)
+        .build()
+        .map_err(|e| anyhow!("Tether failed to connect: {}", e))?;
+
+    let output_scenes = PlugOptionsBuilder::create_output("scenes")
+        .id(lighting_id)
+        .build(&mut tether_agent)?;
+
+    debug!("Publish Remote Scene message {:?}", &msg);
+    tether_agent.encode_and_publish(&output_scenes, msg)?;
+
+    Ok(())
+}
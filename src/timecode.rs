@@ -0,0 +1,320 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+use artnet_protocol::ARTNET_HEADER;
+use serde::{Deserialize, Serialize};
+
+/// OpCode of an ArtTimeCode packet (sent little-endian)
+const OP_TIME_CODE: u16 = 0x9700;
+
+/// SMPTE frame rates, as used by ArtTimeCode
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FrameRate {
+    #[serde(rename = "24")]
+    Film,
+    #[default]
+    #[serde(rename = "25")]
+    Ebu,
+    /// 29.97 fps, drop-frame
+    #[serde(rename = "29.97")]
+    DropFrame,
+    #[serde(rename = "30")]
+    Smpte,
+}
+
+impl FrameRate {
+    /// Frames per second, in real time
+    pub fn fps(self) -> f64 {
+        match self {
+            FrameRate::Film => 24.,
+            FrameRate::Ebu => 25.,
+            FrameRate::DropFrame => 30000. / 1001.,
+            FrameRate::Smpte => 30.,
+        }
+    }
+
+    /// Frame numbers per second, as labelled
+    pub fn frames_per_second(self) -> u8 {
+        match self {
+            FrameRate::Film => 24,
+            FrameRate::Ebu => 25,
+            FrameRate::DropFrame | FrameRate::Smpte => 30,
+        }
+    }
+
+    /// For the "Type" field of an ArtTimeCode packet
+    pub fn art_net_type(self) -> u8 {
+        match self {
+            FrameRate::Film => 0,
+            FrameRate::Ebu => 1,
+            FrameRate::DropFrame => 2,
+            FrameRate::Smpte => 3,
+        }
+    }
+
+    /// From the "Type" field of an ArtTimeCode packet
+    pub fn from_art_net_type(value: u8) -> Option<FrameRate> {
+        match value {
+            0 => Some(FrameRate::Film),
+            1 => Some(FrameRate::Ebu),
+            2 => Some(FrameRate::DropFrame),
+            3 => Some(FrameRate::Smpte),
+            _ => None,
+        }
+    }
+}
+
+/// A SMPTE time, "HH:MM:SS:FF"; the frame rate is kept separately
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+}
+
+impl Timecode {
+    /// Frames since 00:00:00:00, allowing for the frame numbers skipped by drop-frame
+    fn frame_number(&self, rate: FrameRate) -> u64 {
+        let fps = rate.frames_per_second() as u64;
+        let total_minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let labelled = (total_minutes * 60 + self.seconds as u64) * fps + self.frames as u64;
+        match rate {
+            // Frames 0 and 1 are skipped every minute, except every tenth minute
+            FrameRate::DropFrame => labelled - 2 * (total_minutes - total_minutes / 10),
+            _ => labelled,
+        }
+    }
+
+    /// Drop-frame skips the labels for frames 0 and 1 at the start of each
+    /// minute, except every tenth minute
+    fn is_dropped(&self) -> bool {
+        self.seconds == 0 && self.frames < 2 && !self.minutes.is_multiple_of(10)
+    }
+
+    /// Whether this label exists at the given frame rate
+    pub fn is_valid(&self, rate: FrameRate) -> bool {
+        self.frames < rate.frames_per_second()
+            && !(rate == FrameRate::DropFrame && self.is_dropped())
+    }
+
+    /// Parse "HH:MM:SS:FF", also checking the frames against the frame rate
+    pub fn parse_with_rate(s: &str, rate: FrameRate) -> anyhow::Result<Timecode> {
+        let timecode: Timecode = s.parse()?;
+        if timecode.is_valid(rate) {
+            Ok(timecode)
+        } else {
+            Err(anyhow!(
+                "invalid timecode \"{}\" at frame rate {:?}",
+                s,
+                rate
+            ))
+        }
+    }
+
+    /// The first whole ms of this frame, so that [`Timecode::from_ms`] gives it back
+    pub fn to_ms(self, rate: FrameRate) -> u64 {
+        (self.frame_number(rate) as f64 * 1000. / rate.fps()).ceil() as u64
+    }
+
+    pub fn from_ms(ms: u64, rate: FrameRate) -> Timecode {
+        let mut frame_number = (ms as f64 * rate.fps() / 1000.).floor() as u64;
+        if rate == FrameRate::DropFrame {
+            // Frames per ten minutes, and per minute after the first of those
+            let (per_ten_minutes, per_minute) = (17982, 1798);
+            let tens = frame_number / per_ten_minutes;
+            let remainder = frame_number % per_ten_minutes;
+            frame_number += 18 * tens;
+            if remainder > 1 {
+                frame_number += 2 * ((remainder - 2) / per_minute);
+            }
+        }
+        let fps = rate.frames_per_second() as u64;
+        let total_seconds = frame_number / fps;
+        Timecode {
+            hours: (total_seconds / 3600 % 24) as u8,
+            minutes: (total_seconds / 60 % 60) as u8,
+            seconds: (total_seconds % 60) as u8,
+            frames: (frame_number % fps) as u8,
+        }
+    }
+
+    /// Write an ArtTimeCode packet
+    pub fn to_art_net(self, rate: FrameRate) -> Vec<u8> {
+        let mut packet = ARTNET_HEADER.to_vec();
+        packet.extend_from_slice(&OP_TIME_CODE.to_le_bytes());
+        // ProtVer (14), Filler1, StreamId
+        packet.extend_from_slice(&[0, 14, 0, 0]);
+        packet.extend_from_slice(&[
+            self.frames,
+            self.seconds,
+            self.minutes,
+            self.hours,
+            rate.art_net_type(),
+        ]);
+        packet
+    }
+
+    /// Read an ArtTimeCode packet; None if this is some other packet
+    pub fn from_art_net(packet: &[u8]) -> Option<(Timecode, FrameRate)> {
+        if packet.len() < 19
+            || &packet[..8] != ARTNET_HEADER
+            || u16::from_le_bytes([packet[8], packet[9]]) != OP_TIME_CODE
+        {
+            return None;
+        }
+        let rate = FrameRate::from_art_net_type(packet[18])?;
+        Some((
+            Timecode {
+                frames: packet[14],
+                seconds: packet[15],
+                minutes: packet[16],
+                hours: packet[17],
+            },
+            rate,
+        ))
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}:{:02}",
+            self.hours, self.minutes, self.seconds, self.frames
+        )
+    }
+}
+
+impl FromStr for Timecode {
+    type Err = anyhow::Error;
+
+    /// "HH:MM:SS:FF", or with ";" before the frames as usual for drop-frame.
+    /// Frames are only checked against the highest frame rate, and (with ";")
+    /// the labels which drop-frame skips; see [`Timecode::parse_with_rate`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u8> = s
+            .split([':', ';', '.'])
+            .map(|p| p.trim().parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow!("invalid timecode \"{}\"", s))?;
+        let max_frames = FrameRate::Smpte.frames_per_second();
+        let timecode = match parts.as_slice() {
+            [hours, minutes, seconds, frames]
+                if *hours < 24 && *minutes < 60 && *seconds < 60 && *frames < max_frames =>
+            {
+                Timecode {
+                    hours: *hours,
+                    minutes: *minutes,
+                    seconds: *seconds,
+                    frames: *frames,
+                }
+            }
+            _ => return Err(anyhow!("invalid timecode \"{}\"; expected HH:MM:SS:FF", s)),
+        };
+        if s.contains(';') && timecode.is_dropped() {
+            return Err(anyhow!(
+                "invalid timecode \"{}\"; drop-frame skips frames 0 and 1 of this minute",
+                s
+            ));
+        }
+        Ok(timecode)
+    }
+}
+
+impl TryFrom<String> for Timecode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timecode> for String {
+    fn from(value: Timecode) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tc(hours: u8, minutes: u8, seconds: u8, frames: u8) -> Timecode {
+        Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+        }
+    }
+
+    #[test]
+    fn parses_both_separators() {
+        assert_eq!("01:02:03:04".parse::<Timecode>().unwrap(), tc(1, 2, 3, 4));
+        assert_eq!("00:10:00;00".parse::<Timecode>().unwrap(), tc(0, 10, 0, 0));
+        assert_eq!("00:01:00;02".parse::<Timecode>().unwrap(), tc(0, 1, 0, 2));
+        assert_eq!(tc(1, 2, 3, 4).to_string(), "01:02:03:04");
+    }
+
+    #[test]
+    fn impossible_labels_are_rejected() {
+        for invalid in [
+            "24:00:00:00",
+            "00:60:00:00",
+            "00:00:60:00",
+            "00:00:00:30",
+            "00:01:00;00",
+            "00:01:00;01",
+            "00:00:00",
+            "00:00:00:00:00",
+            "aa:00:00:00",
+        ] {
+            assert!(
+                invalid.parse::<Timecode>().is_err(),
+                "\"{}\" should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn frames_are_checked_against_the_rate() {
+        let parse = Timecode::parse_with_rate;
+        assert!(parse("00:00:00:24", FrameRate::Ebu).is_ok());
+        assert!(parse("00:00:00:25", FrameRate::Ebu).is_err());
+        assert!(parse("00:00:00:24", FrameRate::Film).is_err());
+        assert!(parse("00:00:00:29", FrameRate::Smpte).is_ok());
+        assert!(parse("00:01:00:00", FrameRate::Smpte).is_ok());
+
+        // Drop-frame labels which are skipped, with either separator
+        assert!(parse("00:01:00:00", FrameRate::DropFrame).is_err());
+        assert!(parse("00:01:00;01", FrameRate::DropFrame).is_err());
+        assert!(parse("00:01:00;02", FrameRate::DropFrame).is_ok());
+        assert!(parse("00:10:00;00", FrameRate::DropFrame).is_ok());
+    }
+
+    #[test]
+    fn drop_frame_round_trip() {
+        let rate = FrameRate::DropFrame;
+        assert_eq!(Timecode::from_ms(60_060, rate), tc(0, 1, 0, 2));
+        assert_eq!(tc(0, 10, 0, 0).to_ms(rate), 600_000);
+        for ms in (0..3_600_000).step_by(9_973) {
+            let timecode = Timecode::from_ms(ms, rate);
+            assert!(timecode.is_valid(rate), "{} from {}ms", timecode, ms);
+            assert_eq!(Timecode::from_ms(timecode.to_ms(rate), rate), timecode);
+        }
+    }
+
+    #[test]
+    fn art_net_round_trip() {
+        let timecode = tc(10, 20, 30, 12);
+        let packet = timecode.to_art_net(FrameRate::Ebu);
+        assert_eq!(
+            Timecode::from_art_net(&packet),
+            Some((timecode, FrameRate::Ebu))
+        );
+        assert_eq!(Timecode::from_art_net(&packet[..18]), None);
+    }
+}
@@ -0,0 +1,62 @@
+use egui::{Color32, Response, Slider, Ui};
+
+use crate::colour::{hsi_to_rgb, rgb_to_hsi, Temperature, MAX_KELVIN, MIN_KELVIN};
+
+/// Colour button (with egui's own RGB/HSV picker), plus a menu for choosing
+/// the colour by temperature or by HSI instead. Returns the button's response,
+/// marked as changed if the colour was changed from the menu.
+pub fn colour_edit(ui: &mut Ui, colour: &mut Color32) -> Response {
+    let mut response = ui.color_edit_button_srgba(colour);
+    ui.menu_button("🌡", |ui| {
+        ui.label("Colour temperature");
+        let mut temperature = Temperature::from_rgb(*colour).unwrap_or_default();
+        if temperature_sliders(ui, &mut temperature, (MIN_KELVIN, MAX_KELVIN)).changed() {
+            *colour = with_alpha(temperature.to_rgb(), colour.a());
+            response.mark_changed();
+        }
+        ui.separator();
+        ui.label("Hue, saturation, intensity");
+        let mut hsi = rgb_to_hsi(*colour);
+        let mut hsi_changed = false;
+        for (value, label) in hsi.iter_mut().zip(["H", "S", "I"]) {
+            hsi_changed |= ui.add(Slider::new(value, 0.0..=1.0).text(label)).changed();
+        }
+        if hsi_changed {
+            *colour = with_alpha(hsi_to_rgb(hsi), colour.a());
+            response.mark_changed();
+        }
+    })
+    .response
+    .on_hover_text("Set by colour temperature or HSI");
+    response
+}
+
+/// Kelvin and tint sliders, with the Kelvin range limited to what the
+/// fixture can produce. Returns both sliders' responses together.
+pub fn temperature_sliders(
+    ui: &mut Ui,
+    temperature: &mut Temperature,
+    (min_kelvin, max_kelvin): (u16, u16),
+) -> Response {
+    let kelvin = ui.add(
+        Slider::new(&mut temperature.kelvin, min_kelvin..=max_kelvin)
+            .suffix("K")
+            .step_by(50.),
+    );
+    let tint = ui.add(
+        Slider::new(&mut temperature.tint, -1.0..=1.0)
+            .text("tint")
+            .custom_formatter(|v, _| match v {
+                v if v > 0. => format!("{:.2} G", v),
+                v if v < 0. => format!("{:.2} M", -v),
+                _ => String::from("0"),
+            }),
+    );
+    kelvin | tint
+}
+
+/// Colour Macros use alpha for the white mix, so keep it when changing the colour
+fn with_alpha(colour: Color32, alpha: u8) -> Color32 {
+    let [r, g, b, _] = colour.to_array();
+    Color32::from_rgba_unmultiplied(r, g, b, alpha)
+}
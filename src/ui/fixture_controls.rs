@@ -1,7 +1,15 @@
-use egui::{DragValue, Grid, ScrollArea, Slider, Ui};
+use egui::{Color32, DragValue, Grid, Response, RichText, ScrollArea, Slider, Ui};
 use log::debug;
 
-use crate::model::Model;
+use super::record_widget_change;
+use crate::{
+    model::Model,
+    project::{
+        curve::{OutputCurve, OutputLimits},
+        fixture::{FixtureMacro, ValueSource},
+        park::ParkTarget,
+    },
+};
 
 pub fn render_fixture_controls(model: &mut Model, ui: &mut Ui) {
     ui.heading("Fixtures");
@@ -26,7 +34,12 @@ pub fn render_fixture_controls(model: &mut Model, ui: &mut Ui) {
                         ui.horizontal(|ui| {
                             if ui.button("Add to Project ✅").clicked() {
                                 debug!("Adding new fixture to project");
-                                model.project.fixtures.insert(0, new_fixture.clone());
+                                model.history.record(
+                                    &format!("Add fixture \"{}\"", &new_fixture.label),
+                                    &model.engine.project,
+                                );
+                                model.engine.project.fixtures.insert(0, new_fixture.clone());
+                                model.engine.project_changed();
                                 model.adding_new_fixture = false;
                             }
                             if ui.button("Cancel 🗙").clicked() {
@@ -40,7 +53,7 @@ pub fn render_fixture_controls(model: &mut Model, ui: &mut Ui) {
                         }
                     } else {
                         // -------- Provide a list of fixtures
-                        for fixture in model.known_fixtures.iter() {
+                        for fixture in model.engine.known_fixtures.iter() {
                             ui.horizontal(|ui| {
                                 ui.label(&fixture.name);
                                 if ui.button("Select").clicked() {
@@ -58,7 +71,7 @@ pub fn render_fixture_controls(model: &mut Model, ui: &mut Ui) {
                 debug!("Add fixture");
                 model.adding_new_fixture = true;
             }
-            if !model.project.fixtures.is_empty() {
+            if !model.engine.project.fixtures.is_empty() {
                 ui.separator();
                 fixture_controls_in_project(model, ui);
             }
@@ -67,8 +80,16 @@ pub fn render_fixture_controls(model: &mut Model, ui: &mut Ui) {
 
 fn fixture_controls_in_project(model: &mut Model, ui: &mut Ui) {
     let mut remove_index = None;
+    // (fixture index, start channel before, widget response)
+    let mut moved: Option<(usize, u16, Response)> = None;
+    // (fixture index, [invert pan, invert tilt, swap] before change)
+    let mut reoriented: Option<(usize, [bool; 3])> = None;
+    // Park toggled: (target, new value or None to release)
+    let mut park_toggled: Option<(ParkTarget, Option<u8>)> = None;
+    // Park value dragged: (park index, value before, slider response)
+    let mut park_adjusted: Option<(usize, u8, Response)> = None;
 
-    for (i, fixture) in model.project.fixtures.iter_mut().enumerate() {
+    for (i, fixture) in model.engine.project.fixtures.iter_mut().enumerate() {
         let config = &fixture.config;
         // ----------------
         ui.horizontal(|ui| {
@@ -85,12 +106,38 @@ fn fixture_controls_in_project(model: &mut Model, ui: &mut Ui) {
         // ----------------
         ui.horizontal(|ui| {
             ui.label("Offset channels:");
-            ui.add(
+            let start_channel_before = fixture.start_channel;
+            let response = ui.add(
                 DragValue::new(&mut fixture.start_channel)
                     .clamp_range(0..=512)
                     .speed(1),
             );
+            if response.changed() || response.drag_started() {
+                moved = Some((i, start_channel_before, response));
+            }
         });
+        if config
+            .active_mode
+            .macros
+            .iter()
+            .any(|m| matches!(m, FixtureMacro::Position(_)))
+        {
+            ui.horizontal(|ui| {
+                let before = [
+                    fixture.invert_pan,
+                    fixture.invert_tilt,
+                    fixture.swap_pan_tilt,
+                ];
+                let changed = ui.checkbox(&mut fixture.invert_pan, "Invert pan").changed()
+                    | ui.checkbox(&mut fixture.invert_tilt, "Invert tilt")
+                        .changed()
+                    | ui.checkbox(&mut fixture.swap_pan_tilt, "Swap pan/tilt")
+                        .changed();
+                if changed {
+                    reoriented = Some((i, before));
+                }
+            });
+        }
 
         // ----------------
         let current_mode = &config.modes[fixture.mode_index];
@@ -108,27 +155,73 @@ fn fixture_controls_in_project(model: &mut Model, ui: &mut Ui) {
                             "Fixture CH{} => DMX CH{} (idx [{}])",
                             m.channel, channel_one_index, channel_zero_index
                         ));
+                        let limits = m.output.merged(fixture.output_overrides.get(&m.channel));
+                        if !limits.is_empty() {
+                            ui.label("📈").on_hover_text(describe_limits(&limits));
+                        }
                     });
-                    if ui
-                        .add(Slider::new(
-                            &mut model.channels_state[(channel_zero_index) as usize],
-                            0..=255,
-                        ))
-                        .changed()
-                    {
-                        model.apply_macros = false;
+                    let target = ParkTarget::Attribute {
+                        fixture_label: fixture.label.clone(),
+                        attribute: m.label.clone(),
                     };
+                    let park_index = model
+                        .engine
+                        .project
+                        .parks
+                        .iter()
+                        .position(|p| p.target.matches(&target));
+                    ui.horizontal(|ui| {
+                        match park_index {
+                            Some(park_index) => {
+                                let value = &mut model.engine.project.parks[park_index].value;
+                                let value_before = *value;
+                                let response = ui.add(Slider::new(value, 0..=255));
+                                if response.changed() || response.drag_started() {
+                                    park_adjusted = Some((park_index, value_before, response));
+                                }
+                            }
+                            None => {
+                                if ui
+                                    .add(Slider::new(
+                                        &mut model.engine.channels_state[(channel_zero_index) as usize],
+                                        0..=255,
+                                    ))
+                                    .changed()
+                                {
+                                    model.engine.apply_macros = false;
+                                    model.engine.channel_sources[channel_zero_index as usize] =
+                                        ValueSource::Slider;
+                                };
+                            }
+                        }
+                        let mut is_parked = park_index.is_some();
+                        if ui
+                            .toggle_value(&mut is_parked, "🅿")
+                            .on_hover_text("Park this attribute at its current value")
+                            .changed()
+                        {
+                            park_toggled = Some((
+                                target,
+                                is_parked
+                                    .then_some(model.engine.channels_state[channel_zero_index as usize]),
+                            ));
+                        }
+                    });
                     ui.label(m.notes.as_deref().unwrap_or(" "));
                     if let Some(range_sections) = &m.ranges {
                         ui.label("Mode/Programme:");
                         let current_range = range_sections.iter().find(|x| {
                             let [start, end] = x.range;
-                            model.channels_state[(channel_zero_index) as usize] >= start
-                                && model.channels_state[(channel_zero_index) as usize] <= end
+                            model.engine.channels_state[(channel_zero_index) as usize] >= start
+                                && model.engine.channels_state[(channel_zero_index) as usize] <= end
                         });
                         match current_range {
                             Some(r) => {
                                 ui.label(&r.label);
+                                if r.strobe {
+                                    ui.label(RichText::new("⚡").color(Color32::YELLOW))
+                                        .on_hover_text("Strobe effect; blocked by the safety limiter unless allowed");
+                                }
                                 if let Some(notes) = &r.notes {
                                     ui.label("ℹ").on_hover_text(notes);
                                 }
@@ -145,8 +238,83 @@ fn fixture_controls_in_project(model: &mut Model, ui: &mut Ui) {
             });
         ui.separator();
     }
+    if let Some((index, start_channel_before, response)) = moved {
+        let label = format!(
+            "Move fixture \"{}\"",
+            &model.engine.project.fixtures[index].label
+        );
+        record_widget_change(&mut model.history, &label, &response, || {
+            let mut project_before = model.engine.project.clone();
+            project_before.fixtures[index].start_channel = start_channel_before;
+            project_before
+        });
+        if response.changed() {
+            model.engine.project_changed();
+        }
+    }
+    if let Some((index, [invert_pan, invert_tilt, swap_pan_tilt])) = reoriented {
+        let mut project_before = model.engine.project.clone();
+        let fixture_before = &mut project_before.fixtures[index];
+        fixture_before.invert_pan = invert_pan;
+        fixture_before.invert_tilt = invert_tilt;
+        fixture_before.swap_pan_tilt = swap_pan_tilt;
+        model.history.record(
+            &format!(
+                "Orient fixture \"{}\"",
+                &model.engine.project.fixtures[index].label
+            ),
+            &project_before,
+        );
+        model.engine.apply_macros = true;
+    }
+    if let Some((target, value)) = park_toggled {
+        model.history.record(
+            &format!(
+                "{} {}",
+                if value.is_some() { "Park" } else { "Release" },
+                target.describe()
+            ),
+            &model.engine.project,
+        );
+        model.engine.project.set_park(target, value);
+    }
+    if let Some((park_index, value_before, response)) = park_adjusted {
+        let label = format!(
+            "Adjust park {}",
+            model.engine.project.parks[park_index].target.describe()
+        );
+        record_widget_change(&mut model.history, &label, &response, || {
+            let mut project_before = model.engine.project.clone();
+            project_before.parks[park_index].value = value_before;
+            project_before
+        });
+    }
     if let Some(index) = remove_index {
         debug!("Delete fixture with index {}", index);
-        model.project.fixtures.remove(index);
+        model.history.record(
+            &format!(
+                "Delete fixture \"{}\"",
+                &model.engine.project.fixtures[index].label
+            ),
+            &model.engine.project,
+        );
+        model.engine.project.fixtures.remove(index);
+        model.engine.project_changed();
     }
 }
+
+/// e.g. "square law, 10-200"
+fn describe_limits(limits: &OutputLimits) -> String {
+    let curve = match &limits.curve {
+        None | Some(OutputCurve::Linear) => String::from("linear"),
+        Some(OutputCurve::SquareLaw) => String::from("square law"),
+        Some(OutputCurve::SCurve) => String::from("S-curve"),
+        Some(OutputCurve::Table(points)) => format!("table of {} points", points.len()),
+    };
+    format!(
+        "Output: {}, {}-{}",
+        curve,
+        limits.min.unwrap_or(0),
+        limits.max.unwrap_or(u8::MAX)
+    )
+}
@@ -0,0 +1,276 @@
+use egui::{CollapsingHeader, Color32, ComboBox, Grid, Response, RichText, Slider, Ui};
+use log::debug;
+
+use super::{
+    colour_models::{colour_edit, temperature_sliders},
+    record_widget_change,
+    xy_pad::xy_pad,
+};
+use crate::{
+    model::Model,
+    project::{
+        fixture::{FixtureMacro, ValueSource},
+        group::FixtureGroup,
+        scene::SceneValue,
+    },
+};
+
+/// Add, remove, rename and reorder fixture groups (Setup mode)
+pub fn render_group_controls(model: &mut Model, ui: &mut Ui) {
+    CollapsingHeader::new(RichText::new("Groups").heading())
+        .default_open(false)
+        .show(ui, |ui| {
+            let mut remove_group: Option<usize> = None;
+            let mut project_edit: Option<(String, FixtureGroup, usize)> = None;
+
+            for (group_index, group) in model.engine.project.groups.iter().enumerate() {
+                ui.group(|ui| {
+                    let mut edited = group.clone();
+                    let mut edit_label = None;
+
+                    ui.horizontal(|ui| {
+                        if ui.text_edit_singleline(&mut edited.label).changed() {
+                            edit_label = Some(String::from("Rename group"));
+                        }
+                        if ui.button("🗑").clicked() {
+                            remove_group = Some(group_index);
+                        }
+                    });
+
+                    let mut move_up = None;
+                    let mut remove_member = None;
+                    for (member_index, member) in group.fixtures.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}. {}", member_index + 1, member));
+                            if member_index > 0 && ui.small_button("⬆").clicked() {
+                                move_up = Some(member_index);
+                            }
+                            if ui.small_button("🗙").clicked() {
+                                remove_member = Some(member_index);
+                            }
+                        });
+                    }
+                    if let Some(index) = move_up {
+                        edited.fixtures.swap(index - 1, index);
+                        edit_label = Some(format!("Reorder group \"{}\"", &group.label));
+                    }
+                    if let Some(index) = remove_member {
+                        edited.fixtures.remove(index);
+                        edit_label = Some(format!("Remove from group \"{}\"", &group.label));
+                    }
+
+                    let mut add_member: Option<String> = None;
+                    ComboBox::from_id_source(format!("add_to_group_{}", group_index))
+                        .selected_text("Add fixture...")
+                        .show_ui(ui, |ui| {
+                            for fixture in model.engine.project.fixtures.iter() {
+                                if !group.contains(&fixture.label)
+                                    && ui.selectable_label(false, &fixture.label).clicked()
+                                {
+                                    add_member = Some(fixture.label.clone());
+                                }
+                            }
+                        });
+                    if let Some(label) = add_member {
+                        edited.fixtures.push(label);
+                        edit_label = Some(format!("Add to group \"{}\"", &group.label));
+                    }
+
+                    if let Some(label) = edit_label {
+                        project_edit = Some((label, edited, group_index));
+                    }
+                });
+            }
+
+            if let Some((label, edited, group_index)) = project_edit {
+                model.history.record_grouped(&label, &model.engine.project);
+                model.engine.project.groups[group_index] = edited;
+            }
+            if let Some(index) = remove_group {
+                debug!("Delete group with index {}", index);
+                model.history.record(
+                    &format!(
+                        "Delete group \"{}\"",
+                        &model.engine.project.groups[index].label
+                    ),
+                    &model.engine.project,
+                );
+                model.engine.project.groups.remove(index);
+            }
+
+            if ui.button("Add Group ➕").clicked() {
+                model.history.record("Add group", &model.engine.project);
+                let label = format!("New Group {}", model.engine.project.groups.len());
+                model.engine.project.groups.push(FixtureGroup {
+                    label,
+                    fixtures: Vec::new(),
+                });
+            }
+        });
+}
+
+enum GroupChange {
+    /// Set every member to the same value
+    Set(SceneValue),
+    /// Move every member's position by the same (pan, tilt) amount
+    Nudge([i32; 2]),
+}
+
+/// Macro controls which apply to every member of each group at once. The
+/// macros (and current values) shown are those of the first member.
+pub fn render_group_macros(model: &mut Model, ui: &mut Ui) {
+    // (group index, macro label, change, widget response)
+    let mut changed: Option<(usize, String, GroupChange, Response)> = None;
+
+    for (group_index, group) in model.engine.project.groups.iter().enumerate() {
+        let Some(first_member) = group.fixtures.first().and_then(|label| {
+            model
+                .engine
+                .project
+                .fixtures
+                .iter()
+                .find(|f| f.label.eq_ignore_ascii_case(label))
+        }) else {
+            continue;
+        };
+
+        ui.group(|ui| {
+            ui.horizontal(|ui| {
+                ui.heading(format!("👥 {}", &group.label));
+                ui.label(
+                    RichText::new(format!("{} fixtures", group.fixtures.len()))
+                        .color(Color32::GRAY)
+                        .small(),
+                );
+            });
+            Grid::new(format!("group_macros_{}", group_index))
+                .num_columns(2)
+                .show(ui, |ui| {
+                    for m in first_member.config.active_mode.macros.iter() {
+                        match m {
+                            FixtureMacro::Control(control_macro) => {
+                                ui.label(&control_macro.label);
+                                let mut value = control_macro.current_value;
+                                let response = ui.add(Slider::new(&mut value, 0..=u16::MAX));
+                                if response.changed() || response.drag_started() {
+                                    changed = Some((
+                                        group_index,
+                                        control_macro.label.clone(),
+                                        GroupChange::Set(SceneValue::ControlValue(value)),
+                                        response,
+                                    ));
+                                }
+                            }
+                            FixtureMacro::Colour(colour_macro) => {
+                                ui.label(&colour_macro.label);
+                                let mut value = colour_macro.current_value;
+                                let response =
+                                    ui.horizontal(|ui| colour_edit(ui, &mut value)).inner;
+                                if response.changed() || response.drag_started() {
+                                    changed = Some((
+                                        group_index,
+                                        colour_macro.label.clone(),
+                                        GroupChange::Set(SceneValue::ColourValue(value)),
+                                        response,
+                                    ));
+                                }
+                            }
+                            FixtureMacro::Temperature(temperature_macro) => {
+                                ui.label(&temperature_macro.label);
+                                let mut value = temperature_macro.current_value;
+                                let range = temperature_macro.channels.kelvin_range();
+                                let response = ui
+                                    .vertical(|ui| temperature_sliders(ui, &mut value, range))
+                                    .inner;
+                                if response.changed() || response.drag_started() {
+                                    changed = Some((
+                                        group_index,
+                                        temperature_macro.label.clone(),
+                                        GroupChange::Set(SceneValue::TemperatureValue(value)),
+                                        response,
+                                    ));
+                                }
+                            }
+                            FixtureMacro::Position(position_macro) => {
+                                ui.label(&position_macro.label);
+                                let mut value = position_macro.current_value;
+                                let response = xy_pad(ui, &mut value, true);
+                                if response.changed() || response.drag_started() {
+                                    // Members move by the same amount, keeping their offsets
+                                    let delta = [
+                                        value[0] as i32 - position_macro.current_value[0] as i32,
+                                        value[1] as i32 - position_macro.current_value[1] as i32,
+                                    ];
+                                    changed = Some((
+                                        group_index,
+                                        position_macro.label.clone(),
+                                        GroupChange::Nudge(delta),
+                                        response,
+                                    ));
+                                }
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    if let Some((group_index, macro_label, value, response)) = changed {
+        let group = model.engine.project.groups[group_index].clone();
+        record_widget_change(
+            &mut model.history,
+            &format!("Adjust group \"{}\" {}", &group.label, &macro_label),
+            &response,
+            || model.engine.project.clone(),
+        );
+        if !response.changed() {
+            return;
+        }
+        model.engine.apply_macros = true;
+        for fixture in model
+            .engine
+            .project
+            .fixtures
+            .iter_mut()
+            .filter(|f| group.contains(&f.label))
+        {
+            for m in fixture.config.active_mode.macros.iter_mut() {
+                match (m, &value) {
+                    (
+                        FixtureMacro::Control(control_macro),
+                        GroupChange::Set(SceneValue::ControlValue(v)),
+                    ) if control_macro.label == macro_label => {
+                        control_macro.animation = None;
+                        control_macro.current_value = *v;
+                        control_macro.last_source = ValueSource::Macro;
+                    }
+                    (
+                        FixtureMacro::Colour(colour_macro),
+                        GroupChange::Set(SceneValue::ColourValue(c)),
+                    ) if colour_macro.label == macro_label => {
+                        colour_macro.animation = None;
+                        colour_macro.current_value = *c;
+                        colour_macro.last_source = ValueSource::Macro;
+                    }
+                    (
+                        FixtureMacro::Temperature(temperature_macro),
+                        GroupChange::Set(SceneValue::TemperatureValue(t)),
+                    ) if temperature_macro.label == macro_label => {
+                        temperature_macro.animation = None;
+                        temperature_macro.current_value = *t;
+                        temperature_macro.last_source = ValueSource::Macro;
+                    }
+                    (FixtureMacro::Position(position_macro), GroupChange::Nudge(delta))
+                        if position_macro.label == macro_label =>
+                    {
+                        position_macro.animation = None;
+                        position_macro.nudge(*delta);
+                        position_macro.last_source = ValueSource::Macro;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
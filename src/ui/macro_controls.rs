@@ -1,25 +1,36 @@
-use egui::{Color32, Grid, RichText, ScrollArea, Slider, Ui};
+use egui::{Color32, Grid, Response, RichText, ScrollArea, Slider, Ui};
 
+use super::{
+    colour_models::{colour_edit, temperature_sliders},
+    groups::render_group_macros,
+    record_widget_change,
+    xy_pad::xy_pad,
+};
 use crate::{
     artnet::{random, zero},
     model::Model,
-    project::fixture::FixtureMacro,
+    project::{
+        fixture::{FixtureMacro, ValueSource},
+        scene::SceneValue,
+    },
 };
 
 pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
     ui.horizontal(|ui| {
         ui.heading("All");
         if ui.button("HOME").clicked() {
-            model.apply_macros = false;
-            model.apply_home_values();
+            model.engine.apply_macros = false;
+            model.engine.apply_home_values();
         }
         if ui.button("ZERO").clicked() {
-            model.apply_macros = false;
-            zero(&mut model.channels_state);
+            model.engine.apply_macros = false;
+            zero(&mut model.engine.channels_state);
+            model.engine.set_all_channel_sources(ValueSource::Slider);
         }
         if ui.button("RANDOM").clicked() {
-            model.apply_macros = false;
-            random(&mut model.channels_state);
+            model.engine.apply_macros = false;
+            random(&mut model.engine.channels_state);
+            model.engine.set_all_channel_sources(ValueSource::Slider);
         }
     });
 
@@ -27,22 +38,50 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
 
     ui.horizontal(|ui| {
         ui.heading("Macros");
-        ui.label(if model.apply_macros {
+        ui.label(if model.engine.apply_macros {
             RichText::new("active").color(Color32::DARK_GREEN)
         } else {
             RichText::new("inactive").color(Color32::GRAY)
         });
+        ui.checkbox(&mut model.link_positions, "🔗 Aim together")
+            .on_hover_text(
+                "Moving one position pad moves every fixture shown, keeping their offsets",
+            );
     });
 
+    // (fixture index, macro index, value before change, widget response) for the undo history
+    let mut changed_macro: Option<(usize, usize, SceneValue, Response)> = None;
+
     ScrollArea::vertical()
         .auto_shrink([false, false])
         .show(ui, |ui| {
-            for (i, fixture) in model.project.fixtures.iter_mut().enumerate() {
+            if !model.selected_fixtures.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "Showing {} selected fixture(s)",
+                        model.selected_fixtures.len()
+                    ));
+                    if ui.button("Show all").clicked() {
+                        model.selected_fixtures.clear();
+                    }
+                });
+            } else {
+                render_group_macros(model, ui);
+            }
+            for (i, fixture) in model.engine.project.fixtures.iter_mut().enumerate() {
+                if !model.selected_fixtures.is_empty()
+                    && !model
+                        .selected_fixtures
+                        .iter()
+                        .any(|s| s.eq_ignore_ascii_case(&fixture.label))
+                {
+                    continue;
+                }
                 ui.group(|ui| {
-                    let mut this_selected = model.selected_macro_group_index == i;
+                    let mut this_selected = model.engine.selected_macro_group_index == i;
                     ui.horizontal(|ui| {
                         if ui.toggle_value(&mut this_selected, "🎹").clicked() {
-                            model.selected_macro_group_index = i;
+                            model.engine.selected_macro_group_index = i;
                         }
                         ui.heading(&fixture.label);
                     });
@@ -54,23 +93,36 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
                     Grid::new(format!("macros_{}", i))
                         .num_columns(4)
                         .show(ui, |ui| {
-                            for m in current_mode.macros.iter_mut() {
+                            for (macro_index, m) in current_mode.macros.iter_mut().enumerate() {
+                                let palette_label = m.palette_label().map(String::from);
                                 match m {
                                     FixtureMacro::Control(control_macro) => {
-                                        ui.label(&control_macro.label);
-                                        if ui
-                                            .add_enabled(
-                                                control_macro.animation.is_none(),
-                                                Slider::new(
-                                                    &mut control_macro.current_value,
-                                                    0..=u16::MAX,
-                                                ),
-                                            )
-                                            .changed()
-                                        {
-                                            model.apply_macros = true;
+                                        macro_name(
+                                            ui,
+                                            &control_macro.label,
+                                            palette_label.as_deref(),
+                                        );
+                                        let value_before = control_macro.current_value;
+                                        let response = ui.add_enabled(
+                                            control_macro.animation.is_none(),
+                                            Slider::new(
+                                                &mut control_macro.current_value,
+                                                0..=u16::MAX,
+                                            ),
+                                        );
+                                        if response.changed() {
+                                            model.engine.apply_macros = true;
                                             any_changed = true;
-                                        };
+                                            control_macro.last_source = ValueSource::Macro;
+                                        }
+                                        if response.changed() || response.drag_started() {
+                                            changed_macro = Some((
+                                                i,
+                                                macro_index,
+                                                SceneValue::ControlValue(value_before),
+                                                response,
+                                            ));
+                                        }
 
                                         if let Some(global_index) = control_macro.midi_knob_index {
                                             ui.small(global_index.to_string());
@@ -92,16 +144,30 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
                                         }
                                     }
                                     FixtureMacro::Colour(colour_macro) => {
-                                        ui.label(&colour_macro.label);
+                                        macro_name(
+                                            ui,
+                                            &colour_macro.label,
+                                            palette_label.as_deref(),
+                                        );
+                                        let value_before = colour_macro.current_value;
                                         ui.add_enabled_ui(colour_macro.animation.is_none(), |ui| {
-                                            if ui
-                                                .color_edit_button_srgba(
-                                                    &mut colour_macro.current_value,
-                                                )
-                                                .changed()
-                                            {
-                                                model.apply_macros = true;
+                                            let response = ui
+                                                .horizontal(|ui| {
+                                                    colour_edit(ui, &mut colour_macro.current_value)
+                                                })
+                                                .inner;
+                                            if response.changed() {
+                                                model.engine.apply_macros = true;
                                                 any_changed = true;
+                                                colour_macro.last_source = ValueSource::Macro;
+                                            }
+                                            if response.changed() || response.drag_started() {
+                                                changed_macro = Some((
+                                                    i,
+                                                    macro_index,
+                                                    SceneValue::ColourValue(value_before),
+                                                    response,
+                                                ));
                                             }
                                         });
                                         ui.label(" "); // placeholder to make columns line up
@@ -120,6 +186,103 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
                                             ui.label("");
                                         }
                                     }
+                                    FixtureMacro::Position(position_macro) => {
+                                        macro_name(
+                                            ui,
+                                            &position_macro.label,
+                                            palette_label.as_deref(),
+                                        );
+                                        let value_before = position_macro.current_value;
+                                        let response = xy_pad(
+                                            ui,
+                                            &mut position_macro.current_value,
+                                            position_macro.animation.is_none(),
+                                        );
+                                        if response.changed() {
+                                            model.engine.apply_macros = true;
+                                            any_changed = true;
+                                            position_macro.last_source = ValueSource::Macro;
+                                        }
+                                        if response.changed() || response.drag_started() {
+                                            changed_macro = Some((
+                                                i,
+                                                macro_index,
+                                                SceneValue::PositionValue(value_before),
+                                                response,
+                                            ));
+                                        }
+                                        if let Some(pan_index) = position_macro.midi_knob_index {
+                                            ui.small(format!("{}/{}", pan_index, pan_index + 1));
+                                        } else {
+                                            ui.small("");
+                                        }
+                                        if let Some((animation, _start, _end)) =
+                                            &mut position_macro.animation
+                                        {
+                                            ui.label(
+                                                RichText::new(format!(
+                                                    "{}%",
+                                                    (animation.get_progress() * 100.) as u8
+                                                ))
+                                                .color(Color32::GREEN)
+                                                .small(),
+                                            );
+                                        } else {
+                                            ui.label("");
+                                        }
+                                    }
+                                    FixtureMacro::Temperature(temperature_macro) => {
+                                        macro_name(
+                                            ui,
+                                            &temperature_macro.label,
+                                            palette_label.as_deref(),
+                                        );
+                                        let value_before = temperature_macro.current_value;
+                                        let range = temperature_macro.channels.kelvin_range();
+                                        ui.add_enabled_ui(
+                                            temperature_macro.animation.is_none(),
+                                            |ui| {
+                                                let response = ui
+                                                    .vertical(|ui| {
+                                                        temperature_sliders(
+                                                            ui,
+                                                            &mut temperature_macro.current_value,
+                                                            range,
+                                                        )
+                                                    })
+                                                    .inner;
+                                                if response.changed() {
+                                                    model.engine.apply_macros = true;
+                                                    any_changed = true;
+                                                    temperature_macro.last_source =
+                                                        ValueSource::Macro;
+                                                }
+                                                if response.changed() || response.drag_started() {
+                                                    changed_macro = Some((
+                                                        i,
+                                                        macro_index,
+                                                        SceneValue::TemperatureValue(value_before),
+                                                        response,
+                                                    ));
+                                                }
+                                            },
+                                        );
+                                        ui.label(" "); // placeholder to make columns line up
+                                        if let Some((animation, _start, _end)) =
+                                            &mut temperature_macro.animation
+                                        {
+                                            ui.label(
+                                                RichText::new(format!(
+                                                    "{}%",
+                                                    (animation.get_progress() * 100.) as u8
+                                                ))
+                                                .color(Color32::GREEN)
+                                                .small(),
+                                            );
+                                        } else {
+                                            ui.label("");
+                                        }
+                                    }
                                 }
 
                                 ui.end_row();
@@ -127,7 +290,12 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
                         });
 
                     if any_changed {
-                        if let Some(scene) = model.project.scenes.iter_mut().find(|x| x.last_active)
+                        if let Some(scene) = model
+                            .engine
+                            .project
+                            .scenes
+                            .iter_mut()
+                            .find(|x| x.last_active)
                         {
                             scene.is_editing = true;
                         }
@@ -135,4 +303,98 @@ pub fn render_macro_controls(model: &mut Model, ui: &mut Ui) {
                 });
             }
         });
+
+    if let Some((fixture_index, macro_index, value_before, response)) = changed_macro {
+        let fixture = &model.engine.project.fixtures[fixture_index];
+        let label = format!(
+            "Adjust \"{}\" {}",
+            &fixture.label,
+            fixture.config.active_mode.macros[macro_index].label()
+        );
+        record_widget_change(&mut model.history, &label, &response, || {
+            let mut project_before = model.engine.project.clone();
+            let m = &mut project_before.fixtures[fixture_index]
+                .config
+                .active_mode
+                .macros[macro_index];
+            match (m, value_before.clone()) {
+                (FixtureMacro::Control(control_macro), SceneValue::ControlValue(v)) => {
+                    control_macro.current_value = v;
+                }
+                (FixtureMacro::Colour(colour_macro), SceneValue::ColourValue(c)) => {
+                    colour_macro.current_value = c;
+                }
+                (FixtureMacro::Position(position_macro), SceneValue::PositionValue(p)) => {
+                    position_macro.current_value = p;
+                }
+                (FixtureMacro::Temperature(temperature_macro), SceneValue::TemperatureValue(t)) => {
+                    temperature_macro.current_value = t;
+                }
+                _ => {}
+            }
+            project_before
+        });
+
+        if response.changed() && model.link_positions {
+            if let SceneValue::PositionValue(before) = value_before {
+                aim_together(model, fixture_index, macro_index, before);
+            }
+        }
+    }
+}
+
+/// Move the same Position Macro on every other fixture shown by the same amount
+/// as the one just changed, so that their relative offsets are kept
+fn aim_together(model: &mut Model, fixture_index: usize, macro_index: usize, before: [u16; 2]) {
+    let Some(FixtureMacro::Position(changed)) = model.engine.project.fixtures[fixture_index]
+        .config
+        .active_mode
+        .macros
+        .get(macro_index)
+    else {
+        return;
+    };
+    let label = changed.label.clone();
+    let delta = [
+        changed.current_value[0] as i32 - before[0] as i32,
+        changed.current_value[1] as i32 - before[1] as i32,
+    ];
+
+    for (i, fixture) in model.engine.project.fixtures.iter_mut().enumerate() {
+        let is_shown = model.selected_fixtures.is_empty()
+            || model
+                .selected_fixtures
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&fixture.label));
+        if i == fixture_index || !is_shown {
+            continue;
+        }
+        for m in fixture.config.active_mode.macros.iter_mut() {
+            if let FixtureMacro::Position(position_macro) = m {
+                if position_macro.label == label && position_macro.animation.is_none() {
+                    position_macro.nudge(delta);
+                    position_macro.last_source = ValueSource::Macro;
+                }
+            }
+        }
+    }
+}
+
+/// Macro label, plus the Palette which set its current value (if any)
+fn macro_name(ui: &mut Ui, label: &str, palette_label: Option<&str>) {
+    match palette_label {
+        Some(palette_label) => {
+            ui.vertical(|ui| {
+                ui.label(label);
+                ui.label(
+                    RichText::new(format!("🎨 {}", palette_label))
+                        .small()
+                        .color(Color32::GRAY),
+                );
+            });
+        }
+        None => {
+            ui.label(label);
+        }
+    }
 }
@@ -1,21 +1,51 @@
-use egui::{Color32, Grid, RichText, ScrollArea, Slider, Ui, Vec2};
+use egui::{Color32, Grid, Key, Modifiers, Response, RichText, ScrollArea, Slider, Ui, Vec2};
 use log::{error, info, warn};
 
 use crate::{
-    model::{BehaviourOnExit, Model},
-    project::{artnetconfig::get_artnet_interface, Project},
+    engine::BehaviourOnExit,
+    history::History,
+    model::Model,
+    project::{
+        artnetconfig::get_artnet_interface,
+        autosave::{newer_autosave, remove_autosave},
+        fixture::ValueSource,
+        park::ParkTarget,
+        Project,
+    },
     settings::CHANNELS_PER_UNIVERSE,
 };
 
 use self::{
-    fixture_controls::render_fixture_controls, macro_controls::render_macro_controls,
-    network_controls::render_network_controls, scenes::render_scenes,
+    fixture_controls::render_fixture_controls,
+    groups::render_group_controls,
+    macro_controls::render_macro_controls,
+    monitor::render_monitor,
+    network_controls::render_network_controls,
+    palettes::render_palettes,
+    safety::{render_safety_controls, render_safety_status},
+    scenes::render_scenes,
+    schedule::render_schedule,
+    stage::render_stage_plot,
+    timeline::render_timeline,
 };
 
+pub use self::monitor::MonitorState;
+pub use self::receiver::ReceiverApp;
+
+mod colour_models;
 mod fixture_controls;
+mod groups;
 mod macro_controls;
+mod monitor;
 mod network_controls;
+mod palettes;
+mod receiver;
+mod safety;
 mod scenes;
+mod schedule;
+mod stage;
+mod timeline;
+mod xy_pad;
 
 pub const NARROW_WINDOW: Vec2 = Vec2::new(800., 1024.0);
 pub const WIDER_WINDOW: Vec2 = Vec2::new(1280., 900.);
@@ -26,6 +56,8 @@ pub const WIDER_WINDOW: Vec2 = Vec2::new(1280., 900.);
 pub enum ViewMode {
     Scenes,
     Setup,
+    Stage,
+    Monitor,
 }
 
 pub fn render_gui(model: &mut Model, ctx: &eframe::egui::Context, frame: &mut eframe::Frame) {
@@ -40,21 +72,51 @@ pub fn render_gui(model: &mut Model, ctx: &eframe::egui::Context, frame: &mut ef
         }
     }
 
+    handle_undo_shortcuts(model, ctx);
+
     render_mode_switcher(model, ctx, frame);
 
     match model.view_mode {
         ViewMode::Scenes => {
             egui::SidePanel::left("LeftPanel").show(ctx, |ui| {
                 render_network_controls(model, ui);
+                render_safety_status(model, ui);
                 render_macro_controls(model, ui);
             });
             egui::CentralPanel::default().show(ctx, |ui| {
+                render_palettes(model, ui);
+                ui.separator();
+                render_schedule(model, ui);
+                render_timeline(model, ui);
                 render_scenes(model, ui);
             });
         }
+        ViewMode::Stage => {
+            egui::SidePanel::left("LeftPanel").show(ctx, |ui| {
+                render_network_controls(model, ui);
+                render_safety_status(model, ui);
+                render_macro_controls(model, ui);
+            });
+            egui::CentralPanel::default().show(ctx, |ui| {
+                render_palettes(model, ui);
+                ui.separator();
+                render_stage_plot(model, ui);
+            });
+        }
+        ViewMode::Monitor => {
+            egui::SidePanel::left("LeftPanel").show(ctx, |ui| {
+                render_network_controls(model, ui);
+                render_safety_status(model, ui);
+                render_macro_controls(model, ui);
+            });
+            egui::CentralPanel::default().show(ctx, |ui| {
+                render_monitor(model, ui);
+            });
+        }
         ViewMode::Setup => {
             egui::SidePanel::left("LeftPanel").show(ctx, |ui| {
                 render_network_controls(model, ui);
+                render_safety_status(model, ui);
                 render_macro_controls(model, ui);
             });
 
@@ -63,11 +125,34 @@ pub fn render_gui(model: &mut Model, ctx: &eframe::egui::Context, frame: &mut ef
             });
 
             egui::CentralPanel::default().show(ctx, |ui| {
+                render_safety_controls(model, ui);
+                render_group_controls(model, ui);
                 render_fixture_controls(model, ui);
             });
         }
     }
 
+    if let Some(autosave_path) = model.recoverable_autosave.clone() {
+        egui::Window::new("Recover unsaved changes?")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("An autosave newer than the project file was found:");
+                ui.label(RichText::new(&autosave_path).italics().small());
+                ui.horizontal(|ui| {
+                    if ui.button("Recover").clicked() {
+                        model.recover_autosave();
+                    }
+                    if ui.button("Discard 🗑").clicked() {
+                        if let Some(project_path) = &model.engine.current_project_path {
+                            remove_autosave(project_path);
+                        }
+                        model.recoverable_autosave = None;
+                    }
+                });
+            });
+    }
+
     if model.show_confirm_exit {
         egui::Window::new("Ready to Quit?")
             .collapsible(false)
@@ -84,28 +169,28 @@ pub fn render_gui(model: &mut Model, ctx: &eframe::egui::Context, frame: &mut ef
                         model.allowed_to_close = false;
                     }
                 });
-                ui.checkbox(&mut model.save_on_exit, "Save Project on exit");
+                ui.checkbox(&mut model.engine.save_on_exit, "Save Project on exit");
                 ui.group(|ui| {
                     ui.heading("Behaviour on exit");
                     ui.radio_value(
-                        &mut model.exit_mode,
+                        &mut model.engine.exit_mode,
                         BehaviourOnExit::DoNothing,
                         "Do nothing",
                     );
                     ui.radio_value(
-                        &mut model.exit_mode,
+                        &mut model.engine.exit_mode,
                         BehaviourOnExit::Home,
                         "All fixtures to Home",
                     );
                     ui.radio_value(
-                        &mut model.exit_mode,
+                        &mut model.engine.exit_mode,
                         BehaviourOnExit::Zero,
                         "All channels to Zero",
                     );
                 });
             });
     } else {
-        model.update();
+        model.engine.update();
     }
 }
 
@@ -131,17 +216,35 @@ pub fn render_mode_switcher(
                 {
                     ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(WIDER_WINDOW));
                 }
+                if ui
+                    .selectable_value(&mut model.view_mode, ViewMode::Stage, "Stage")
+                    .clicked()
+                {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(WIDER_WINDOW));
+                }
+                if ui
+                    .selectable_value(&mut model.view_mode, ViewMode::Monitor, "Monitor")
+                    .clicked()
+                {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(WIDER_WINDOW));
+                }
                 ui.label("|");
                 if ui.button("New").clicked() {
-                    // TODO: ask for confirmation first!
+                    // No confirmation needed, since this can be undone
                     warn!("Clearing current project from memory");
-                    model.project = Project::new();
-                    model.current_project_path = None;
+                    model.history.record_with_path(
+                        "New project",
+                        &model.engine.project,
+                        model.engine.current_project_path.as_deref(),
+                    );
+                    model.engine.project = Project::new();
+                    model.engine.current_project_path = None;
+                    model.engine.project_changed();
                 }
-                match &model.current_project_path {
+                match model.engine.current_project_path.clone() {
                     Some(existing_project_path) => {
                         if ui.button("Save").clicked() {
-                            match Project::save(existing_project_path, &model.project) {
+                            match model.engine.save_project_as(&existing_project_path) {
                                 Ok(()) => {
                                     info!("Saved OK!");
                                 }
@@ -157,7 +260,7 @@ pub fn render_mode_switcher(
                                 .add_filter("text", &["json"])
                                 .save_file()
                             {
-                                match Project::save(&path.display().to_string(), &model.project) {
+                                match model.engine.save_project_as(&path.display().to_string()) {
                                     Ok(()) => {
                                         info!("Saved OK!");
                                     }
@@ -176,10 +279,21 @@ pub fn render_mode_switcher(
                     {
                         match Project::load(&path.display().to_string()) {
                             Ok(p) => {
-                                model.project = p;
-                                model.current_project_path = Some(path.display().to_string());
-                                model.artnet =
-                                    get_artnet_interface(&model.settings, &model.project).ok()
+                                model.engine.project = p;
+                                model.engine.project_changed();
+                                model.engine.current_project_path =
+                                    Some(path.display().to_string());
+                                model.recoverable_autosave =
+                                    newer_autosave(&path.display().to_string());
+                                model.engine.artnet = get_artnet_interface(
+                                    &model.engine.settings,
+                                    &model.engine.project,
+                                )
+                                .ok()
+                                .map(|a| {
+                                    a.with_clock(model.engine.clock.clone())
+                                        .with_send_policy(model.engine.settings.send_policy())
+                                })
                             }
                             Err(e) => {
                                 error!(
@@ -191,7 +305,24 @@ pub fn render_mode_switcher(
                         }
                     }
                 }
-                if let Some(existing_project_path) = &model.current_project_path {
+                ui.label("|");
+                let undo_label = model.history.undo_label().map(String::from);
+                if ui
+                    .add_enabled(undo_label.is_some(), egui::Button::new("⟲"))
+                    .on_hover_text(format!("Undo {}", undo_label.unwrap_or_default()))
+                    .clicked()
+                {
+                    model.undo();
+                }
+                let redo_label = model.history.redo_label().map(String::from);
+                if ui
+                    .add_enabled(redo_label.is_some(), egui::Button::new("⟳"))
+                    .on_hover_text(format!("Redo {}", redo_label.unwrap_or_default()))
+                    .clicked()
+                {
+                    model.redo();
+                }
+                if let Some(existing_project_path) = &model.engine.current_project_path {
                     ui.label(
                         RichText::new(existing_project_path)
                             .color(Color32::WHITE)
@@ -210,30 +341,141 @@ pub fn render_mode_switcher(
         });
 }
 
+/// Ctrl+Z / Ctrl+Shift+Z (Cmd on Mac) for undo/redo, unless a text field has focus
+/// (which has its own undo). Releasing the pointer ends any grouped interaction,
+/// e.g. a slider drag, so the next change becomes a new undo step.
+fn handle_undo_shortcuts(model: &mut Model, ctx: &eframe::egui::Context) {
+    if ctx.input(|i| i.pointer.any_released()) {
+        model.history.end_group();
+    }
+    if ctx.wants_keyboard_input() {
+        return;
+    }
+    // Check the more specific shortcut first, since Shift is ignored when matching
+    if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z)) {
+        model.redo();
+    } else if ctx.input_mut(|i| i.consume_key(Modifiers::COMMAND, Key::Z)) {
+        model.undo();
+    }
+}
+
+/// Record the undo step for a change made with a slider (or any draggable
+/// widget). Call when the widget has changed or a drag has just started: the
+/// snapshot is built once, as the interaction starts, rather than on every
+/// frame of a drag.
+pub fn record_widget_change(
+    history: &mut History,
+    label: &str,
+    response: &Response,
+    project_before: impl FnOnce() -> Project,
+) {
+    // Mid-drag, the step was recorded when the drag started
+    if response.dragged() && !response.drag_started() {
+        return;
+    }
+    if history.starts_group(label) {
+        history.record_grouped(label, &project_before());
+    }
+}
+
 pub fn render_sliders(model: &mut Model, ui: &mut Ui) {
     ui.heading("Global Slider Controls");
 
+    let parked = model.engine.project.parked_channels().to_vec();
+    // (label, project before) for the undo history
+    let mut park_change: Option<(String, Project)> = None;
+    // Park value dragged: (park index, value before, slider response)
+    let mut park_adjusted: Option<(usize, u8, Response)> = None;
+
     ScrollArea::vertical()
         .auto_shrink([false, false])
         .show(ui, |ui| {
-            Grid::new("sliders").num_columns(2).show(ui, |ui| {
+            Grid::new("sliders").num_columns(3).show(ui, |ui| {
                 for i in 0..(CHANNELS_PER_UNIVERSE - 1) {
                     let one_indexed_channel = i + 1;
                     let text = format!("Channel #{}", one_indexed_channel);
-                    let is_assigned = model.channels_assigned[one_indexed_channel as usize];
+                    let is_assigned = model.engine.channels_assigned[one_indexed_channel as usize];
                     ui.label(RichText::new(text).color(if is_assigned {
                         Color32::GREEN
                     } else {
                         Color32::GRAY
                     }));
+
+                    let target = ParkTarget::Channel {
+                        channel: one_indexed_channel,
+                    };
+                    let channel_park = model.engine.project.find_park(&target);
+                    let parked_value = parked
+                        .iter()
+                        .rev()
+                        .find(|(c, _)| *c == one_indexed_channel)
+                        .map(|(_, v)| *v);
+                    match (channel_park, parked_value) {
+                        (Some(park_index), _) => {
+                            let value_before = model.engine.project.parks[park_index].value;
+                            let response = ui.add(Slider::new(
+                                &mut model.engine.project.parks[park_index].value,
+                                0..=255,
+                            ));
+                            if response.changed() || response.drag_started() {
+                                park_adjusted = Some((park_index, value_before, response));
+                            }
+                        }
+                        (None, Some(mut value)) => {
+                            ui.add_enabled(false, Slider::new(&mut value, 0..=255))
+                                .on_disabled_hover_text("Parked via a fixture attribute");
+                        }
+                        (None, None) => {
+                            if ui
+                                .add(Slider::new(
+                                    &mut model.engine.channels_state[i as usize],
+                                    0..=255,
+                                ))
+                                .changed()
+                            {
+                                model.engine.apply_macros = false;
+                                model.engine.channel_sources[i as usize] = ValueSource::Slider;
+                            };
+                        }
+                    }
+
+                    let mut is_parked = channel_park.is_some();
                     if ui
-                        .add(Slider::new(&mut model.channels_state[i as usize], 0..=255))
+                        .toggle_value(&mut is_parked, "🅿")
+                        .on_hover_text("Park: always send this value, whatever else is going on")
                         .changed()
                     {
-                        model.apply_macros = false;
-                    };
+                        let project_before = model.engine.project.clone();
+                        model.engine.project.set_park(
+                            target,
+                            is_parked.then_some(model.engine.channels_state[i as usize]),
+                        );
+                        park_change = Some((
+                            format!(
+                                "{} CH{}",
+                                if is_parked { "Park" } else { "Release" },
+                                one_indexed_channel
+                            ),
+                            project_before,
+                        ));
+                    }
                     ui.end_row();
                 }
             });
         });
+
+    if let Some((park_index, value_before, response)) = park_adjusted {
+        let label = format!(
+            "Adjust park {}",
+            model.engine.project.parks[park_index].target.describe()
+        );
+        record_widget_change(&mut model.history, &label, &response, || {
+            let mut project_before = model.engine.project.clone();
+            project_before.parks[park_index].value = value_before;
+            project_before
+        });
+    }
+    if let Some((label, project_before)) = park_change {
+        model.history.record(&label, &project_before);
+    }
 }
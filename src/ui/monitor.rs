@@ -0,0 +1,288 @@
+use std::time::{Duration, Instant};
+
+use egui::{
+    ecolor::Hsva, vec2, Align2, Color32, FontId, Rect, RichText, ScrollArea, Sense, Stroke, Ui,
+};
+
+use crate::{
+    model::Model,
+    project::fixture::{FixtureInstance, Mapping, ValueSource},
+    settings::CHANNELS_PER_UNIVERSE,
+};
+
+pub(super) const COLUMNS: usize = 32;
+pub(super) const CELL_SIZE: [f32; 2] = [34.0, 22.0];
+/// How long a changed value stays highlighted
+const CHANGE_HIGHLIGHT: Duration = Duration::from_millis(500);
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum MonitorFormat {
+    Decimal,
+    Percent,
+    Hex,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum MonitorColouring {
+    Fixture,
+    Source,
+}
+
+/// Display options for the output monitor, plus the previous frame so that
+/// recently-changed channels can be highlighted
+pub struct MonitorState {
+    pub format: MonitorFormat,
+    pub colouring: MonitorColouring,
+    previous_frame: Vec<u8>,
+    changed_at: Vec<Option<Instant>>,
+}
+
+impl Default for MonitorState {
+    fn default() -> Self {
+        MonitorState {
+            format: MonitorFormat::Decimal,
+            colouring: MonitorColouring::Fixture,
+            previous_frame: Vec::new(),
+            changed_at: [None].repeat(CHANNELS_PER_UNIVERSE as usize),
+        }
+    }
+}
+
+impl MonitorState {
+    fn track_changes(&mut self, frame: &[u8]) {
+        let now = Instant::now();
+        for (i, value) in frame.iter().enumerate() {
+            if self
+                .previous_frame
+                .get(i)
+                .is_some_and(|previous| previous != value)
+            {
+                if let Some(t) = self.changed_at.get_mut(i) {
+                    *t = Some(now);
+                }
+            }
+        }
+        self.previous_frame = frame.to_vec();
+    }
+
+    /// 1.0 if the channel has only just changed, fading to 0.0
+    fn highlight(&self, channel_index: usize) -> f32 {
+        match self.changed_at.get(channel_index) {
+            Some(Some(t)) => {
+                1.0 - (t.elapsed().as_secs_f32() / CHANGE_HIGHLIGHT.as_secs_f32()).min(1.0)
+            }
+            _ => 0.,
+        }
+    }
+}
+
+/// Compact grid of the frame actually being transmitted via ArtNet
+pub fn render_monitor(model: &mut Model, ui: &mut Ui) {
+    let (frame, is_live) = match &model.engine.artnet {
+        Some(artnet) if !artnet.get_state().is_empty() => (artnet.get_state().to_vec(), true),
+        _ => (model.engine.channels_state.clone(), false),
+    };
+    model.monitor.track_changes(&frame);
+
+    ui.horizontal(|ui| {
+        ui.heading("Output Monitor");
+        if is_live {
+            ui.label(RichText::new("transmitted").color(Color32::DARK_GREEN));
+        } else {
+            ui.label(RichText::new("not sending; showing channel state").color(Color32::GRAY));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Values:");
+        let format = &mut model.monitor.format;
+        ui.selectable_value(format, MonitorFormat::Decimal, "Dec");
+        ui.selectable_value(format, MonitorFormat::Percent, "%");
+        ui.selectable_value(format, MonitorFormat::Hex, "Hex");
+        ui.label("|");
+        ui.label("Colour by:");
+        let colouring = &mut model.monitor.colouring;
+        ui.selectable_value(colouring, MonitorColouring::Fixture, "Fixture");
+        ui.selectable_value(colouring, MonitorColouring::Source, "Source");
+    });
+    if model.monitor.colouring == MonitorColouring::Source {
+        ui.horizontal(|ui| {
+            for source in [
+                ValueSource::Home,
+                ValueSource::Slider,
+                ValueSource::Macro,
+                ValueSource::Scene,
+                ValueSource::Remote,
+                ValueSource::Parked,
+            ] {
+                ui.label(RichText::new(source_label(source)).color(source_colour(source)));
+            }
+        });
+    }
+    ui.separator();
+
+    let parked = model.engine.project.parked_channels().to_vec();
+    let owners = channel_owners(&model.engine.project.fixtures);
+
+    ScrollArea::both()
+        .auto_shrink([false, false])
+        .show(ui, |ui| {
+            ui.spacing_mut().item_spacing = vec2(2., 2.);
+            for row in frame.chunks(COLUMNS).enumerate() {
+                let (row_index, values) = row;
+                ui.horizontal(|ui| {
+                    ui.add_sized(
+                        vec2(CELL_SIZE[0], CELL_SIZE[1]),
+                        egui::Label::new(
+                            RichText::new(format!("{}", row_index * COLUMNS + 1))
+                                .small()
+                                .color(Color32::GRAY),
+                        ),
+                    );
+                    for (column_index, value) in values.iter().enumerate() {
+                        let channel_index = row_index * COLUMNS + column_index;
+                        let owner = owners.get(channel_index).and_then(|o| *o);
+                        let is_parked =
+                            parked.iter().any(|(c, _)| *c as usize == channel_index + 1);
+                        let source = if is_parked {
+                            ValueSource::Parked
+                        } else {
+                            model
+                                .engine
+                                .channel_sources
+                                .get(channel_index)
+                                .copied()
+                                .unwrap_or_default()
+                        };
+                        let background = match model.monitor.colouring {
+                            MonitorColouring::Fixture => match owner {
+                                Some((fixture_index, _)) => fixture_colour(fixture_index),
+                                None => Color32::from_gray(32),
+                            },
+                            MonitorColouring::Source => source_colour(source).gamma_multiply(0.5),
+                        };
+
+                        let (rect, response) = ui
+                            .allocate_exact_size(vec2(CELL_SIZE[0], CELL_SIZE[1]), Sense::hover());
+                        draw_cell(
+                            ui,
+                            rect,
+                            background,
+                            model.monitor.highlight(channel_index),
+                            &format_value(*value, model.monitor.format),
+                            is_parked,
+                        );
+
+                        response.on_hover_ui(|ui| {
+                            ui.label(
+                                RichText::new(format!("DMX CH{}", channel_index + 1)).strong(),
+                            );
+                            ui.label(format!(
+                                "Value: {} / {:.0}% / 0x{:02X}",
+                                value,
+                                *value as f32 / 255. * 100.,
+                                value
+                            ));
+                            match owner {
+                                Some((fixture_index, mapping)) => {
+                                    let fixture = &model.engine.project.fixtures[fixture_index];
+                                    ui.label(format!(
+                                        "{} ({}) CH{}: {}",
+                                        fixture.label,
+                                        fixture.config.name,
+                                        mapping.channel,
+                                        mapping.label
+                                    ));
+                                }
+                                None => {
+                                    ui.label(RichText::new("Unassigned").color(Color32::GRAY));
+                                }
+                            }
+                            ui.label(format!("Set by: {}", source_label(source)));
+                        });
+                    }
+                });
+            }
+        });
+}
+
+pub(super) fn draw_cell(
+    ui: &Ui,
+    rect: Rect,
+    background: Color32,
+    highlight: f32,
+    text: &str,
+    is_parked: bool,
+) {
+    let painter = ui.painter();
+    painter.rect_filled(rect, 2.0, background);
+    if is_parked {
+        painter.rect_stroke(rect.shrink(1.0), 2.0, Stroke::new(1.0, Color32::WHITE));
+    }
+    if highlight > 0. {
+        painter.rect_stroke(
+            rect,
+            2.0,
+            Stroke::new(2.0, Color32::YELLOW.gamma_multiply(highlight)),
+        );
+    }
+    painter.text(
+        rect.center(),
+        Align2::CENTER_CENTER,
+        text,
+        FontId::monospace(11.0),
+        Color32::WHITE,
+    );
+}
+
+/// For each (zero-indexed) DMX channel, the index of the fixture using it
+/// and the mapping for that channel
+fn channel_owners(fixtures: &[FixtureInstance]) -> Vec<Option<(usize, &Mapping)>> {
+    let mut owners = [None].repeat(CHANNELS_PER_UNIVERSE as usize);
+    for (fixture_index, fixture) in fixtures.iter().enumerate() {
+        for m in &fixture.config.active_mode.mappings {
+            let channel_index = (m.channel - 1) + (fixture.start_channel - 1);
+            if let Some(owner) = owners.get_mut(channel_index as usize) {
+                *owner = Some((fixture_index, m));
+            }
+        }
+    }
+    owners
+}
+
+pub(super) fn format_value(value: u8, format: MonitorFormat) -> String {
+    match format {
+        MonitorFormat::Decimal => value.to_string(),
+        MonitorFormat::Percent => format!("{:.0}", value as f32 / 255. * 100.),
+        MonitorFormat::Hex => format!("{:02X}", value),
+    }
+}
+
+/// Distinct hues for neighbouring fixtures
+fn fixture_colour(fixture_index: usize) -> Color32 {
+    let hue = (fixture_index as f32 * 0.618034).fract();
+    Hsva::new(hue, 0.6, 0.45, 1.0).into()
+}
+
+fn source_colour(source: ValueSource) -> Color32 {
+    match source {
+        ValueSource::None => Color32::from_gray(64),
+        ValueSource::Home => Color32::from_rgb(120, 120, 160),
+        ValueSource::Slider => Color32::from_rgb(200, 140, 40),
+        ValueSource::Macro => Color32::from_rgb(60, 160, 220),
+        ValueSource::Scene => Color32::from_rgb(170, 90, 210),
+        ValueSource::Remote => Color32::from_rgb(60, 190, 90),
+        ValueSource::Parked => Color32::from_rgb(200, 60, 60),
+    }
+}
+
+fn source_label(source: ValueSource) -> &'static str {
+    match source {
+        ValueSource::None => "None",
+        ValueSource::Home => "Home",
+        ValueSource::Slider => "Slider",
+        ValueSource::Macro => "Macro",
+        ValueSource::Scene => "Scene",
+        ValueSource::Remote => "Remote",
+        ValueSource::Parked => "Parked",
+    }
+}
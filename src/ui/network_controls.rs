@@ -7,9 +7,10 @@ use egui::{Color32, RichText, Ui};
 use log::debug;
 
 use crate::{
-    artnet::{ArtNetInterface, ArtNetMode},
-    model::{attempt_connection, Model, TetherStatus},
-    project::artnetconfig::ArtNetConfigMode,
+    artnet::{ArtNetInterface, ArtNetMode, PortAddress, Reachability, SendPolicy},
+    engine::TetherStatus,
+    model::Model,
+    project::artnetconfig::{destination_addresses, output_port_address, ArtNetConfigMode},
     settings::{UNICAST_DST_STRING, UNICAST_SRC_STRING},
 };
 use anyhow::anyhow;
@@ -18,7 +19,7 @@ pub fn render_network_controls(model: &mut Model, ui: &mut Ui) {
     ui.horizontal(|ui| {
         ui.heading("Tether");
 
-        match &model.tether_status {
+        match &model.engine.tether_status {
             TetherStatus::NotConnected => {
                 ui.label(RichText::new("Not (yet) connected").color(Color32::YELLOW));
                 offer_tether_connect(model, ui);
@@ -33,7 +34,7 @@ pub fn render_network_controls(model: &mut Model, ui: &mut Ui) {
         }
     });
 
-    if let Some(artnet) = &model.artnet {
+    if let Some(artnet) = &model.engine.artnet {
         let mut should_clear = false;
         ui.horizontal(|ui| {
             ui.heading("ArtNet");
@@ -41,21 +42,42 @@ pub fn render_network_controls(model: &mut Model, ui: &mut Ui) {
                 ArtNetMode::Broadcast => {
                     ui.label(RichText::new("Broadcast Mode").color(Color32::LIGHT_YELLOW));
                 }
-                ArtNetMode::Unicast(src, dst) => {
+                ArtNetMode::Unicast(src, _) => {
                     ui.horizontal(|ui| {
                         ui.label(RichText::new("Unicast Mode: ").color(Color32::LIGHT_GREEN));
-                        ui.small(format!("{} => {}", src, dst));
+                        ui.small(format!("from {}", src));
                     });
                 }
             }
             if ui.button("✏").clicked() {
                 debug!("Edit (and disable) ArtNet interface");
-                // model.artnet = None;
+                // model.engine.artnet = None;
                 should_clear = true;
             }
         });
+        if let ArtNetMode::Unicast(..) = artnet.mode_in_use() {
+            for (destination, reachability) in artnet.destinations() {
+                ui.horizontal(|ui| {
+                    ui.small(format!("=> {}", destination.address));
+                    let (text, colour) = match reachability {
+                        Reachability::Waiting => (String::from("polling..."), Color32::GRAY),
+                        Reachability::Reachable => (
+                            format!(
+                                "reachable ({})",
+                                destination.node_name.as_deref().unwrap_or("unnamed")
+                            ),
+                            Color32::LIGHT_GREEN,
+                        ),
+                        Reachability::Unreachable => {
+                            (String::from("no ArtPollReply"), Color32::LIGHT_RED)
+                        }
+                    };
+                    ui.small(RichText::new(text).color(colour));
+                });
+            }
+        }
         if should_clear {
-            model.artnet = None;
+            model.engine.artnet = None;
         }
     } else {
         ui.horizontal(|ui| {
@@ -70,63 +92,141 @@ pub fn render_network_controls(model: &mut Model, ui: &mut Ui) {
             );
             ui.radio_value(
                 &mut model.artnet_edit_mode,
-                ArtNetConfigMode::Unicast(UNICAST_SRC_STRING.into(), UNICAST_DST_STRING.into()),
+                ArtNetConfigMode::Unicast(
+                    UNICAST_SRC_STRING.into(),
+                    vec![UNICAST_DST_STRING.into()],
+                ),
                 "Unicast mode",
             );
         });
         match &mut model.artnet_edit_mode {
             ArtNetConfigMode::Broadcast => (), // no settings for broadcast
-            ArtNetConfigMode::Unicast(src, dst) => {
+            ArtNetConfigMode::Unicast(src, destinations) => {
                 ui.horizontal(|ui| {
                     ui.label("Network Interface IP");
                     ui.text_edit_singleline(src);
                 });
-                ui.horizontal(|ui| {
-                    ui.label("Destination/ArtNet IP");
-                    ui.text_edit_singleline(dst);
-                });
+                let mut remove_index = None;
+                for (i, destination) in destinations.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label("Destination/ArtNet IP");
+                        ui.text_edit_singleline(destination);
+                        if i > 0 && ui.button("🗑").clicked() {
+                            remove_index = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove_index {
+                    destinations.remove(i);
+                }
+                if ui.button("Add destination").clicked() {
+                    destinations.push(UNICAST_DST_STRING.into());
+                }
             }
         }
         if ui.button("Apply & Connect").clicked() {
+            let port_address = output_port_address(&model.engine.settings, &model.engine.project);
             let new_artnet_interface: Result<ArtNetInterface, anyhow::Error> =
                 match &model.artnet_edit_mode {
                     ArtNetConfigMode::Broadcast => ArtNetInterface::new(
                         ArtNetMode::Broadcast,
-                        model.settings.artnet_update_frequency,
-                        model.settings.artnet_universe,
+                        model.engine.settings.artnet_update_frequency,
+                        port_address,
                     ),
-                    ArtNetConfigMode::Unicast(src, dst) => {
-                        let src_parsed = Ipv4Addr::from_str(src);
-                        let dst_parsed = Ipv4Addr::from_str(dst);
-                        if src_parsed.is_ok() && dst_parsed.is_ok() {
-                            ArtNetInterface::new(
-                                ArtNetMode::Unicast(
-                                    SocketAddr::from((Ipv4Addr::from_str(src).unwrap(), 6453)),
-                                    SocketAddr::from((Ipv4Addr::from_str(dst).unwrap(), 6454)),
-                                ),
-                                model.settings.artnet_update_frequency,
-                                model.settings.artnet_universe,
-                            )
-                        } else {
-                            Err(anyhow!("Invalid IP address string"))
+                    ArtNetConfigMode::Unicast(src, destinations) => {
+                        match (Ipv4Addr::from_str(src), destination_addresses(destinations)) {
+                            (Ok(src), Ok(destinations)) => ArtNetInterface::new(
+                                ArtNetMode::Unicast(SocketAddr::from((src, 6453)), destinations),
+                                model.engine.settings.artnet_update_frequency,
+                                port_address,
+                            ),
+                            _ => Err(anyhow!("Invalid IP address string")),
                         }
                     }
                 };
             if let Ok(interface) = new_artnet_interface {
-                model.project.artnet_config = Some(ArtNetConfigMode::from(&interface));
-                model.artnet = Some(interface);
+                model.engine.project.artnet_config = Some(ArtNetConfigMode::from(&interface));
+                model.engine.artnet = Some(
+                    interface
+                        .with_clock(model.engine.clock.clone())
+                        .with_send_policy(model.engine.settings.send_policy()),
+                );
             }
         }
     }
-    ui.horizontal(|ui| {
-        ui.label("Output Universe");
-        ui.label(format!("{}", model.settings.artnet_universe));
-    });
+    match &model.engine.artnet {
+        Some(artnet) => render_output_stats(artnet, ui),
+        None => {
+            ui.horizontal(|ui| {
+                ui.label("Output Port-Address");
+                render_port_address(
+                    output_port_address(&model.engine.settings, &model.engine.project),
+                    ui,
+                );
+            });
+        }
+    }
     ui.separator();
 }
 
+/// How (and how much) is being sent, per universe
+fn render_output_stats(artnet: &ArtNetInterface, ui: &mut Ui) {
+    match artnet.send_policy() {
+        SendPolicy::Continuous => {
+            ui.label("Sending every frame");
+        }
+        SendPolicy::OnChange { keep_alive } => {
+            ui.label(format!(
+                "Sending on change, keep-alive every {:.1}s",
+                keep_alive.as_secs_f32()
+            ));
+        }
+    }
+    let stats = artnet.stats();
+    egui::Grid::new("universe_stats")
+        .num_columns(6)
+        .striped(true)
+        .show(ui, |ui| {
+            for heading in [
+                "Port-Address",
+                "Packets",
+                "Keep-alive",
+                "Unchanged",
+                "Errors",
+                "Rate",
+            ] {
+                ui.label(RichText::new(heading).strong());
+            }
+            ui.end_row();
+            render_port_address(artnet.port_address(), ui);
+            ui.label(format!("{}", stats.packets));
+            ui.label(format!("{}", stats.keep_alives));
+            ui.label(format!("{}", stats.unchanged));
+            ui.label(
+                RichText::new(format!("{}", stats.errors)).color(if stats.errors > 0 {
+                    Color32::RED
+                } else {
+                    Color32::GRAY
+                }),
+            );
+            ui.label(format!("{:.1}/s", stats.packets_per_second));
+            ui.end_row();
+        });
+}
+
+/// net:sub-net:universe, with the 15-bit number as it appears on some nodes
+fn render_port_address(port_address: PortAddress, ui: &mut Ui) {
+    ui.label(format!("{}", port_address)).on_hover_text(format!(
+        "Net {}, sub-net {}, universe {} (Port-Address {})",
+        port_address.net,
+        port_address.sub_net,
+        port_address.universe,
+        u16::from(port_address)
+    ));
+}
+
 fn offer_tether_connect(model: &mut Model, ui: &mut Ui) {
     if ui.button("Connect").clicked() {
-        attempt_connection(model);
+        model.engine.connect_tether();
     }
 }
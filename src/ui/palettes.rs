@@ -0,0 +1,287 @@
+use egui::{vec2, CollapsingHeader, Color32, ComboBox, RichText, Ui};
+
+use crate::{
+    model::Model,
+    project::{
+        fixture::FixtureInstance,
+        palette::{Palette, PaletteKind, PALETTE_KINDS},
+        scene::SceneValue,
+    },
+};
+
+const SWATCH_SIZE: f32 = 32.0;
+
+enum PaletteAction {
+    Apply(usize),
+    UpdateFromCurrent(usize),
+    StartRename(usize),
+    Delete(usize),
+}
+
+/// Swatch grid of the project's palettes. Clicking a swatch applies it to the
+/// fixtures selected on the stage plot, or all fixtures if none are selected.
+pub fn render_palettes(model: &mut Model, ui: &mut Ui) {
+    CollapsingHeader::new(RichText::new("Palettes").heading())
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(if model.selected_fixtures.is_empty() {
+                    String::from("Click to apply to all fixtures; right-click to edit")
+                } else {
+                    format!(
+                        "Click to apply to {} selected fixture(s); right-click to edit",
+                        model.selected_fixtures.len()
+                    )
+                })
+                .small()
+                .color(Color32::GRAY),
+            );
+
+            let mut action = None;
+            for kind in PALETTE_KINDS {
+                if !model.engine.project.palettes.iter().any(|p| p.kind == kind) {
+                    continue;
+                }
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(RichText::new(kind_label(kind)).strong());
+                    for (index, palette) in model.engine.project.palettes.iter().enumerate() {
+                        if palette.kind == kind {
+                            if let Some(a) = render_swatch(model, ui, index, palette) {
+                                action = Some(a);
+                            }
+                        }
+                    }
+                });
+            }
+
+            let source = capture_source(model).map(|f| f.label.clone());
+            ui.horizontal(|ui| {
+                ComboBox::from_id_source("new_palette_kind")
+                    .selected_text(kind_label(model.new_palette_kind))
+                    .show_ui(ui, |ui| {
+                        for kind in PALETTE_KINDS {
+                            ui.selectable_value(
+                                &mut model.new_palette_kind,
+                                kind,
+                                kind_label(kind),
+                            );
+                        }
+                    });
+                let button = ui.add_enabled(source.is_some(), egui::Button::new("＋ Capture"));
+                if let Some(source) = &source {
+                    if button
+                        .on_hover_text(format!(
+                            "New palette from the current values of \"{}\"",
+                            source
+                        ))
+                        .clicked()
+                    {
+                        capture_new_palette(model);
+                    }
+                }
+            });
+
+            match action {
+                Some(PaletteAction::Apply(index)) => {
+                    let label = model.engine.project.palettes[index].label.clone();
+                    let targets = if model.selected_fixtures.is_empty() {
+                        None
+                    } else {
+                        Some(model.selected_fixtures.clone())
+                    };
+                    model.engine.apply_palette(&label, None, targets);
+                }
+                Some(PaletteAction::UpdateFromCurrent(index)) => {
+                    if let Some(fixture) = capture_source(model) {
+                        let palette = &model.engine.project.palettes[index];
+                        let updated = Palette::capture(&palette.label, palette.kind, fixture);
+                        model.history.record(
+                            &format!("Update palette \"{}\"", &palette.label),
+                            &model.engine.project,
+                        );
+                        model.engine.project.palettes[index] = updated;
+                    }
+                }
+                Some(PaletteAction::StartRename(index)) => {
+                    model.renaming_palette =
+                        Some((index, model.engine.project.palettes[index].label.clone()));
+                }
+                Some(PaletteAction::Delete(index)) => {
+                    model.history.record(
+                        &format!(
+                            "Delete palette \"{}\"",
+                            &model.engine.project.palettes[index].label
+                        ),
+                        &model.engine.project,
+                    );
+                    model.engine.project.remove_palette(index);
+                    model.renaming_palette = None;
+                }
+                None => {}
+            }
+
+            render_rename(model, ui);
+        });
+}
+
+fn render_swatch(
+    model: &Model,
+    ui: &mut Ui,
+    index: usize,
+    palette: &Palette,
+) -> Option<PaletteAction> {
+    let button = match palette.swatch_colour() {
+        Some(colour) => egui::Button::new("")
+            .fill(colour)
+            .min_size(vec2(SWATCH_SIZE, SWATCH_SIZE)),
+        None => egui::Button::new(&palette.label).min_size(vec2(0., SWATCH_SIZE)),
+    };
+    let response = ui.add(button);
+
+    let scenes = model.engine.project.scenes_using_palette(&palette.label);
+    let response = response.on_hover_ui(|ui| {
+        ui.label(RichText::new(&palette.label).strong());
+        for (macro_label, value) in palette.values.iter() {
+            ui.label(format!("{}: {}", macro_label, describe_value(value)));
+        }
+        if !scenes.is_empty() {
+            ui.label(
+                RichText::new(format!("Used by: {}", scenes.join(", ")))
+                    .small()
+                    .color(Color32::GRAY),
+            );
+        }
+    });
+
+    let mut action = None;
+    if response.clicked() {
+        action = Some(PaletteAction::Apply(index));
+    }
+    response.context_menu(|ui| {
+        if ui
+            .add_enabled(
+                capture_source(model).is_some(),
+                egui::Button::new("Update from current"),
+            )
+            .clicked()
+        {
+            action = Some(PaletteAction::UpdateFromCurrent(index));
+            ui.close_menu();
+        }
+        if ui.button("Rename").clicked() {
+            action = Some(PaletteAction::StartRename(index));
+            ui.close_menu();
+        }
+        if ui.button("Delete 🗑").clicked() {
+            action = Some(PaletteAction::Delete(index));
+            ui.close_menu();
+        }
+    });
+    action
+}
+
+fn render_rename(model: &mut Model, ui: &mut Ui) {
+    let Some((index, new_label)) = &mut model.renaming_palette else {
+        return;
+    };
+    let index = *index;
+    let mut done = false;
+    let mut apply = false;
+    ui.horizontal(|ui| {
+        ui.label("Rename palette:");
+        ui.text_edit_singleline(new_label);
+        if ui.button("OK ✅").clicked() {
+            apply = true;
+            done = true;
+        }
+        if ui.button("Cancel 🗙").clicked() {
+            done = true;
+        }
+    });
+    if apply {
+        let new_label = new_label.trim().to_string();
+        if !new_label.is_empty() && index < model.engine.project.palettes.len() {
+            model.history.record(
+                &format!(
+                    "Rename palette \"{}\"",
+                    &model.engine.project.palettes[index].label
+                ),
+                &model.engine.project,
+            );
+            model.engine.project.rename_palette(index, &new_label);
+        }
+    }
+    if done {
+        model.renaming_palette = None;
+    }
+}
+
+fn capture_new_palette(model: &mut Model) {
+    let kind = model.new_palette_kind;
+    let Some(fixture) = capture_source(model) else {
+        return;
+    };
+    let count = model
+        .engine
+        .project
+        .palettes
+        .iter()
+        .filter(|p| p.kind == kind)
+        .count();
+    let mut label = format!("{} {}", kind_label(kind), count + 1);
+    let mut n = count + 1;
+    while model.engine.project.find_palette(&label).is_some() {
+        n += 1;
+        label = format!("{} {}", kind_label(kind), n);
+    }
+    let palette = Palette::capture(&label, kind, fixture);
+    model.history.record(
+        &format!("Add palette \"{}\"", &label),
+        &model.engine.project,
+    );
+    model.engine.project.palettes.push(palette);
+}
+
+/// Palettes are captured from the first fixture selected on the stage plot,
+/// or otherwise the fixture currently selected for MIDI control
+fn capture_source(model: &Model) -> Option<&FixtureInstance> {
+    match model.selected_fixtures.first() {
+        Some(label) => model
+            .engine
+            .project
+            .fixtures
+            .iter()
+            .find(|f| f.label.eq_ignore_ascii_case(label)),
+        None => model
+            .engine
+            .project
+            .fixtures
+            .get(model.engine.selected_macro_group_index),
+    }
+}
+
+fn kind_label(kind: PaletteKind) -> &'static str {
+    match kind {
+        PaletteKind::Colour => "Colour",
+        PaletteKind::Position => "Position",
+        PaletteKind::Beam => "Beam",
+        PaletteKind::Intensity => "Intensity",
+    }
+}
+
+fn describe_value(value: &SceneValue) -> String {
+    match value {
+        SceneValue::ControlValue(v) => format!("{:.0}%", *v as f32 / u16::MAX as f32 * 100.),
+        SceneValue::ColourValue(c) => {
+            let [r, g, b, a] = c.to_array();
+            format!("RGBA {} {} {} {}", r, g, b, a)
+        }
+        SceneValue::PositionValue([pan, tilt]) => format!(
+            "pan {:.0}%, tilt {:.0}%",
+            *pan as f32 / u16::MAX as f32 * 100.,
+            *tilt as f32 / u16::MAX as f32 * 100.
+        ),
+        SceneValue::TemperatureValue(t) => format!("{}K, tint {:+.2}", t.kelvin, t.tint),
+        SceneValue::Palette(label) => format!("palette \"{}\"", label),
+    }
+}
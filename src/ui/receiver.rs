@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use egui::{vec2, Color32, RichText, ScrollArea, Sense};
+
+use crate::{artnet::PortAddress, receiver::VirtualReceiver};
+
+use super::monitor::{draw_cell, format_value, MonitorFormat, CELL_SIZE, COLUMNS};
+
+/// A universe is shown as stale if nothing has arrived for this long
+const STALE_AFTER: Duration = Duration::from_secs(2);
+
+/// Standalone window showing what a VirtualReceiver picks up
+pub struct ReceiverApp {
+    receiver: VirtualReceiver,
+    format: MonitorFormat,
+}
+
+impl ReceiverApp {
+    pub fn new(receiver: VirtualReceiver) -> Self {
+        ReceiverApp {
+            receiver,
+            format: MonitorFormat::Decimal,
+        }
+    }
+}
+
+impl eframe::App for ReceiverApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while self.receiver.receive().is_some() {}
+        ctx.request_repaint();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("ArtNet Receiver");
+                if let Ok(addr) = self.receiver.local_addr() {
+                    ui.label(RichText::new(format!("listening on {}", addr)).color(Color32::GRAY));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Values:");
+                ui.selectable_value(&mut self.format, MonitorFormat::Decimal, "Dec");
+                ui.selectable_value(&mut self.format, MonitorFormat::Percent, "%");
+                ui.selectable_value(&mut self.format, MonitorFormat::Hex, "Hex");
+                ui.label("|");
+                ui.label(format!("ArtSync: {}", self.receiver.syncs));
+                if let Some((timecode, rate)) = self.receiver.last_timecode {
+                    ui.label(format!("ArtTimeCode: {} ({} fps)", timecode, rate.fps()));
+                }
+            });
+            ui.separator();
+
+            if self.receiver.universes.is_empty() {
+                ui.label(RichText::new("Nothing received yet").italics());
+            }
+
+            ScrollArea::both()
+                .auto_shrink([false, false])
+                .show(ui, |ui| {
+                    for (port_address, universe) in self.receiver.universes.iter() {
+                        let colour = if universe.last_received.elapsed() < STALE_AFTER {
+                            Color32::DARK_GREEN
+                        } else {
+                            Color32::GRAY
+                        };
+                        let full_address = PortAddress::try_from(*port_address)
+                            .map(|p| p.to_string())
+                            .unwrap_or_default();
+                        ui.label(
+                            RichText::new(format!(
+                                "Universe {} ({}) from {} ({} frames)",
+                                port_address, full_address, universe.from, universe.frames
+                            ))
+                            .strong()
+                            .color(colour),
+                        );
+                        ui.spacing_mut().item_spacing = vec2(2., 2.);
+                        for (row_index, values) in universe.data.chunks(COLUMNS).enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.add_sized(
+                                    vec2(CELL_SIZE[0], CELL_SIZE[1]),
+                                    egui::Label::new(
+                                        RichText::new(format!("{}", row_index * COLUMNS + 1))
+                                            .small()
+                                            .color(Color32::GRAY),
+                                    ),
+                                );
+                                for value in values {
+                                    let (rect, _) = ui.allocate_exact_size(
+                                        vec2(CELL_SIZE[0], CELL_SIZE[1]),
+                                        Sense::hover(),
+                                    );
+                                    draw_cell(
+                                        ui,
+                                        rect,
+                                        Color32::from_gray(32),
+                                        0.,
+                                        &format_value(*value, self.format),
+                                        false,
+                                    );
+                                }
+                            });
+                        }
+                        ui.separator();
+                    }
+                });
+        });
+    }
+}
@@ -0,0 +1,74 @@
+use egui::{CollapsingHeader, Color32, RichText, Slider, Ui};
+
+use crate::{model::Model, project::safety::SafetyConfig};
+
+/// Whether the safety limiter is on, and whether it is currently holding anything back
+pub fn render_safety_status(model: &Model, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.heading("Safety");
+        match &model.engine.project.safety {
+            Some(safety) if safety.enabled => {
+                match model
+                    .engine
+                    .artnet
+                    .as_ref()
+                    .map(|a| a.safety_limiter())
+                    .filter(|l| l.is_limiting(model.engine.clock.now()))
+                    .and_then(|l| l.last_limited.as_ref())
+                {
+                    Some((_, reason)) => {
+                        ui.label(RichText::new("⚠ Limiting").color(Color32::LIGHT_RED))
+                            .on_hover_text(reason);
+                    }
+                    None => {
+                        ui.label(RichText::new("On").color(Color32::LIGHT_GREEN));
+                    }
+                }
+            }
+            _ => {
+                ui.label(RichText::new("Off").color(Color32::GRAY));
+            }
+        }
+    });
+}
+
+/// Flash and strobe limits for the project (Setup mode)
+pub fn render_safety_controls(model: &mut Model, ui: &mut Ui) {
+    CollapsingHeader::new(RichText::new("Safety").heading())
+        .default_open(false)
+        .show(ui, |ui| {
+            let mut edited = model.engine.project.safety.clone();
+
+            let mut use_limiter = edited.is_some();
+            if ui
+                .checkbox(&mut use_limiter, "Limit flashing and strobing")
+                .changed()
+            {
+                edited = use_limiter.then(SafetyConfig::default);
+            }
+
+            if let Some(safety) = &mut edited {
+                ui.checkbox(&mut safety.enabled, "Enabled");
+                ui.add(
+                    Slider::new(&mut safety.max_flash_hz, 0.5..=10.).text("max flashes per second"),
+                );
+                ui.add(
+                    Slider::new(&mut safety.max_flash_area, 0. ..=1.)
+                        .text("max fraction of rig flashing"),
+                );
+                ui.add(Slider::new(&mut safety.flash_threshold, 1..=255).text("flash threshold"));
+                ui.checkbox(&mut safety.allow_strobe, "Allow fixture strobe effects");
+                ui.checkbox(
+                    &mut safety.allow_remote_override,
+                    "Allow remote messages to disable",
+                );
+            }
+
+            if edited != model.engine.project.safety {
+                model
+                    .history
+                    .record_grouped("Edit safety limits", &model.engine.project);
+                model.engine.project.safety = edited;
+            }
+        });
+}
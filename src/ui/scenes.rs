@@ -6,7 +6,7 @@ use log::debug;
 use crate::{
     model::Model,
     project::{
-        fixture::FixtureMacro,
+        palette::resolve_scene_value,
         scene::{Scene, SceneState, SceneValue},
     },
 };
@@ -24,27 +24,14 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
 
     ScrollArea::new([false, true]).show(ui, |ui| {
         if ui.button("+ Add New").clicked() {
-            let label = format!("New Scene {}", model.project.scenes.len());
+            let label = format!("New Scene {}", model.engine.project.scenes.len());
 
             let mut state = IndexMap::<String, SceneState>::new();
 
-            for fixture in model.project.fixtures.iter() {
+            for fixture in model.engine.project.fixtures.iter() {
                 let mut m_state: IndexMap<String, SceneValue> = IndexMap::new();
                 for m in fixture.config.active_mode.macros.iter() {
-                    match m {
-                        FixtureMacro::Control(control_macro) => {
-                            m_state.insert(
-                                String::from(&control_macro.label),
-                                SceneValue::ControlValue(control_macro.current_value),
-                            );
-                        }
-                        FixtureMacro::Colour(colour_macro) => {
-                            m_state.insert(
-                                String::from(&colour_macro.label),
-                                SceneValue::ColourValue(colour_macro.current_value),
-                            );
-                        }
-                    };
+                    m_state.insert(String::from(m.label()), m.scene_value());
                 }
                 state.insert(String::from(&fixture.label), m_state);
             }
@@ -60,7 +47,8 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
 
         ui.separator();
 
-        for (scene_index, scene) in model.project.scenes.iter_mut().enumerate() {
+        let palettes = model.engine.project.palettes.clone();
+        for (scene_index, scene) in model.engine.project.scenes.iter_mut().enumerate() {
             ui.group(|ui| {
                 if scene.is_editing {
                     ui.horizontal(|ui| {
@@ -82,20 +70,20 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
                             go_scene = Some((scene_index, None)); // go to scene "immediately"
                         };
                         for (_fixture_instance_label, macros_used) in scene.state.iter() {
-                            for (_macro_name, value) in macros_used.iter() {
-                                match value {
-                                    SceneValue::ControlValue(_) => {}
-                                    SceneValue::ColourValue(c) => {
-                                        let [r, g, b, a] = c.to_array();
-                                        let text_color = {
-                                            if a == 0 {
-                                                Color32::from_rgba_unmultiplied(255, 255, 255, 255)
-                                            } else {
-                                                Color32::from_rgba_unmultiplied(r, g, b, 255)
-                                            }
-                                        };
-                                        ui.label(RichText::new("💡").color(text_color));
-                                    }
+                            for macro_name in macros_used.keys() {
+                                if let Some(c) =
+                                    resolve_scene_value(macros_used, macro_name, &palettes)
+                                        .and_then(|(value, _)| value.as_colour())
+                                {
+                                    let [r, g, b, a] = c.to_array();
+                                    let text_color = {
+                                        if a == 0 {
+                                            Color32::from_rgba_unmultiplied(255, 255, 255, 255)
+                                        } else {
+                                            Color32::from_rgba_unmultiplied(r, g, b, 255)
+                                        }
+                                    };
+                                    ui.label(RichText::new("💡").color(text_color));
                                 }
                             }
                         }
@@ -159,7 +147,7 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
 
     if let Some(scene_index) = edit_scene {
         // First, mark any CURRENTLY-edited scene for update (save)
-        for (index, scene) in model.project.scenes.iter_mut().enumerate() {
+        for (index, scene) in model.engine.project.scenes.iter_mut().enumerate() {
             if scene.is_editing {
                 debug!("Scene {} should get saved", index);
                 update_scene = Some(index);
@@ -167,41 +155,35 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
         }
 
         // Then mark is_editing exclusively to the target Scene
-        for (index, scene) in model.project.scenes.iter_mut().enumerate() {
+        for (index, scene) in model.engine.project.scenes.iter_mut().enumerate() {
             scene.is_editing = index == scene_index;
         }
     }
 
     if let Some(scene_index) = update_scene {
-        let scene = &mut model.project.scenes[scene_index];
+        model.history.record(
+            &format!(
+                "Save scene \"{}\"",
+                &model.engine.project.scenes[scene_index].label
+            ),
+            &model.engine.project,
+        );
+        let scene = &mut model.engine.project.scenes[scene_index];
         scene.is_editing = false;
 
-        for fixture in model.project.fixtures.iter() {
+        for fixture in model.engine.project.fixtures.iter() {
             let mut m_state = IndexMap::new();
             for m in fixture.config.active_mode.macros.iter() {
-                match m {
-                    FixtureMacro::Control(control_macro) => {
-                        m_state.insert(
-                            String::from(&control_macro.label),
-                            SceneValue::ControlValue(control_macro.current_value),
-                        );
-                    }
-                    FixtureMacro::Colour(colour_macro) => {
-                        m_state.insert(
-                            String::from(&colour_macro.label),
-                            SceneValue::ColourValue(colour_macro.current_value),
-                        );
-                    }
-                }
+                m_state.insert(String::from(m.label()), m.scene_value());
             }
             scene.state.insert(String::from(&fixture.label), m_state);
         }
     }
 
     if let Some((scene_index, ms)) = go_scene {
-        model.apply_scene(scene_index, ms, None);
+        model.engine.apply_scene(scene_index, ms, None);
 
-        for (index, scene) in model.project.scenes.iter_mut().enumerate() {
+        for (index, scene) in model.engine.project.scenes.iter_mut().enumerate() {
             if index == scene_index {
                 // This one
                 scene.last_active = true;
@@ -214,16 +196,27 @@ pub fn render_scenes(model: &mut Model, ui: &mut Ui) {
     }
 
     if let Some(scene_index) = delete_scene {
-        model.project.scenes.remove(scene_index);
+        model.history.record(
+            &format!(
+                "Delete scene \"{}\"",
+                &model.engine.project.scenes[scene_index].label
+            ),
+            &model.engine.project,
+        );
+        model.engine.project.scenes.remove(scene_index);
     }
 
     if let Some(scene) = add_scene {
-        model.project.scenes.push(scene);
+        model.history.record(
+            &format!("Add scene \"{}\"", &scene.label),
+            &model.engine.project,
+        );
+        model.engine.project.scenes.push(scene);
 
-        let new_scene_index = model.project.scenes.len() - 1;
+        let new_scene_index = model.engine.project.scenes.len() - 1;
 
         // Also, mark all other scenes as not-editing and not-active...
-        for (index, scene) in model.project.scenes.iter_mut().enumerate() {
+        for (index, scene) in model.engine.project.scenes.iter_mut().enumerate() {
             if index != new_scene_index {
                 scene.last_active = false;
                 scene.is_editing = false;
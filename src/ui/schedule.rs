@@ -0,0 +1,34 @@
+use chrono::Local;
+use egui::{CollapsingHeader, Color32, Grid, RichText, Ui};
+
+use crate::model::Model;
+
+/// How many upcoming events to list
+const UPCOMING_COUNT: usize = 8;
+
+/// The next few events in the project's schedule
+pub fn render_schedule(model: &Model, ui: &mut Ui) {
+    let Some(schedule) = &model.engine.project.schedule else {
+        return;
+    };
+    CollapsingHeader::new(RichText::new("Schedule").heading())
+        .default_open(false)
+        .show(ui, |ui| {
+            let upcoming = schedule.upcoming(Local::now(), UPCOMING_COUNT);
+            if upcoming.is_empty() {
+                ui.label(RichText::new("Nothing scheduled").color(Color32::GRAY));
+                return;
+            }
+            Grid::new("upcoming_schedule")
+                .num_columns(3)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (time, entry) in upcoming {
+                        ui.label(time.format("%a %d %b %H:%M").to_string());
+                        ui.label(entry.action.describe());
+                        ui.label(RichText::new(&entry.when).small().color(Color32::GRAY));
+                        ui.end_row();
+                    }
+                });
+        });
+}
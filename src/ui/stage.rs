@@ -0,0 +1,320 @@
+use egui::{
+    epaint::PathShape, pos2, vec2, Align2, Color32, FontId, Pos2, Rect, Response, RichText, Sense,
+    Stroke, Ui,
+};
+use log::debug;
+
+use super::record_widget_change;
+use crate::{
+    model::Model,
+    project::{
+        fixture::{FixtureInstance, FixtureMacro},
+        group::FixtureGroup,
+        palette::INTENSITY_MACROS,
+    },
+};
+
+const FIXTURE_RADIUS: f32 = 14.0;
+const TRAY_HEIGHT: f32 = 48.0;
+
+/// Top-down stage plan. Fixtures are dragged into place, drawn with their live
+/// colour, intensity and pan/tilt direction, and can be selected by clicking or
+/// by drawing a lasso around them.
+pub fn render_stage_plot(model: &mut Model, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.heading("Stage");
+        ui.label(
+            RichText::new("Drag fixtures to place them; drag on empty stage to lasso-select")
+                .small()
+                .color(Color32::GRAY),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label(format!("{} selected", model.selected_fixtures.len()));
+        if ui
+            .add_enabled(
+                !model.selected_fixtures.is_empty(),
+                egui::Button::new("Clear selection"),
+            )
+            .clicked()
+        {
+            model.selected_fixtures.clear();
+        }
+        if ui
+            .add_enabled(
+                !model.selected_fixtures.is_empty(),
+                egui::Button::new("Save selection as Group 👥"),
+            )
+            .clicked()
+        {
+            save_selection_as_group(model);
+        }
+    });
+    ui.separator();
+
+    let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
+    let full_rect = response.rect;
+    let stage_rect = Rect::from_min_max(
+        full_rect.min,
+        pos2(full_rect.max.x, full_rect.max.y - TRAY_HEIGHT),
+    );
+    let tray_rect = Rect::from_min_max(pos2(full_rect.min.x, stage_rect.max.y), full_rect.max);
+
+    painter.rect_filled(stage_rect, 4.0, Color32::from_gray(24));
+    painter.rect_filled(tray_rect, 0.0, Color32::from_gray(12));
+    painter.text(
+        pos2(stage_rect.center().x, stage_rect.max.y - 4.0),
+        Align2::CENTER_BOTTOM,
+        "AUDIENCE",
+        FontId::proportional(10.0),
+        Color32::DARK_GRAY,
+    );
+
+    let mut clicked_fixture: Option<usize> = None;
+    // (fixture index, position before, drag response)
+    let mut moved: Option<(usize, Option<[f32; 2]>, Response)> = None;
+    let mut unplaced_count = 0;
+
+    for (i, fixture) in model.engine.project.fixtures.iter_mut().enumerate() {
+        let centre = match fixture.stage_position {
+            Some([x, y]) => pos2(
+                stage_rect.min.x + x * stage_rect.width(),
+                stage_rect.min.y + y * stage_rect.height(),
+            ),
+            None => {
+                unplaced_count += 1;
+                pos2(
+                    tray_rect.min.x + FIXTURE_RADIUS * 2.5 * unplaced_count as f32,
+                    tray_rect.center().y,
+                )
+            }
+        };
+
+        let hit_rect = Rect::from_center_size(centre, vec2(FIXTURE_RADIUS, FIXTURE_RADIUS) * 2.0);
+        let fixture_response = ui.interact(
+            hit_rect,
+            ui.id().with(("stage_fixture", i)),
+            Sense::click_and_drag(),
+        );
+
+        if fixture_response.clicked() {
+            clicked_fixture = Some(i);
+        }
+        if fixture_response.dragged() {
+            let new_centre = centre + fixture_response.drag_delta();
+            let x = ((new_centre.x - stage_rect.min.x) / stage_rect.width()).clamp(0., 1.);
+            let y = ((new_centre.y - stage_rect.min.y) / stage_rect.height()).clamp(0., 1.);
+            moved = Some((i, fixture.stage_position, fixture_response.clone()));
+            fixture.stage_position = Some([x, y]);
+        }
+
+        let is_selected = model
+            .selected_fixtures
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(&fixture.label));
+        draw_fixture(&painter, fixture, centre, is_selected);
+    }
+
+    if let Some((index, position_before, response)) = moved {
+        let label = format!(
+            "Move \"{}\" on stage",
+            &model.engine.project.fixtures[index].label
+        );
+        record_widget_change(&mut model.history, &label, &response, || {
+            let mut project_before = model.engine.project.clone();
+            project_before.fixtures[index].stage_position = position_before;
+            project_before
+        });
+    }
+
+    if let Some(index) = clicked_fixture {
+        let label = model.engine.project.fixtures[index].label.clone();
+        if ui.input(|i| i.modifiers.shift) {
+            toggle_selection(&mut model.selected_fixtures, &label);
+        } else {
+            model.selected_fixtures = vec![label];
+        }
+        model.engine.selected_macro_group_index = index;
+    }
+
+    // Lasso selection by dragging on the background
+    if response.drag_started() {
+        model.stage_lasso.clear();
+    }
+    if response.dragged() {
+        if let Some(p) = response.interact_pointer_pos() {
+            model.stage_lasso.push(p);
+        }
+    }
+    if model.stage_lasso.len() > 1 {
+        painter.add(PathShape::closed_line(
+            model.stage_lasso.clone(),
+            Stroke::new(1.0, Color32::LIGHT_BLUE),
+        ));
+    }
+    if response.drag_released() && model.stage_lasso.len() > 2 {
+        let lasso = std::mem::take(&mut model.stage_lasso);
+        if !ui.input(|i| i.modifiers.shift) {
+            model.selected_fixtures.clear();
+        }
+        for fixture in model.engine.project.fixtures.iter() {
+            if let Some([x, y]) = fixture.stage_position {
+                let p = pos2(
+                    stage_rect.min.x + x * stage_rect.width(),
+                    stage_rect.min.y + y * stage_rect.height(),
+                );
+                if point_in_polygon(p, &lasso)
+                    && !model
+                        .selected_fixtures
+                        .iter()
+                        .any(|s| s.eq_ignore_ascii_case(&fixture.label))
+                {
+                    model.selected_fixtures.push(fixture.label.clone());
+                }
+            }
+        }
+        debug!("Lasso selected {:?}", model.selected_fixtures);
+    }
+}
+
+fn draw_fixture(painter: &egui::Painter, fixture: &FixtureInstance, centre: Pos2, selected: bool) {
+    let intensity = find_control_value(fixture, &INTENSITY_MACROS)
+        .map(|v| v as f32 / u16::MAX as f32)
+        .unwrap_or(1.0);
+    let colour = fixture
+        .config
+        .active_mode
+        .macros
+        .iter()
+        .find_map(|m| match m {
+            FixtureMacro::Colour(colour_macro) => Some(colour_macro.current_value.to_opaque()),
+            FixtureMacro::Temperature(temperature_macro) => {
+                Some(temperature_macro.current_value.to_rgb())
+            }
+            _ => None,
+        })
+        .unwrap_or(Color32::WHITE);
+    let lit = Color32::from_rgb(
+        (colour.r() as f32 * intensity) as u8,
+        (colour.g() as f32 * intensity) as u8,
+        (colour.b() as f32 * intensity) as u8,
+    );
+
+    painter.circle(
+        centre,
+        FIXTURE_RADIUS,
+        lit,
+        Stroke::new(
+            if selected { 3.0 } else { 1.0 },
+            if selected {
+                Color32::LIGHT_BLUE
+            } else {
+                Color32::GRAY
+            },
+        ),
+    );
+
+    // Pan as direction (full range = one turn), tilt as length of the beam line
+    let position = fixture
+        .config
+        .active_mode
+        .macros
+        .iter()
+        .find_map(|m| match m {
+            FixtureMacro::Position(position_macro) => Some(position_macro.current_value),
+            _ => None,
+        });
+    if let Some([pan, tilt]) = position {
+        let angle = pan as f32 / u16::MAX as f32 * std::f32::consts::TAU;
+        let length = FIXTURE_RADIUS + FIXTURE_RADIUS * 2.0 * tilt as f32 / u16::MAX as f32;
+        let direction = vec2(angle.sin(), angle.cos());
+        painter.line_segment(
+            [
+                centre + direction * FIXTURE_RADIUS,
+                centre + direction * length,
+            ],
+            Stroke::new(2.0, lit),
+        );
+    }
+
+    painter.text(
+        centre + vec2(0., FIXTURE_RADIUS + 2.0),
+        Align2::CENTER_TOP,
+        &fixture.label,
+        FontId::proportional(11.0),
+        Color32::LIGHT_GRAY,
+    );
+}
+
+fn find_control_value(fixture: &FixtureInstance, labels: &[&str]) -> Option<u16> {
+    fixture
+        .config
+        .active_mode
+        .macros
+        .iter()
+        .find_map(|m| match m {
+            FixtureMacro::Control(control_macro)
+                if labels
+                    .iter()
+                    .any(|l| control_macro.label.eq_ignore_ascii_case(l)) =>
+            {
+                Some(control_macro.current_value)
+            }
+            _ => None,
+        })
+}
+
+fn toggle_selection(selection: &mut Vec<String>, label: &str) {
+    match selection.iter().position(|s| s.eq_ignore_ascii_case(label)) {
+        Some(index) => {
+            selection.remove(index);
+        }
+        None => selection.push(String::from(label)),
+    }
+}
+
+/// Ad-hoc groups are ordered left-to-right across the stage, which suits fan/spread
+fn save_selection_as_group(model: &mut Model) {
+    let mut members: Vec<(f32, String)> = model
+        .engine
+        .project
+        .fixtures
+        .iter()
+        .filter(|f| {
+            model
+                .selected_fixtures
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&f.label))
+        })
+        .map(|f| {
+            (
+                f.stage_position.map(|[x, _]| x).unwrap_or(0.),
+                f.label.clone(),
+            )
+        })
+        .collect();
+    members.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    model
+        .history
+        .record("Add group from selection", &model.engine.project);
+    let label = format!("Selection {}", model.engine.project.groups.len());
+    model.engine.project.groups.push(FixtureGroup {
+        label,
+        fixtures: members.into_iter().map(|(_, label)| label).collect(),
+    });
+}
+
+/// Ray-casting test for whether a point lies inside a (closed) polygon
+fn point_in_polygon(p: Pos2, polygon: &[Pos2]) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
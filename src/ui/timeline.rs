@@ -0,0 +1,207 @@
+use egui::{CollapsingHeader, Color32, ComboBox, DragValue, Grid, RichText, Slider, Ui};
+
+use crate::{
+    model::Model,
+    project::{
+        scene::Scene,
+        schedule::ScheduleAction,
+        timeline::{Timeline, TimelineCue},
+    },
+    timecode::{FrameRate, Timecode},
+};
+
+/// Extra time shown on the scrub bar after the last cue, in ms
+const SCRUB_TAIL_MS: u64 = 10_000;
+
+/// Transport, scrub bar and cue list for the project's timeline
+pub fn render_timeline(model: &mut Model, ui: &mut Ui) {
+    CollapsingHeader::new(RichText::new("Timeline").heading())
+        .default_open(false)
+        .show(ui, |ui| {
+            let Some(timeline) = &model.engine.project.timeline else {
+                if ui.button("+ Add timeline").clicked() {
+                    model.history.record("Add timeline", &model.engine.project);
+                    model.engine.project.timeline = Some(Timeline::default());
+                }
+                return;
+            };
+            let rate = timeline.frame_rate;
+
+            let player = &mut model.engine.timeline_player;
+            let position = player.position_ms(timeline);
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(Timecode::from_ms(position, rate).to_string())
+                        .monospace()
+                        .size(20.),
+                );
+                if player.is_running(timeline) {
+                    if ui.button("⏹").clicked() {
+                        player.stop(timeline);
+                    }
+                } else if ui.button("▶").clicked() {
+                    player.play(timeline);
+                }
+                if ui.button("⏮").clicked() {
+                    player.locate(0);
+                }
+                ui.label(
+                    RichText::new(player.status(timeline))
+                        .small()
+                        .color(Color32::GRAY),
+                );
+            });
+
+            let mut scrub = position;
+            if ui
+                .add(
+                    Slider::new(&mut scrub, 0..=timeline.duration_ms() + SCRUB_TAIL_MS)
+                        .show_value(false),
+                )
+                .changed()
+            {
+                player.locate(scrub);
+            }
+
+            let mut edited = timeline.clone();
+            let mut locate = None;
+            let mut remove = None;
+
+            ui.horizontal(|ui| {
+                ComboBox::from_label("fps")
+                    .selected_text(frame_rate_label(edited.frame_rate))
+                    .show_ui(ui, |ui| {
+                        for r in [
+                            FrameRate::Film,
+                            FrameRate::Ebu,
+                            FrameRate::DropFrame,
+                            FrameRate::Smpte,
+                        ] {
+                            ui.selectable_value(&mut edited.frame_rate, r, frame_rate_label(r));
+                        }
+                    });
+                ui.checkbox(&mut edited.chase, "Chase on jump");
+                ui.add(
+                    DragValue::new(&mut edited.freewheel_ms)
+                        .suffix("ms")
+                        .prefix("freewheel "),
+                );
+            });
+
+            Grid::new("timeline_cues")
+                .num_columns(5)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (index, cue) in edited.cues.iter_mut().enumerate() {
+                        timecode_edit(ui, &mut cue.at, edited.frame_rate);
+                        let label = cue.label.get_or_insert_with(String::new);
+                        ui.add(egui::TextEdit::singleline(label).desired_width(100.));
+                        if cue.label.as_deref() == Some("") {
+                            cue.label = None;
+                        }
+                        action_edit(ui, index, &mut cue.action, &model.engine.project.scenes);
+                        if ui.button("⏵").on_hover_text("Go to this cue").clicked() {
+                            locate = Some(cue.at.to_ms(edited.frame_rate));
+                        }
+                        if ui.button("🗑").clicked() {
+                            remove = Some(index);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+            if ui.button("+ Add cue here").clicked() {
+                let action = match model.engine.project.scenes.first() {
+                    Some(scene) => ScheduleAction::GoScene {
+                        scene: scene.label.clone(),
+                        ms: None,
+                        fixture_labels: None,
+                    },
+                    None => ScheduleAction::Blackout { ms: None },
+                };
+                let at = Timecode::from_ms(position, edited.frame_rate);
+                let index = edited.cues.partition_point(|c| c.at <= at);
+                edited.cues.insert(
+                    index,
+                    TimelineCue {
+                        at,
+                        label: None,
+                        action,
+                    },
+                );
+            }
+            if let Some(index) = remove {
+                edited.cues.remove(index);
+            }
+
+            if let Some(position) = locate {
+                model.engine.timeline_player.locate(position);
+            }
+            if Some(&edited) != model.engine.project.timeline.as_ref() {
+                model
+                    .history
+                    .record_grouped("Edit timeline", &model.engine.project);
+                model.engine.project.timeline = Some(edited);
+            }
+        });
+}
+
+fn frame_rate_label(rate: FrameRate) -> &'static str {
+    match rate {
+        FrameRate::Film => "24",
+        FrameRate::Ebu => "25",
+        FrameRate::DropFrame => "29.97 DF",
+        FrameRate::Smpte => "30",
+    }
+}
+
+fn timecode_edit(ui: &mut Ui, timecode: &mut Timecode, rate: FrameRate) {
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 1.;
+        ui.add(DragValue::new(&mut timecode.hours).clamp_range(0..=23));
+        ui.label(":");
+        ui.add(DragValue::new(&mut timecode.minutes).clamp_range(0..=59));
+        ui.label(":");
+        ui.add(DragValue::new(&mut timecode.seconds).clamp_range(0..=59));
+        ui.label(":");
+        ui.add(DragValue::new(&mut timecode.frames).clamp_range(0..=rate.frames_per_second() - 1));
+    });
+}
+
+fn action_edit(ui: &mut Ui, index: usize, action: &mut ScheduleAction, scenes: &[Scene]) {
+    ui.horizontal(|ui| {
+        let selected = match action {
+            ScheduleAction::GoScene { scene, .. } => scene.clone(),
+            ScheduleAction::Blackout { .. } => String::from("(blackout)"),
+        };
+        let ms = match action {
+            ScheduleAction::GoScene { ms, .. } | ScheduleAction::Blackout { ms } => *ms,
+        };
+        ComboBox::from_id_source(format!("timeline_cue_action_{}", index))
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                for scene in scenes.iter() {
+                    if ui.selectable_label(false, &scene.label).clicked() {
+                        *action = ScheduleAction::GoScene {
+                            scene: scene.label.clone(),
+                            ms,
+                            fixture_labels: None,
+                        };
+                    }
+                }
+                if ui.selectable_label(false, "(blackout)").clicked() {
+                    *action = ScheduleAction::Blackout { ms };
+                }
+            });
+        let ms = match action {
+            ScheduleAction::GoScene { ms, .. } | ScheduleAction::Blackout { ms } => ms,
+        };
+        let mut fade = ms.is_some();
+        if ui.checkbox(&mut fade, "fade").changed() {
+            *ms = fade.then_some(1000);
+        }
+        if let Some(ms) = ms {
+            ui.add(DragValue::new(ms).speed(10).suffix("ms"));
+        }
+    });
+}
@@ -0,0 +1,57 @@
+use egui::{pos2, vec2, Color32, Response, Sense, Stroke, Ui};
+
+const PAD_SIZE: f32 = 96.0;
+
+/// Square pad for setting pan (x, left to right) and tilt (y, bottom to top)
+/// together, by clicking or dragging
+pub fn xy_pad(ui: &mut Ui, value: &mut [u16; 2], enabled: bool) -> Response {
+    let sense = if enabled {
+        Sense::click_and_drag()
+    } else {
+        Sense::hover()
+    };
+    let (rect, mut response) = ui.allocate_exact_size(vec2(PAD_SIZE, PAD_SIZE), sense);
+
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let x = ((pointer.x - rect.left()) / rect.width()).clamp(0., 1.);
+        let y = ((rect.bottom() - pointer.y) / rect.height()).clamp(0., 1.);
+        let new_value = [(x * u16::MAX as f32) as u16, (y * u16::MAX as f32) as u16];
+        if new_value != *value {
+            *value = new_value;
+            response.mark_changed();
+        }
+    }
+
+    let painter = ui.painter_at(rect);
+    let visuals = ui.style().interact(&response);
+    painter.rect_filled(rect, 2.0, visuals.bg_fill);
+    painter.line_segment(
+        [rect.center_top(), rect.center_bottom()],
+        Stroke::new(1.0, Color32::DARK_GRAY),
+    );
+    painter.line_segment(
+        [rect.left_center(), rect.right_center()],
+        Stroke::new(1.0, Color32::DARK_GRAY),
+    );
+    let [pan, tilt] = *value;
+    let point = pos2(
+        rect.left() + rect.width() * pan as f32 / u16::MAX as f32,
+        rect.bottom() - rect.height() * tilt as f32 / u16::MAX as f32,
+    );
+    painter.circle_filled(
+        point,
+        4.0,
+        if enabled {
+            Color32::LIGHT_BLUE
+        } else {
+            Color32::GRAY
+        },
+    );
+    painter.rect_stroke(rect, 2.0, visuals.bg_stroke);
+
+    response.on_hover_text(format!(
+        "Pan {:.0}%, Tilt {:.0}%",
+        pan as f32 / u16::MAX as f32 * 100.,
+        tilt as f32 / u16::MAX as f32 * 100.
+    ))
+}
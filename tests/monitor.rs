@@ -0,0 +1,286 @@
+//! Runs the controller and the `monitor` virtual receiver against each other
+//! on loopback, and checks what actually goes over the wire.
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{mpsc, Mutex},
+    time::{Duration, Instant},
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_tether-artnet-controller");
+
+/// Every test needs the ArtNet port, so they take turns
+static ARTNET_PORT: Mutex<()> = Mutex::new(());
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+const UNICAST_LOOPBACK: [&str; 4] = [
+    "--artnet.interface",
+    "127.0.0.1",
+    "--artnet.destination",
+    "127.0.0.1",
+];
+
+/// A running `monitor`, already listening
+struct Monitor {
+    child: Child,
+    /// Each line printed, with when it arrived
+    lines: mpsc::Receiver<(Instant, String)>,
+}
+
+impl Monitor {
+    fn start(args: &[&str]) -> Self {
+        let mut child = Command::new(BIN)
+            .arg("monitor")
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("failed to start monitor");
+        let stdout = child.stdout.take().unwrap();
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send((Instant::now(), line)).is_err() {
+                    break;
+                }
+            }
+        });
+        let (_, first) = lines
+            .recv_timeout(TIMEOUT)
+            .expect("monitor did not start listening");
+        assert!(first.starts_with("Listening on"), "unexpected: {}", first);
+        Monitor { child, lines }
+    }
+
+    /// Everything printed until the monitor exits (by itself, after --count)
+    fn finish(self) -> Vec<String> {
+        self.finish_timed()
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect()
+    }
+
+    fn finish_timed(mut self) -> Vec<(Instant, String)> {
+        let started = Instant::now();
+        let mut output = Vec::new();
+        loop {
+            while let Ok(line) = self.lines.try_recv() {
+                output.push(line);
+            }
+            if self.child.try_wait().unwrap().is_some() {
+                // Pick up anything printed just before exiting
+                while let Ok(line) = self.lines.recv_timeout(Duration::from_millis(100)) {
+                    output.push(line);
+                }
+                return output;
+            }
+            if started.elapsed() > TIMEOUT {
+                self.child.kill().ok();
+                panic!("monitor timed out; received so far: {:#?}", output);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// The controller, in headless mode, with the example project
+struct Controller(Child);
+
+impl Controller {
+    fn command(args: &[&str]) -> Command {
+        let project = concat!(env!("CARGO_MANIFEST_DIR"), "/example.project.json");
+        let mut command = Command::new(BIN);
+        command
+            .args([
+                project,
+                "--headless",
+                "--tether.noAutoConnect",
+                "--project.noWatch",
+                "--project.autosave",
+                "0",
+            ])
+            .args(UNICAST_LOOPBACK)
+            .args(args)
+            .stdout(Stdio::null());
+        command
+    }
+
+    fn start_headless(args: &[&str]) -> Self {
+        let child = Controller::command(args)
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start controller");
+        Controller(child)
+    }
+
+    /// Also returns the controller's log, line by line
+    fn start_headless_logged(args: &[&str]) -> (Self, mpsc::Receiver<String>) {
+        let mut child = Controller::command(args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("failed to start controller");
+        let stderr = child.stderr.take().unwrap();
+        let (tx, log) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        (Controller(child), log)
+    }
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+fn run(args: &[&str]) {
+    let status = Command::new(BIN)
+        .args(args)
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run controller");
+    assert!(status.success());
+}
+
+#[test]
+fn blackout_sends_zero_frames() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let monitor = Monitor::start(&["--all", "--count", "3"]);
+    run(&[&["blackout"], &UNICAST_LOOPBACK[..]].concat());
+
+    let lines = monitor.finish();
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        assert_eq!(line, "ArtDmx universe 1 seq 0: from 127.0.0.1:6453");
+    }
+}
+
+#[test]
+fn universe_and_sync_options() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let monitor = Monitor::start(&["--all", "--count", "6"]);
+    run(&[
+        &["blackout", "--artnet.universe", "7", "--artnet.sync"],
+        &UNICAST_LOOPBACK[..],
+    ]
+    .concat());
+
+    let lines = monitor.finish();
+    assert_eq!(lines.len(), 6);
+    for pair in lines.chunks(2) {
+        assert!(pair[0].starts_with("ArtDmx universe 7 "), "{}", pair[0]);
+        assert!(pair[1].starts_with("ArtSync "), "{}", pair[1]);
+    }
+}
+
+#[test]
+fn net_and_sub_net_in_port_address() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let monitor = Monitor::start(&["--all", "--count", "1"]);
+    run(&[
+        &["blackout", "--artnet.universe", "1:2:3"],
+        &UNICAST_LOOPBACK[..],
+    ]
+    .concat());
+
+    let lines = monitor.finish();
+    // 0x123, i.e. net 1, sub-net 2, universe 3
+    assert!(lines[0].starts_with("ArtDmx universe 291 "), "{}", lines[0]);
+}
+
+#[test]
+fn headless_controller_sends_home_values() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    // Only changed frames are printed, so the first is the real output
+    let monitor = Monitor::start(&["--count", "1"]);
+    let _controller = Controller::start_headless(&[]);
+
+    let lines = monitor.finish();
+    let frame = &lines[0];
+    assert!(frame.starts_with("ArtDmx universe 1 "), "{}", frame);
+    let values: Vec<&str> = frame.split_whitespace().collect();
+    for expected in ["3=128", "18=255"] {
+        assert!(
+            values.contains(&expected),
+            "{} missing from {}",
+            expected,
+            frame
+        );
+    }
+}
+
+#[test]
+fn headless_controller_sends_timecode() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let monitor = Monitor::start(&["--all", "--count", "200"]);
+    let _controller = Controller::start_headless(&["--artnet.timecode", "internal"]);
+
+    let lines = monitor.finish();
+    let timecodes: Vec<&String> = lines
+        .iter()
+        .filter(|l| l.starts_with("ArtTimeCode "))
+        .collect();
+    assert!(!timecodes.is_empty(), "no ArtTimeCode in {:#?}", lines);
+    assert!(timecodes[0].contains("(25 fps)"), "{}", timecodes[0]);
+}
+
+#[test]
+fn on_change_output_sends_keep_alives() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    let monitor = Monitor::start(&["--all", "--count", "6"]);
+    let _controller =
+        Controller::start_headless(&["--artnet.onChange", "--artnet.keepAlive", "0.5"]);
+
+    // Nothing changes after the first frame, so the rest are keep-alives
+    // (the controller also polls its destination now and then)
+    let lines: Vec<(Instant, String)> = monitor
+        .finish_timed()
+        .into_iter()
+        .filter(|(_, line)| !line.starts_with("ArtPoll "))
+        .collect();
+    assert!(lines.len() >= 4, "{:#?}", lines);
+    for (_, line) in &lines {
+        assert!(line.starts_with("ArtDmx universe 1 "), "{}", line);
+    }
+    let (first, _) = lines[0];
+    let (last, _) = lines[3];
+    let elapsed = last.duration_since(first);
+    assert!(
+        elapsed >= Duration::from_millis(1400),
+        "3 keep-alives after {:?}",
+        elapsed
+    );
+}
+
+#[test]
+fn destinations_answering_poll_are_reachable() {
+    let _port = ARTNET_PORT.lock().unwrap_or_else(|e| e.into_inner());
+    // The monitor answers ArtPoll like a node would
+    let _monitor = Monitor::start(&[]);
+    let (_controller, log) = Controller::start_headless_logged(&[]);
+
+    let started = Instant::now();
+    loop {
+        let line = log
+            .recv_timeout(TIMEOUT.saturating_sub(started.elapsed()))
+            .expect("destination never became reachable");
+        if line.contains("ArtNet destination 127.0.0.1:6454 is reachable") {
+            assert!(line.contains("\"Virtual receiver\""), "{}", line);
+            break;
+        }
+    }
+}